@@ -1,27 +1,35 @@
 use std::ffi::CString;
+use std::path::{Path, PathBuf};
 use anyhow::{anyhow, Result};
 use bpaf::*;
 use bpaf::parsers::NamedArg;
 use pcap::{self, Device};
 use pnet::datalink::{self, NetworkInterface};
+use pnet::ipnetwork::IpNetwork;
+use crate::direction::Method;
 use crate::fanout;
 use crate::flow::Addr;
+use crate::mirror::{self, Mirror};
 use crate::version::Version;
 
 #[derive(Clone, Debug)]
 pub struct Args {
-    pub capture:     Capture,
+    pub capture:     Vec<Capture>,
     pub email:       CString,
     pub token:       CString,
 
     pub sample:      Option<u64>,
     pub decode:      bool,
-    pub fangroup:    Option<u16>,
+    pub fangroup:    Option<Vec<u16>>,
     pub fanmode:     Option<fanout::Mode>,
     pub filter:      Option<String>,
+    pub if_filter:   Option<Vec<(String, String)>>,
     pub promisc:     bool,
     pub snaplen:     Option<i32>,
 
+    pub mirror_format: Option<mirror::Format>,
+    pub mirror_port:   Option<Vec<u16>>,
+
     pub device_id:   Option<u32>,
     pub device_if:   Option<CString>,
     pub device_ip:   Option<CString>,
@@ -39,10 +47,27 @@ pub struct Args {
     pub status_host: Option<CString>,
     pub status_port: Option<u16>,
 
-    pub translate:   Option<Vec<(Addr, Addr)>>,
-    pub http_port:   Option<Vec<u16>>,
-    pub dns_port:    Option<u16>,
-    pub radius_port: Option<Vec<u16>>,
+    pub translate:     Option<Vec<(Addr, Addr)>>,
+    pub http_port:     Option<Vec<u16>>,
+    pub http_header:   Option<Vec<String>>,
+    pub dns_port:      Option<u16>,
+    pub dns_timeout:   Option<u64>,
+    pub radius_port:   Option<Vec<u16>>,
+    pub postgres_port: Option<Vec<u16>>,
+    pub mysql_port:    Option<Vec<u16>>,
+    pub redis_port:    Option<Vec<u16>>,
+    pub redis_depth:   Option<usize>,
+
+    pub direction:   Option<Vec<Method>>,
+    pub local_net:   Option<Vec<IpNetwork>>,
+
+    pub geoip_asn:     Option<PathBuf>,
+    pub geoip_city:    Option<PathBuf>,
+    pub geoip_country: Option<PathBuf>,
+
+    pub k8s:          bool,
+    pub k8s_snapshot: Option<PathBuf>,
+    pub k8s_node:     Option<String>,
 
     pub verbose:     usize,
 
@@ -57,11 +82,15 @@ pub enum Mode {
     Dns {
         filter:  Option<String>,
         juniper: bool,
+        empty:   bool,
+        dnstap:  Option<PathBuf>,
     },
 
     Radius {
         ports:   Option<Vec<u16>>,
     },
+
+    Dhcp,
 }
 
 pub fn arguments() -> Result<Args> {
@@ -69,19 +98,23 @@ pub fn arguments() -> Result<Args> {
 }
 
 pub fn parser() -> OptionParser<Args> {
-    let capture = short('i').long("interface").argument("interface").map(Capture);
+    let capture = short('i').long("interface").argument("interface").map(Capture).many();
 
     let email = long("email").env("KENTIK_EMAIL").cstring("email");
     let token = long("token").env("KENTIK_TOKEN").cstring("token");
 
     let sample      = long("sample").argument("N").optional();
     let decode      = long("no-decode").switch().map(|b| !b);
-    let fangroup    = long("fanout-group").argument("group").optional();
+    let fangroup    = long("fanout-group").argument("group").some("").optional();
     let fanmode     = long("fanout-mode").argument("mode").optional();
     let filter      = long("filter").argument("filter").optional();
+    let if_filter   = if_filter();
     let promisc     = long("promisc").switch();
     let snaplen     = long("snaplen").argument("N").optional();
 
+    let mirror_format = long("mirror-format").argument("format").optional();
+    let mirror_port   = long("mirror-port").argument("port").some("").optional();
+
     let device_id   = long("device-id").argument("ID").optional();
     let device_if   = long("device-if").cstring("interface").optional();
     let device_ip   = long("device-ip").cstring("IP").optional();
@@ -99,17 +132,35 @@ pub fn parser() -> OptionParser<Args> {
     let status_host = long("status-host").cstring("host").optional();
     let status_port = long("status-port").argument("port").optional();
 
-    let translate   = translate();
-    let http_port   = long("http-port").argument("port").some("").optional();
-    let dns_port    = long("dns-port").argument("port").optional();
-    let radius_port = long("radius-port").argument("port").some("").optional();
+    let translate     = translate();
+    let http_port     = long("http-port").argument("port").some("").optional();
+    let http_header   = long("http-header").argument("name").some("").optional();
+    let dns_port      = long("dns-port").argument("port").optional();
+    let dns_timeout   = dns_timeout();
+    let radius_port   = long("radius-port").argument("port").some("").optional();
+    let postgres_port = long("postgres-port").argument("port").some("").optional();
+    let mysql_port    = long("mysql-port").argument("port").some("").optional();
+    let redis_port    = long("redis-port").argument("port").some("").optional();
+    let redis_depth   = long("redis-key-depth").argument("depth").optional();
+
+    let direction   = long("direction").argument("method").some("").optional();
+    let local_net   = long("local-net").argument("CIDR").some("").optional();
+
+    let geoip_asn     = long("geoip-asn").argument("path").optional();
+    let geoip_city    = long("geoip-city").argument("path").optional();
+    let geoip_country = long("geoip-country").argument("path").optional();
+
+    let k8s          = long("k8s").switch();
+    let k8s_snapshot = long("k8s-snapshot").argument("path").optional();
+    let k8s_node     = long("k8s-node").argument("name").optional();
 
     let verbose     = short('v').req_flag(()).count();
     let version     = Version::new();
 
     let dns    = dns().command("dns");
     let radius = radius().command("radius");
-    let mode   = construct!([dns, radius]).optional();
+    let dhcp   = dhcp().command("dhcp");
+    let mode   = construct!([dns, radius, dhcp]).optional();
 
     construct!(Args {
         capture,
@@ -122,9 +173,13 @@ pub fn parser() -> OptionParser<Args> {
         fangroup,
         fanmode,
         filter,
+        if_filter,
         promisc,
         snaplen,
 
+        mirror_format,
+        mirror_port,
+
         device_id,
         device_if,
         device_ip,
@@ -144,13 +199,44 @@ pub fn parser() -> OptionParser<Args> {
 
         translate,
         http_port,
+        http_header,
         dns_port,
+        dns_timeout,
         radius_port,
+        postgres_port,
+        mysql_port,
+        redis_port,
+        redis_depth,
+
+        direction,
+        local_net,
+
+        geoip_asn,
+        geoip_city,
+        geoip_country,
+
+        k8s,
+        k8s_snapshot,
+        k8s_node,
 
         verbose,
 
         mode,
-    }).to_options().version(&*version.version)
+    }).guard(|args| {
+        !args.capture.is_empty() || args.dnstap().is_some()
+    }, "missing interface").guard(|args| {
+        args.mode.is_none() || args.capture.len() <= 1
+    }, "dns, radius and dhcp modes capture from a single interface").guard(|args| {
+        args.mirror_port.is_none() || matches!(args.mirror_format, Some(mirror::Format::Juniper) | Some(mirror::Format::Fixed(..)))
+    }, "--mirror-port requires the juniper or fixed mirror format").guard(|args| {
+        args.fangroup.as_ref().map_or(true, |groups| groups.len() == args.capture.len())
+    }, "--fanout-group must be given once per interface").to_options().version(&*version.version)
+}
+
+// Unanswered queries are dropped along with their connection once it
+// has been idle for 60 seconds, so a longer timeout would never fire.
+fn dns_timeout() -> impl Parser<Option<u64>> {
+    long("dns-timeout").argument::<u64>("seconds").guard(|n| (1..60).contains(n), "must be between 1 and 59 seconds").optional()
 }
 
 fn translate() -> impl Parser<Option<Vec<(Addr, Addr)>>> {
@@ -170,10 +256,21 @@ fn translate() -> impl Parser<Option<Vec<(Addr, Addr)>>> {
     }).some("").optional()
 }
 
+fn if_filter() -> impl Parser<Option<Vec<(String, String)>>> {
+    long("interface-filter").argument::<String>("interface=filter").parse(|value| {
+        match value.split_once('=') {
+            Some((name, filter)) => Ok((name.to_owned(), filter.to_owned())),
+            None                 => Err(anyhow!("missing interface filter")),
+        }
+    }).some("").optional()
+}
+
 fn dns() -> OptionParser<Mode> {
     let filter  = long("filter").argument("filter").optional();
     let juniper = long("juniper-mirror").switch();
-    construct!(Mode::Dns { filter, juniper }).to_options()
+    let empty   = long("empty-replies").switch();
+    let dnstap  = long("dnstap-socket").argument("path").optional();
+    construct!(Mode::Dns { filter, juniper, empty, dnstap }).to_options()
 }
 
 fn radius() -> OptionParser<Mode> {
@@ -181,6 +278,10 @@ fn radius() -> OptionParser<Mode> {
     construct!(Mode::Radius { ports }).to_options()
 }
 
+fn dhcp() -> OptionParser<Mode> {
+    pure(Mode::Dhcp).to_options()
+}
+
 impl Args {
    pub fn http_config(&self) -> Result<(String, String, Option<String>)> {
         let email = self.email.to_string_lossy().to_string();
@@ -188,6 +289,37 @@ impl Args {
         let proxy = self.proxy_url.as_ref().map(|p| p.to_string_lossy().to_string());
         Ok((email, token, proxy))
     }
+
+    pub fn mirror(&self) -> Option<Mirror> {
+        let ports = self.mirror_port.clone().unwrap_or_default();
+        self.mirror_format.map(|format| Mirror::new(format, ports))
+    }
+
+    // dnstap input replaces packet capture in DNS mode.
+    pub fn dnstap(&self) -> Option<&Path> {
+        match self.mode {
+            Some(Mode::Dns { ref dnstap, .. }) => dnstap.as_deref(),
+            _                                  => None,
+        }
+    }
+
+    // Interface the device is registered with: the first capture
+    // interface or, without any, the first interface which is up and
+    // has an address.
+    pub fn interface(&self) -> Result<NetworkInterface> {
+        match self.capture.first() {
+            Some(capture) => capture.interface(),
+            None          => datalink::interfaces().into_iter().find(|i| {
+                i.is_up() && !i.is_loopback() && !i.ips.is_empty()
+            }).ok_or_else(|| anyhow!("no usable interface")),
+        }
+    }
+
+    pub fn filter(&self, capture: &Capture) -> Option<&str> {
+        self.if_filter.iter().flatten().find(|(name, _)| {
+            name == &capture.0
+        }).map(|(_, filter)| filter.as_str()).or(self.filter.as_deref())
+    }
 }
 
 impl Capture {
@@ -1,18 +1,28 @@
 use std::ffi::CStr;
+use std::panic::{self, AssertUnwindSafe};
 use std::process::exit;
-use anyhow::Result;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use anyhow::{anyhow, Result};
 use env_logger::Builder;
 use log::{LevelFilter, debug};
-use pcap::Capture;
+use pcap::{Active, Capture, Device};
+use pnet::datalink::NetworkInterface;
+use time::Duration;
 use url::Url;
 use kentik_api::{dns, tag, AsyncClient, Client};
 use kprobe::{Config, Kprobe};
 use kprobe::args::{arguments, Mode};
+use kprobe::custom::Customs;
+use kprobe::direction;
 use kprobe::fanout;
+use kprobe::geo::{self, Geo};
+use kprobe::k8s::{self, Kubernetes};
 use kprobe::flow::Protocol;
 use kprobe::libkflow;
+use kprobe::mirror::{Format, Mirror};
 use kprobe::mode;
-use kprobe::protocol::{Classify, Decoder};
+use kprobe::protocol::{self, Classify, Decoder};
 use kprobe::libkflow::Error::*;
 
 #[global_allocator]
@@ -23,11 +33,17 @@ fn main() -> Result<()> {
     let args = arguments()?;
 
     let (email, token, proxy) = args.http_config()?;
-    let device    = args.capture.device()?;
-    let interface = args.capture.interface()?;
+    let captures  = args.capture.iter().map(|c| {
+        Ok((c.device()?, c.interface()?, args.filter(c).map(str::to_owned)))
+    }).collect::<Result<Vec<_>>>()?;
+    let interface = args.interface()?;
 
     let snaplen = args.snaplen.unwrap_or(65535);
+    let promisc = args.promisc;
     let verbose = args.verbose;
+    let mirror  = args.mirror();
+    let fanmode = args.fanmode.unwrap_or(fanout::Mode::Hash);
+    let fanout  = args.fangroup.map(|groups| (groups, fanmode));
 
     let mut builder = Builder::from_default_env();
     builder.filter(None, match args.verbose {
@@ -37,6 +53,9 @@ fn main() -> Result<()> {
     });
     builder.init();
 
+    // libkflow sends every flow for a single device, registered with
+    // the first interface. Flows from other interfaces are told apart
+    // by their ifindex.
     let mut cfg = libkflow::Config::new(&interface, args.region, snaplen, args.promisc);
     cfg.url         = args.flow_url.unwrap_or(cfg.url);
     cfg.api.email   = args.email;
@@ -83,6 +102,7 @@ fn main() -> Result<()> {
     let mut classify = Classify::new();
 
     classify.add(Protocol::UDP, args.dns_port.unwrap_or(53), Decoder::DNS);
+    classify.add(Protocol::TCP, args.dns_port.unwrap_or(53), Decoder::DNS);
 
     for port in args.http_port.as_deref().unwrap_or(&[]) {
         classify.add(Protocol::TCP, *port, Decoder::HTTP);
@@ -92,22 +112,27 @@ fn main() -> Result<()> {
         classify.add(Protocol::UDP, *port, Decoder::Radius)
     }
 
+    for port in args.postgres_port.as_deref().unwrap_or(&[]) {
+        classify.add(Protocol::TCP, *port, Decoder::Postgres);
+    }
+
+    for port in args.mysql_port.as_deref().unwrap_or(&[]) {
+        classify.add(Protocol::TCP, *port, Decoder::MySQL);
+    }
+
+    for port in args.redis_port.as_deref().unwrap_or(&[]) {
+        classify.add(Protocol::TCP, *port, Decoder::Redis);
+    }
+
     let timeout = match args.mode {
         Some(Mode::Dns{..}) => 15_000,
         _                   =>  1_000,
     };
 
-    let mut cap = Capture::from_device(device).unwrap()
-        .buffer_size(100_000_000)
-        .timeout(timeout)
-        .snaplen(snaplen)
-        .promisc(args.promisc)
-        .open()?;
-
-    if let Some(group) = args.fangroup {
-        let mode = args.fanmode.unwrap_or(fanout::Mode::Hash);
-        fanout::join(&cap, group, mode)?;
-    }
+    let open = |captures: Vec<_>| {
+        let fanout = fanout.as_ref().map(|(groups, mode)| (&groups[..], *mode));
+        open(captures, timeout, snaplen, promisc, fanout)
+    };
 
     if let Some(mode) = args.mode {
         let email = &email;
@@ -115,45 +140,153 @@ fn main() -> Result<()> {
         let proxy = proxy.as_deref();
 
         match mode {
-            Mode::Dns { filter, juniper } => {
+            Mode::Dns { empty, dnstap: Some(path), .. } => {
+                let client = async_api_client(email, token, proxy, &cfg.dns.url)?;
+                let client = dns::Client::new(client);
+
+                mode::dnstap::run(&path, client, empty)?;
+            },
+            Mode::Dns { filter, juniper, empty, dnstap: None } => {
+                let (_, cap, _) = open(captures)?.swap_remove(0);
+
                 let client = async_api_client(email, token, proxy, &cfg.dns.url)?;
                 let client = dns::Client::new(client);
 
-                if juniper {
-                    mode::dns::run_juniper(cap, client, filter)?;
-                } else {
-                    mode::dns::run(cap, client, filter)?;
-                }
+                let ports  = args.mirror_port.unwrap_or_default();
+                let mirror = match juniper {
+                    true  => Some(Mirror::new(Format::Juniper, ports)),
+                    false => mirror,
+                };
+
+                mode::dns::run(cap, client, filter, empty, mirror)?;
             },
             Mode::Radius { ports } => {
+                let (_, cap, _) = open(captures)?.swap_remove(0);
+
                 let ports  = ports.unwrap_or(vec![1812, 1813]);
                 let client = sync_api_client(email, token, proxy, &cfg.api.url)?;
                 let client = tag::Client::new(client);
 
                 mode::radius::run(cap, client, &ports)?;
             },
+            Mode::Dhcp => {
+                let (_, cap, _) = open(captures)?.swap_remove(0);
+
+                let client = sync_api_client(email, token, proxy, &cfg.api.url)?;
+                let client = tag::Client::new(client);
+
+                mode::dhcp::run(cap, client)?;
+            },
         }
 
         exit(0);
     }
 
-    if let Some(ref filter) = args.filter {
-        cap.filter(filter, true)?;
-    }
+    let mut direction = direction::Config::default();
+    direction.methods = args.direction.unwrap_or(direction.methods);
+    direction.local   = args.local_net.unwrap_or(direction.local);
+
+    let geo = match (args.geoip_asn, args.geoip_city, args.geoip_country) {
+        (None, None, None)   => None,
+        (asn, city, country) => Some(geo::Config { asn, city, country }),
+    };
 
-    let mut kprobe = Kprobe::new(interface, Config{
+    // GeoIP databases and k8s metadata are loaded once and shared by
+    // every capture thread.
+    let geo = geo.map(|cfg| Arc::new(Mutex::new(Geo::new(cfg))));
+
+    let k8s = match (args.k8s, args.k8s_snapshot) {
+        (false, None) => None,
+        (kubelet, snapshot) => Some(k8s::Config {
+            kubelet:  kubelet,
+            snapshot: snapshot,
+            node:     args.k8s_node.unwrap_or_else(|| {
+                libkflow::hostname().to_string_lossy().to_string()
+            }),
+        }),
+    };
+    let k8s = k8s.map(|cfg| Kubernetes::new(cfg, &Customs::new(&dev.customs)));
+
+    let mut protocol = protocol::Config::default();
+    protocol.http_headers = args.http_header.unwrap_or_default();
+    protocol.redis_key_depth = args.redis_depth.unwrap_or(protocol.redis_key_depth);
+    protocol.dns_timeout = args.dns_timeout.map(|n| Duration::seconds(n as i64)).unwrap_or(protocol.dns_timeout);
+
+    let cfg = Config{
         classify:  classify,
         customs:   dev.customs,
         decode:    args.decode,
+        direction: direction,
+        geo:       geo,
+        k8s:       k8s,
+        mirror:    mirror,
+        protocol:  protocol,
         sample:    sample,
         translate: args.translate
-    });
+    };
 
-    kprobe.run(cap)?;
+    let (tx, rx) = mpsc::channel();
+
+    for (interface, mut cap, filter) in open(captures)? {
+        if let Some(ref filter) = filter {
+            cap.filter(filter, true)?;
+        }
+
+        let name = interface.name.clone();
+        let cfg  = cfg.clone();
+        let tx   = tx.clone();
+
+        thread::Builder::new().name(name.clone()).spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                Kprobe::new(interface, cfg).run(cap)
+            }));
+
+            let _ = tx.send(match result {
+                Ok(result) => result.map_err(Into::into),
+                Err(_)     => Err(anyhow!("capture thread {} panicked", name)),
+            });
+        })?;
+    }
+
+    drop(tx);
+
+    // The first capture to fail ends the process.
+    for result in rx {
+        result?;
+    }
 
     Ok(())
 }
 
+fn open(
+    captures: Vec<(Device, NetworkInterface, Option<String>)>,
+    timeout:  i32,
+    snaplen:  i32,
+    promisc:  bool,
+    fanout:   Option<(&[u16], fanout::Mode)>,
+) -> Result<Vec<(NetworkInterface, Capture<Active>, Option<String>)>> {
+    let mut caps = Vec::new();
+
+    for (n, (device, interface, filter)) in captures.into_iter().enumerate() {
+        let cap = Capture::from_device(device).unwrap()
+            .buffer_size(100_000_000)
+            .timeout(timeout)
+            .snaplen(snaplen)
+            .promisc(promisc)
+            .open()?;
+
+        // A fanout group is bound to one interface, so each interface
+        // joins the group given for it.
+        if let Some((groups, mode)) = fanout {
+            fanout::join(&cap, groups[n], mode)?;
+        }
+
+        caps.push((interface, cap, filter));
+    }
+
+    Ok(caps)
+}
+
 fn async_api_client(email: &str, token: &str, proxy: Option<&str>, url: &CStr) -> Result<AsyncClient> {
     let endpoint = endpoint(url)?;
     Ok(AsyncClient::new(email, token, &endpoint, proxy)?)
@@ -1,16 +1,28 @@
 use std::mem;
+use std::sync::{Arc, Mutex};
+use pnet::datalink::NetworkInterface;
 use crate::custom::Customs;
+use crate::direction::{self, Infer};
 use crate::flow::Addr;
+use crate::geo::Geo;
+use crate::k8s::Kubernetes;
 use crate::libkflow::kflowCustom;
-use crate::protocol::Classify;
+use crate::mirror::Mirror;
+use crate::protocol::{self, Classify};
 use crate::queue::FlowQueue;
 use crate::sample::Sampler;
 use crate::translate::Translate;
 
+#[derive(Clone)]
 pub struct Config {
     pub classify:  Classify,
     pub customs:   Vec<kflowCustom>,
     pub decode:    bool,
+    pub direction: direction::Config,
+    pub geo:       Option<Arc<Mutex<Geo>>>,
+    pub k8s:       Option<Kubernetes>,
+    pub mirror:    Option<Mirror>,
+    pub protocol:  protocol::Config,
     pub sample:    Option<u64>,
     pub translate: Option<Vec<(Addr, Addr)>>,
 }
@@ -18,13 +30,29 @@ pub struct Config {
 impl Config {
     pub fn queue(self) -> FlowQueue {
         let customs = Customs::new(&self.customs);
-        FlowQueue::new(self.sample, customs, self.classify, self.decode)
+        let mut queue = FlowQueue::new(self.sample, customs, self.classify, self.decode);
+        queue.protocol(&self.protocol);
+        if let Some(k8s) = self.k8s {
+            queue.kubernetes(k8s);
+        }
+        if let Some(geo) = self.geo {
+            queue.geo(geo);
+        }
+        queue
+    }
+
+    pub fn direction(&self, interface: &NetworkInterface) -> Infer {
+        Infer::new(self.direction.clone(), interface)
     }
 
     pub fn sampler(&self) -> Option<Sampler> {
         self.sample.map(Sampler::new)
     }
 
+    pub fn mirror(&mut self) -> Option<Mirror> {
+        mem::replace(&mut self.mirror, None)
+    }
+
     pub fn translate(&mut self) -> Option<Translate> {
         mem::replace(&mut self.translate, None).map(Translate::new)
     }
@@ -5,6 +5,7 @@ use std::ffi::CStr;
 use std::net::IpAddr;
 use std::ops::Deref;
 use time::Duration;
+use crate::direction::Method;
 use crate::libkflow::*;
 use crate::protocol::Decoder;
 use crate::queue::Counter;
@@ -23,18 +24,52 @@ pub const RECEIVE_WINDOW:         &str = "RECEIVE_WINDOW";
 pub const ZERO_WINDOWS:           &str = "ZERO_WINDOWS";
 pub const APP_PROTOCOL:           &str = "APP_PROTOCOL";
 pub const CONNECTION_ID:          &str = "CONNECTION_ID";
+pub const DIRECTION_METHOD:       &str = "DIRECTION_METHOD";
 pub const DNS_QUERY_NAME:         &str = "KFLOW_DNS_QUERY";
 pub const DNS_QUERY_TYPE:         &str = "KFLOW_DNS_QUERY_TYPE";
 pub const DNS_REPLY_CODE:         &str = "KFLOW_DNS_RET_CODE";
 pub const DNS_REPLY_DATA:         &str = "KFLOW_DNS_RESPONSE";
+pub const DNS_FLAGS:              &str = "DNS_FLAGS";
+pub const DNS_EDNS_SIZE:          &str = "DNS_EDNS_SIZE";
+pub const DNS_EDNS_DO:            &str = "DNS_EDNS_DO";
+pub const DNS_EXT_RCODE:          &str = "DNS_EXT_RCODE";
+pub const DNS_ECS_ADDR:           &str = "DNS_ECS_ADDR";
+pub const DNS_ECS_PREFIX:         &str = "DNS_ECS_PREFIX";
+pub const DNS_RESOLVER:           &str = "DNS_RESOLVER";
+pub const DNS_SERVFAIL_COUNT:     &str = "DNS_SERVFAIL_COUNT";
+pub const DNS_NXDOMAIN_COUNT:     &str = "DNS_NXDOMAIN_COUNT";
+pub const DNS_TIMEOUT_COUNT:      &str = "DNS_TIMEOUT_COUNT";
+pub const DNS_RETRANSMITS:        &str = "DNS_RETRANSMITS";
+pub const DNS_UNMATCHED:          &str = "DNS_UNMATCHED";
 pub const HTTP_URL:               &str = "KFLOW_HTTP_URL";
 pub const HTTP_HOST:              &str = "KFLOW_HTTP_HOST";
 pub const HTTP_REFERER:           &str = "KFLOW_HTTP_REFERER";
 pub const HTTP_UA:                &str = "KFLOW_HTTP_UA";
 pub const HTTP_STATUS:            &str = "KFLOW_HTTP_STATUS";
+pub const HTTP_METHOD:            &str = "HTTP_METHOD";
+pub const HTTP_VERSION:           &str = "HTTP_VERSION";
+pub const HTTP_REQ_LENGTH:        &str = "HTTP_REQ_LENGTH";
+pub const HTTP_RES_LENGTH:        &str = "HTTP_RES_LENGTH";
+pub const HTTP_CONTENT_TYPE:      &str = "HTTP_CONTENT_TYPE";
+pub const HTTP_CLIENT_IP:         &str = "HTTP_CLIENT_IP";
+pub const GRPC_SERVICE:           &str = "GRPC_SERVICE";
+pub const GRPC_METHOD:            &str = "GRPC_METHOD";
+pub const GRPC_STATUS:            &str = "GRPC_STATUS";
 pub const TLS_SERVER_NAME:        &str = "TLS_SERVER_NAME";
 pub const TLS_SERVER_VERSION:     &str = "TLS_SERVER_VERSION";
 pub const TLS_CIPHER_SUITE:       &str = "TLS_CIPHER_SUITE";
+pub const TLS_JA3:                &str = "TLS_JA3";
+pub const TLS_JA3S:               &str = "TLS_JA3S";
+pub const TLS_JA4:                &str = "TLS_JA4";
+pub const TLS_ALPN:               &str = "TLS_ALPN";
+pub const TLS_CERT_SUBJECT:       &str = "TLS_CERT_SUBJECT";
+pub const TLS_CERT_SAN:           &str = "TLS_CERT_SAN";
+pub const TLS_CERT_ISSUER:        &str = "TLS_CERT_ISSUER";
+pub const TLS_CERT_SERIAL:        &str = "TLS_CERT_SERIAL";
+pub const TLS_CERT_NOT_AFTER:     &str = "TLS_CERT_NOT_AFTER";
+pub const QUIC_VERSION:           &str = "QUIC_VERSION";
+pub const QUIC_DCID:              &str = "QUIC_DCID";
+pub const QUIC_SCID:              &str = "QUIC_SCID";
 pub const DHCP_OP:                &str = "DHCP_OP";
 pub const DHCP_MSG_TYPE:          &str = "DHCP_MSG_TYPE";
 pub const DHCP_CI_ADDR:           &str = "DHCP_CI_ADDR";
@@ -44,6 +79,20 @@ pub const DHCP_LEASE:             &str = "DHCP_LEASE";
 pub const DHCP_CH_ADDR:           &str = "DHCP_CH_ADDR";
 pub const DHCP_HOSTNAME:          &str = "DHCP_HOSTNAME";
 pub const DHCP_DOMAIN:            &str = "DHCP_DOMAIN";
+pub const DHCP_GI_ADDR:           &str = "DHCP_GI_ADDR";
+pub const DHCP_REQUESTED_ADDR:    &str = "DHCP_REQUESTED_ADDR";
+pub const DHCP_SERVER_ID:         &str = "DHCP_SERVER_ID";
+pub const DHCP_VENDOR_CLASS:      &str = "DHCP_VENDOR_CLASS";
+pub const DHCP_CLIENT_ID:         &str = "DHCP_CLIENT_ID";
+pub const DHCP_CLIENT_FQDN:       &str = "DHCP_CLIENT_FQDN";
+pub const DHCP_CIRCUIT_ID:        &str = "DHCP_CIRCUIT_ID";
+pub const DHCP_REMOTE_ID:         &str = "DHCP_REMOTE_ID";
+pub const DHCP_FINGERPRINT:       &str = "DHCP_FINGERPRINT";
+pub const DHCPV6_MSG_TYPE:        &str = "DHCPV6_MSG_TYPE";
+pub const DHCPV6_DUID:            &str = "DHCPV6_DUID";
+pub const DHCPV6_ADDRS:           &str = "DHCPV6_ADDRS";
+pub const DHCPV6_PREFIXES:        &str = "DHCPV6_PREFIXES";
+pub const DHCPV6_FQDN:            &str = "DHCPV6_FQDN";
 pub const RADIUS_CODE:            &str = "RADIUS_CODE";
 pub const RADIUS_USER_NAME:       &str = "RADIUS_USER_NAME";
 pub const RADIUS_SERVICE_TYPE:    &str = "RADIUS_SERVICE_TYPE";
@@ -52,6 +101,26 @@ pub const RADIUS_FRAMED_IP_MASK:  &str = "RADIUS_FRAMED_IP_MASK";
 pub const RADIUS_FRAMED_PROTO:    &str = "RADIUS_FRAMED_PROTO";
 pub const RADIUS_ACCT_SESSION_ID: &str = "RADIUS_ACCT_SESSION_ID";
 pub const RADIUS_ACCT_STATUS:     &str = "RADIUS_ACCT_STATUS";
+pub const POSTGRES_QUERY:         &str = "POSTGRES_QUERY";
+pub const POSTGRES_COMMAND:       &str = "POSTGRES_COMMAND";
+pub const POSTGRES_ROWS:          &str = "POSTGRES_ROWS";
+pub const POSTGRES_SQLSTATE:      &str = "POSTGRES_SQLSTATE";
+pub const MYSQL_STATEMENT:        &str = "MYSQL_STATEMENT";
+pub const MYSQL_VERSION:          &str = "MYSQL_VERSION";
+pub const MYSQL_ERROR:            &str = "MYSQL_ERROR";
+pub const MYSQL_ROWS:             &str = "MYSQL_ROWS";
+pub const REDIS_COMMAND:          &str = "REDIS_COMMAND";
+pub const REDIS_KEY:              &str = "REDIS_KEY";
+pub const REDIS_REPLY_TYPE:       &str = "REDIS_REPLY_TYPE";
+pub const REDIS_ERROR:            &str = "REDIS_ERROR";
+pub const K8S_SRC_POD_NAME:       &str = "K8S_SRC_POD_NAME";
+pub const K8S_SRC_NAMESPACE:      &str = "K8S_SRC_NAMESPACE";
+pub const K8S_SRC_WORKLOAD:       &str = "K8S_SRC_WORKLOAD";
+pub const K8S_SRC_NODE:           &str = "K8S_SRC_NODE";
+pub const K8S_DST_POD_NAME:       &str = "K8S_DST_POD_NAME";
+pub const K8S_DST_NAMESPACE:      &str = "K8S_DST_NAMESPACE";
+pub const K8S_DST_WORKLOAD:       &str = "K8S_DST_WORKLOAD";
+pub const K8S_DST_NODE:           &str = "K8S_DST_NODE";
 
 #[derive(Debug)]
 pub struct Customs {
@@ -63,6 +132,7 @@ pub struct Customs {
 #[derive(Debug)]
 pub struct Columns {
     app_proto: Option<u64>,
+    direction: Option<u64>,
     fragments: Option<u64>,
     columns:   HashMap<String, u64>,
 }
@@ -91,16 +161,23 @@ impl Customs {
             fields.insert(DNS_QUERY_TYPE.to_owned(),         int00);
             fields.insert(DNS_REPLY_CODE.to_owned(),         int01);
             fields.insert(DNS_REPLY_DATA.to_owned(),         str01);
+            fields.insert(DNS_FLAGS.to_owned(),              str02);
+            fields.insert(DNS_EDNS_SIZE.to_owned(),          int02);
+            fields.insert(DNS_ECS_ADDR.to_owned(),           addr00);
 
             fields.insert(HTTP_URL.to_owned(),               str00);
             fields.insert(HTTP_HOST.to_owned(),              str01);
             fields.insert(HTTP_REFERER.to_owned(),           str02);
             fields.insert(HTTP_UA.to_owned(),                str03);
             fields.insert(HTTP_STATUS.to_owned(),            int00);
+            fields.insert(GRPC_STATUS.to_owned(),            int01);
 
             fields.insert(TLS_SERVER_NAME.to_owned(),        str00);
             fields.insert(TLS_SERVER_VERSION.to_owned(),     int00);
             fields.insert(TLS_CIPHER_SUITE.to_owned(),       int01);
+            fields.insert(TLS_JA3.to_owned(),                str01);
+            fields.insert(TLS_JA3S.to_owned(),               str02);
+            fields.insert(TLS_JA4.to_owned(),                str03);
 
             fields.insert(DHCP_OP.to_owned(),                int00);
             fields.insert(DHCP_MSG_TYPE.to_owned(),          int01);
@@ -111,6 +188,7 @@ impl Customs {
             fields.insert(DHCP_CH_ADDR.to_owned(),           str00);
             fields.insert(DHCP_HOSTNAME.to_owned(),          str01);
             fields.insert(DHCP_DOMAIN.to_owned(),            str02);
+            fields.insert(DHCP_FINGERPRINT.to_owned(),       str03);
 
             fields.insert(RADIUS_CODE.to_owned(),            int00);
             fields.insert(RADIUS_USER_NAME.to_owned(),       str00);
@@ -132,6 +210,7 @@ impl Customs {
 
         let columns = Columns {
             app_proto: fields.get(APP_PROTOCOL).cloned(),
+            direction: fields.get(DIRECTION_METHOD).cloned(),
             fragments: fields.get(FRAGMENTS).cloned(),
             columns:   fields,
         };
@@ -164,6 +243,12 @@ impl Customs {
                 self.add_u32(id, self.protocol);
             }
         }
+
+        if let Some(id) = self.columns.direction {
+            if ctr.method != Method::None {
+                self.add_u32(id, ctr.method.id());
+            }
+        }
     }
 
     pub fn add_str(&mut self, id: u64, val: &CStr) {
@@ -0,0 +1,152 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+use anyhow::{anyhow, Error, Result};
+use pnet::datalink::NetworkInterface;
+use pnet::ipnetwork::IpNetwork;
+use pnet::util::MacAddr;
+use crate::flow::{Direction, Flow, Protocol};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Method {
+    None,
+    Mac,
+    Interface,
+    Local,
+    Port,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub methods: Vec<Method>,
+    pub local:   Vec<IpNetwork>,
+}
+
+#[derive(Debug)]
+pub struct Infer {
+    methods: Vec<Method>,
+    mac:     Option<MacAddr>,
+    addrs:   Vec<IpAddr>,
+    local:   Vec<IpNetwork>,
+}
+
+const DEFAULT: &[Method] = &[Method::Mac, Method::Interface, Method::Local, Method::Port];
+
+// Ports of common services which are never chosen as ephemeral
+// client ports, in addition to the well-known range below 1024.
+const SERVER_PORTS: &[u16] = &[
+    1433, 1521, 1812, 1813, 2049, 2181, 3306, 3389, 5060, 5432,
+    5672, 6379, 6443, 8080, 8443, 9042, 9092, 9200, 11211, 27017,
+];
+
+impl Infer {
+    pub fn new(cfg: Config, interface: &NetworkInterface) -> Self {
+        Infer {
+            methods: cfg.methods,
+            mac:     interface.mac,
+            addrs:   interface.ips.iter().map(IpNetwork::ip).collect(),
+            local:   cfg.local,
+        }
+    }
+
+    pub fn infer(&self, flow: &Flow) -> (Direction, Method) {
+        for &method in &self.methods {
+            let dir = match method {
+                Method::Mac       => self.mac(flow),
+                Method::Interface => self.interface(flow),
+                Method::Local     => self.local(flow),
+                Method::Port      => port(flow),
+                Method::None      => None,
+            };
+
+            if let Some(dir) = dir {
+                return (dir, method);
+            }
+        }
+
+        (Direction::Unknown, Method::None)
+    }
+
+    fn mac(&self, flow: &Flow) -> Option<Direction> {
+        match self.mac {
+            Some(mac) if mac == flow.ethernet.dst => Some(Direction::In),
+            Some(mac) if mac == flow.ethernet.src => Some(Direction::Out),
+            _                                     => None,
+        }
+    }
+
+    fn interface(&self, flow: &Flow) -> Option<Direction> {
+        let src = self.addrs.contains(&flow.src.addr);
+        let dst = self.addrs.contains(&flow.dst.addr);
+        direction(src, dst)
+    }
+
+    fn local(&self, flow: &Flow) -> Option<Direction> {
+        let local = |addr| self.local.iter().any(|net| net.contains(addr));
+        direction(local(flow.src.addr), local(flow.dst.addr))
+    }
+}
+
+// Assume the local end of a connection is the client, so traffic to
+// a server port is outbound and traffic from a server port inbound.
+fn port(flow: &Flow) -> Option<Direction> {
+    match flow.protocol {
+        Protocol::TCP | Protocol::UDP => (),
+        _                             => return None,
+    }
+
+    let src = server(flow.src.port);
+    let dst = server(flow.dst.port);
+
+    match (src, dst) {
+        (false, true) => Some(Direction::Out),
+        (true, false) => Some(Direction::In),
+        _             => None,
+    }
+}
+
+fn server(port: u16) -> bool {
+    (port > 0 && port < 1024) || SERVER_PORTS.contains(&port)
+}
+
+fn direction(src: bool, dst: bool) -> Option<Direction> {
+    match (src, dst) {
+        (true, false) => Some(Direction::Out),
+        (false, true) => Some(Direction::In),
+        _             => None,
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            methods: DEFAULT.to_vec(),
+            local:   Vec::new(),
+        }
+    }
+}
+
+impl Method {
+    pub fn id(&self) -> u32 {
+        match self {
+            Method::None      => 0,
+            Method::Mac       => 1,
+            Method::Interface => 2,
+            Method::Local     => 3,
+            Method::Port      => 4,
+        }
+    }
+}
+
+impl FromStr for Method {
+    type Err = Error;
+
+    fn from_str(method: &str) -> Result<Self> {
+        match method {
+            "mac"       => Ok(Method::Mac),
+            "interface" => Ok(Method::Interface),
+            "local"     => Ok(Method::Local),
+            "port"      => Ok(Method::Port),
+            _           => Err(anyhow!("invalid direction method")),
+        }
+    }
+}
@@ -4,6 +4,7 @@ use std::net::IpAddr;
 use std::ptr::addr_of_mut;
 use pnet::packet::tcp::TcpPacket;
 use pnet::util::MacAddr;
+use crate::direction::Method;
 use crate::time::Timestamp;
 
 pub const FIN: u8 = 0b00001;
@@ -24,6 +25,8 @@ pub struct Flow<'a> {
     pub fragments: u16,
     pub bytes:     usize,
     pub direction: Direction,
+    pub method:    Method,
+    pub ifindex:   u32,
     pub export:    bool,
     pub payload:   &'a [u8]
 }
@@ -66,7 +69,7 @@ pub struct Window {
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct Key(pub Protocol, pub Addr, pub Addr);
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Direction {
     In, Out, Unknown
 }
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use anyhow::Result;
+use log::{debug, warn};
+use maxminddb::{geoip2, Reader};
+use time::Duration;
+use crate::flow::Key;
+use crate::libkflow::kflow;
+use crate::time::Timestamp;
+use crate::timer::Timer;
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub asn:     Option<PathBuf>,
+    pub city:    Option<PathBuf>,
+    pub country: Option<PathBuf>,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Info {
+    pub asn:     u32,
+    pub country: u32,
+    pub region:  u32,
+    pub city:    u32,
+}
+
+pub struct Geo {
+    asn:     Database,
+    city:    Database,
+    country: Database,
+    cache:   HashMap<IpAddr, Info>,
+    reload:  Timer,
+}
+
+struct Database {
+    path:     Option<PathBuf>,
+    modified: Option<SystemTime>,
+    reader:   Option<Reader<Vec<u8>>>,
+}
+
+const CACHE_SIZE: usize = 65536;
+
+impl Geo {
+    pub fn new(cfg: Config) -> Self {
+        Geo {
+            asn:     Database::new(cfg.asn),
+            city:    Database::new(cfg.city),
+            country: Database::new(cfg.country),
+            cache:   HashMap::new(),
+            reload:  Timer::new(Duration::minutes(5)),
+        }
+    }
+
+    pub fn append(&mut self, key: &Key, kflow: &mut kflow) {
+        let src = self.lookup(key.1.addr);
+        let dst = self.lookup(key.2.addr);
+
+        kflow.srcAs        = src.asn;
+        kflow.srcGeo       = src.country;
+        kflow.srcGeoRegion = src.region;
+        kflow.srcGeoCity   = src.city;
+
+        kflow.dstAs        = dst.asn;
+        kflow.dstGeo       = dst.country;
+        kflow.dstGeoRegion = dst.region;
+        kflow.dstGeoCity   = dst.city;
+    }
+
+    pub fn lookup(&mut self, addr: IpAddr) -> Info {
+        if let Some(info) = self.cache.get(&addr) {
+            return *info;
+        }
+
+        if self.cache.len() >= CACHE_SIZE {
+            self.cache.clear();
+        }
+
+        let mut info = Info::default();
+
+        if let Some(r) = &self.asn.reader {
+            if let Ok(asn) = r.lookup::<geoip2::Asn>(addr) {
+                info.asn = asn.autonomous_system_number.unwrap_or(0);
+            }
+        }
+
+        if let Some(r) = &self.country.reader {
+            if let Ok(c) = r.lookup::<geoip2::Country>(addr) {
+                info.country = country(c.country.and_then(|c| c.iso_code));
+            }
+        }
+
+        if let Some(r) = &self.city.reader {
+            if let Ok(c) = r.lookup::<geoip2::City>(addr) {
+                if info.country == 0 {
+                    info.country = country(c.country.and_then(|c| c.iso_code));
+                }
+                info.region = c.subdivisions.as_ref().and_then(|s| s.first()).and_then(|s| s.geoname_id).unwrap_or(0);
+                info.city   = c.city.and_then(|c| c.geoname_id).unwrap_or(0);
+            }
+        }
+
+        self.cache.insert(addr, info);
+        info
+    }
+
+    pub fn refresh(&mut self, ts: Timestamp) {
+        if !self.reload.ready(ts) {
+            return;
+        }
+
+        let asn     = self.asn.reload();
+        let city    = self.city.reload();
+        let country = self.country.reload();
+
+        if asn || city || country {
+            self.cache.clear();
+        }
+    }
+}
+
+impl Database {
+    fn new(path: Option<PathBuf>) -> Self {
+        let mut db = Database {
+            path:     path,
+            modified: None,
+            reader:   None,
+        };
+        db.reload();
+        db
+    }
+
+    // Reload the database when its modification time changes,
+    // keeping the current reader if the new file is invalid.
+    fn reload(&mut self) -> bool {
+        let path = match &self.path {
+            Some(path) => path,
+            None       => return false,
+        };
+
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        if modified.is_some() && modified == self.modified {
+            return false;
+        }
+
+        match open(path) {
+            Ok(reader) => {
+                debug!("loaded {} ({})", path.display(), reader.metadata.database_type);
+                self.modified = modified;
+                self.reader   = Some(reader);
+                true
+            },
+            Err(e) => {
+                warn!("failed to load {}: {}", path.display(), e);
+                false
+            }
+        }
+    }
+}
+
+fn open(path: &PathBuf) -> Result<Reader<Vec<u8>>> {
+    Ok(Reader::open_readfile(path)?)
+}
+
+// Pack a two letter ISO country code into the kflow geo field.
+fn country(code: Option<&str>) -> u32 {
+    match code.map(str::as_bytes) {
+        Some(&[a, b]) => (a as u32) << 8 | b as u32,
+        _             => 0,
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+use anyhow::Result;
+use log::{debug, warn};
+use serde_json::{Deserializer, Value};
+use crate::custom::*;
+use crate::flow::Key;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub kubelet:  bool,
+    pub snapshot: Option<PathBuf>,
+    pub node:     String,
+}
+
+#[derive(Debug)]
+pub struct Pod {
+    pub name:      CString,
+    pub namespace: CString,
+    pub workload:  CString,
+    pub node:      CString,
+}
+
+#[derive(Clone)]
+pub struct Kubernetes {
+    src:  Columns,
+    dst:  Columns,
+    pods: Arc<RwLock<HashMap<IpAddr, Pod>>>,
+}
+
+#[derive(Copy, Clone)]
+struct Columns {
+    name:      Option<u64>,
+    namespace: Option<u64>,
+    workload:  Option<u64>,
+    node:      Option<u64>,
+}
+
+const PODS_DIR: &str = "/var/lib/kubelet/pods";
+const LOGS_DIR: &str = "/var/log/pods";
+const REFRESH:  Duration = Duration::from_secs(60);
+
+impl Kubernetes {
+    pub fn new(config: Config, cs: &Customs) -> Self {
+        let pods = Arc::new(RwLock::new(load(&config)));
+
+        // Pods are reloaded by a background thread so capture threads
+        // never wait on the filesystem. It exits once every copy of
+        // the metadata has been dropped.
+        let shared = Arc::downgrade(&pods);
+        thread::spawn(move || loop {
+            thread::sleep(REFRESH);
+            let loaded = load(&config);
+            match shared.upgrade() {
+                Some(pods) => *pods.write().unwrap() = loaded,
+                None       => return,
+            }
+        });
+
+        Kubernetes {
+            src: Columns {
+                name:      cs.get(K8S_SRC_POD_NAME).ok(),
+                namespace: cs.get(K8S_SRC_NAMESPACE).ok(),
+                workload:  cs.get(K8S_SRC_WORKLOAD).ok(),
+                node:      cs.get(K8S_SRC_NODE).ok(),
+            },
+            dst: Columns {
+                name:      cs.get(K8S_DST_POD_NAME).ok(),
+                namespace: cs.get(K8S_DST_NAMESPACE).ok(),
+                workload:  cs.get(K8S_DST_WORKLOAD).ok(),
+                node:      cs.get(K8S_DST_NODE).ok(),
+            },
+            pods: pods,
+        }
+    }
+
+    pub fn append(&self, key: &Key, cs: &mut Customs) {
+        let pods = self.pods.read().unwrap();
+
+        if let Some(pod) = pods.get(&key.1.addr) {
+            self.src.append(pod, cs);
+        }
+
+        if let Some(pod) = pods.get(&key.2.addr) {
+            self.dst.append(pod, cs);
+        }
+    }
+}
+
+fn load(config: &Config) -> HashMap<IpAddr, Pod> {
+    let mut pods = HashMap::new();
+
+    if let Some(ref path) = config.snapshot {
+        match fs::read_to_string(path).map_err(Into::into).and_then(|s| snapshot(&s)) {
+            Ok(ps) => pods.extend(ps),
+            Err(e) => warn!("failed to load k8s snapshot {}: {}", path.display(), e),
+        }
+    }
+
+    if config.kubelet {
+        let node = &config.node;
+        match kubelet(Path::new(PODS_DIR), Path::new(LOGS_DIR), node) {
+            Ok(ps) => pods.extend(ps),
+            Err(e) => warn!("failed to scan kubelet pods: {}", e),
+        }
+    }
+
+    debug!("loaded {} k8s pod addresses", pods.len());
+
+    pods
+}
+
+impl Columns {
+    fn append(&self, pod: &Pod, cs: &mut Customs) {
+        self.name.map(|id| cs.add_str(id, &pod.name));
+        self.namespace.map(|id| cs.add_str(id, &pod.namespace));
+        self.workload.map(|id| cs.add_str(id, &pod.workload));
+        self.node.map(|id| cs.add_str(id, &pod.node));
+    }
+}
+
+impl Pod {
+    fn new(name: &str, namespace: &str, workload: &str, node: &str) -> Option<Self> {
+        Some(Pod {
+            name:      CString::new(name).ok()?,
+            namespace: CString::new(namespace).ok()?,
+            workload:  CString::new(workload).ok()?,
+            node:      CString::new(node).ok()?,
+        })
+    }
+}
+
+// Parse a `kubectl get pods -o json` pod list, a single pod, or a
+// sequence of `crictl inspectp -o json` documents.
+pub fn snapshot(json: &str) -> Result<Vec<(IpAddr, Pod)>> {
+    let mut pods = Vec::new();
+
+    for doc in Deserializer::from_str(json).into_iter::<Value>() {
+        let doc = doc?;
+        let items = match doc["items"].as_array() {
+            Some(items) => items.iter().collect(),
+            None        => vec![&doc],
+        };
+
+        for item in items {
+            if item["status"]["network"].is_object() {
+                pods.extend(cri_pod(item));
+            } else {
+                pods.extend(api_pod(item));
+            }
+        }
+    }
+
+    Ok(pods)
+}
+
+fn api_pod(v: &Value) -> Vec<(IpAddr, Pod)> {
+    let meta = &v["metadata"];
+    let name = meta["name"].as_str().unwrap_or("");
+    let ns   = meta["namespace"].as_str().unwrap_or("default");
+    let node = v["spec"]["nodeName"].as_str().unwrap_or("");
+
+    if v["spec"]["hostNetwork"].as_bool().unwrap_or(false) {
+        return Vec::new();
+    }
+
+    let owner = meta["ownerReferences"].as_array().and_then(|refs| {
+        refs.iter().find(|r| r["controller"].as_bool().unwrap_or(false)).or(refs.first())
+    });
+
+    let workload = match owner {
+        Some(o) if o["kind"] == "ReplicaSet" => strip(o["name"].as_str().unwrap_or(name), 1),
+        Some(o)                              => o["name"].as_str().unwrap_or(name),
+        None                                 => name,
+    };
+
+    let mut ips = v["status"]["podIPs"].as_array().map(|ips| {
+        ips.iter().flat_map(|ip| ip["ip"].as_str()).collect::<Vec<_>>()
+    }).unwrap_or_default();
+    if ips.is_empty() {
+        ips.extend(v["status"]["podIP"].as_str());
+    }
+
+    addrs(ips, name, ns, workload, node)
+}
+
+fn cri_pod(v: &Value) -> Vec<(IpAddr, Pod)> {
+    let status = &v["status"];
+    let meta   = &status["metadata"];
+    let name   = meta["name"].as_str().unwrap_or("");
+    let ns     = meta["namespace"].as_str().unwrap_or("default");
+    let net    = &status["network"];
+
+    let mut ips = vec![net["ip"].as_str().unwrap_or("")];
+    if let Some(more) = net["additionalIps"].as_array() {
+        ips.extend(more.iter().flat_map(|ip| ip["ip"].as_str()));
+    }
+
+    addrs(ips, name, ns, workload(name), "")
+}
+
+fn addrs(ips: Vec<&str>, name: &str, ns: &str, workload: &str, node: &str) -> Vec<(IpAddr, Pod)> {
+    ips.into_iter().flat_map(|ip| ip.parse().ok()).flat_map(|ip| {
+        Pod::new(name, ns, workload, node).map(|pod| (ip, pod))
+    }).collect()
+}
+
+// Discover pods running on this node from the kubelet's pod directories
+// and the pod log directories, which are named <namespace>_<name>_<uid>.
+pub fn kubelet(pods: &Path, logs: &Path, node: &str) -> Result<Vec<(IpAddr, Pod)>> {
+    let mut found = Vec::new();
+
+    for entry in fs::read_dir(logs)? {
+        let entry = entry?.file_name();
+        let entry = entry.to_string_lossy();
+
+        let mut parts = entry.splitn(3, '_');
+        let (ns, name, uid) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(ns), Some(name), Some(uid)) => (ns, name, uid),
+            _                                 => continue,
+        };
+
+        let hosts = match fs::read_to_string(pods.join(uid).join("etc-hosts")) {
+            Ok(hosts) => hosts,
+            Err(_)    => continue,
+        };
+
+        let ips = hosts.lines().filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some(ip), Some(host)) if host == name => Some(ip),
+                _                                      => None,
+            }
+        }).collect();
+
+        found.extend(addrs(ips, name, ns, workload(name), node));
+    }
+
+    Ok(found)
+}
+
+// Guess the owning workload from a generated pod name: deployments
+// produce <name>-<hash>-<suffix>, daemonsets and jobs <name>-<suffix>,
+// and statefulsets <name>-<ordinal>.
+fn workload(name: &str) -> &str {
+    let parts = name.rsplit('-').take(2).collect::<Vec<_>>();
+    match parts[..] {
+        [pod, rs] if suffix(pod, 5) && hash(rs) => strip(name, 2),
+        [pod, _]  if suffix(pod, 5)             => strip(name, 1),
+        [ord, _]  if ordinal(ord)               => strip(name, 1),
+        _                                       => name,
+    }
+}
+
+fn strip(name: &str, n: usize) -> &str {
+    let mut name = name;
+    for _ in 0..n {
+        name = name.rsplit_once('-').map(|(head, _)| head).unwrap_or(name);
+    }
+    name
+}
+
+fn suffix(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+}
+
+fn hash(s: &str) -> bool {
+    (6..=10).contains(&s.len()) && s.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+}
+
+fn ordinal(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
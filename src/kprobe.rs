@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use pcap::{self, Capture, Active, Error};
 use pcap::Error::*;
 use pnet::datalink::NetworkInterface;
@@ -6,7 +7,10 @@ use pnet::packet::ethernet::EthernetPacket;
 use pnet::packet::icmp::IcmpPacket;
 use pnet::packet::tcp::TcpPacket;
 use pnet::packet::udp::UdpPacket;
+use pnet::util::MacAddr;
 use crate::config::Config;
+use crate::direction::Infer;
+use crate::mirror::{self, Frame, Mirror};
 use crate::packet::{self, Packet, Opaque};
 use crate::packet::Transport::*;
 use crate::flow::*;
@@ -18,7 +22,9 @@ use crate::translate::Translate;
 use crate::queue::FlowQueue;
 
 pub struct Kprobe {
-    interface:  NetworkInterface,
+    direction:  Infer,
+    ifindex:    u32,
+    mirror:     Option<Mirror>,
     sampler:    Option<Sampler>,
     translate:  Option<Translate>,
     asm:        Reassembler,
@@ -28,7 +34,9 @@ pub struct Kprobe {
 impl Kprobe {
     pub fn new(interface: NetworkInterface, mut cfg: Config) -> Kprobe {
         Kprobe {
-            interface: interface,
+            direction: cfg.direction(&interface),
+            ifindex:   interface.index,
+            mirror:    cfg.mirror(),
             sampler:   cfg.sampler(),
             translate: cfg.translate(),
             asm:       Reassembler::new(),
@@ -48,7 +56,27 @@ impl Kprobe {
     }
 
     pub fn record<'a>(&mut self, packet: pcap::Packet<'a>) {
-        let eth = match EthernetPacket::new(packet.data) {
+        let ts = Timestamp::from(packet.header.ts);
+
+        let data = match self.mirror {
+            Some(ref m) => m.reassemble(&mut self.asm, ts, packet.data),
+            None        => Some(Cow::from(packet.data)),
+        };
+
+        let data = match data {
+            Some(data) => data,
+            None       => return,
+        };
+
+        match mirror::frame(self.mirror.as_ref(), &data) {
+            Some(Frame::Ethernet(data)) => self.ethernet(ts, data),
+            Some(Frame::IP(data))       => self.datagram(ts, data),
+            None                        => (),
+        }
+    }
+
+    fn ethernet(&mut self, ts: Timestamp, data: &[u8]) {
+        let eth = match EthernetPacket::new(data) {
             Some(pkt) => pkt,
             None      => return,
         };
@@ -60,46 +88,59 @@ impl Kprobe {
                 vlan: vlan,
             };
 
-            let dir = match self.interface.mac {
-                Some(mac) if mac == eth.dst => Direction::In,
-                Some(mac) if mac == eth.src => Direction::Out,
-                _                           => Direction::Unknown,
+            self.packet(ts, eth, &pkt);
+        }
+    }
+
+    // Mirrored IP packets without their original Ethernet header.
+    fn datagram(&mut self, ts: Timestamp, data: &[u8]) {
+        if let Some(pkt) = packet::decode_from_l3(data) {
+            let eth = Ethernet {
+                src:  MacAddr::zero(),
+                dst:  MacAddr::zero(),
+                vlan: None,
             };
 
-            let ts = Timestamp::from(packet.header.ts);
-
-            if let Some(out) = self.asm.reassemble(ts, &pkt) {
-                if let Some(transport) = pkt.transport(&out.data) {
-                    let mut flow = match transport {
-                        TCP(ref tcp)   => self.tcp(eth, &pkt, tcp),
-                        UDP(ref udp)   => self.udp(eth, &pkt, udp),
-                        ICMP(ref icmp) => self.icmp(eth, &pkt, icmp),
-                        Other(ref o)   => self.ip(eth, &pkt, o),
-                    };
-
-                    flow.timestamp = ts;
-                    flow.packets   = out.packets;
-                    flow.fragments = out.frags;
-                    flow.bytes     = out.bytes;
-                    flow.direction = dir;
-                    flow.export    = true;
-
-                    if let Some(ref s) = self.sampler {
-                        match s.accept(&flow) {
-                            Export => flow.export = true,
-                            Decode => flow.export = false,
-                            Ignore => return,
-                        }
-                    }
+            self.packet(ts, eth, &pkt);
+        }
+    }
 
-                    if let Some(ref t) = self.translate {
-                        t.translate(&mut flow);
+    fn packet(&mut self, ts: Timestamp, eth: Ethernet, pkt: &Packet) {
+        if let Some(out) = self.asm.reassemble(ts, pkt) {
+            if let Some(transport) = pkt.transport(&out.data) {
+                let mut flow = match transport {
+                    TCP(ref tcp)   => self.tcp(eth, pkt, tcp),
+                    UDP(ref udp)   => self.udp(eth, pkt, udp),
+                    ICMP(ref icmp) => self.icmp(eth, pkt, icmp),
+                    Other(ref o)   => self.ip(eth, pkt, o),
+                };
+
+                let (dir, method) = self.direction.infer(&flow);
+
+                flow.timestamp = ts;
+                flow.packets   = out.packets;
+                flow.fragments = out.frags;
+                flow.bytes     = out.bytes;
+                flow.direction = dir;
+                flow.method    = method;
+                flow.ifindex   = self.ifindex;
+                flow.export    = true;
+
+                if let Some(ref s) = self.sampler {
+                    match s.accept(&flow) {
+                        Export => flow.export = true,
+                        Decode => flow.export = false,
+                        Ignore => return,
                     }
+                }
 
-                    self.queue.add(flow);
-                    self.queue.export(ts);
-                    self.asm.flush(ts);
+                if let Some(ref t) = self.translate {
+                    t.translate(&mut flow);
                 }
+
+                self.queue.add(flow);
+                self.queue.export(ts);
+                self.asm.flush(ts);
             }
         }
     }
@@ -1,6 +1,7 @@
 pub mod args;
 pub mod config;
 pub mod custom;
+pub mod direction;
 pub mod flow;
 pub mod kprobe;
 pub mod mode;
@@ -9,6 +10,9 @@ pub mod packet;
 pub mod time;
 
 pub mod fanout;
+pub mod geo;
+pub mod k8s;
+pub mod mirror;
 pub mod queue;
 pub mod protocol;
 pub mod reasm;
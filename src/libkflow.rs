@@ -5,6 +5,7 @@ use std::default::Default;
 use std::ffi::{CStr, CString};
 use std::marker::PhantomData;
 use std::net::IpAddr;
+use std::sync::{Mutex, PoisonError};
 use std::{fmt, ptr, slice};
 use pnet::datalink::NetworkInterface;
 use pnet::util::MacAddr;
@@ -273,13 +274,13 @@ pub fn flow(key: &Key, ctr: &Counter, sample_rate: u32, app_protocol: u32) -> kf
         Direction::In => {
             kflow.inPkts     = ctr.packets;
             kflow.inBytes    = ctr.bytes;
-            kflow.inputPort  = (kflow.dstEthMac & 0xFFFF) as u32;
+            kflow.inputPort  = port(ctr.ifindex, kflow.dstEthMac);
             kflow.vlanIn     = ctr.ethernet.vlan.unwrap_or(0) as u32;
         },
         Direction::Out | Direction::Unknown => {
             kflow.outPkts    = ctr.packets;
             kflow.outBytes   = ctr.bytes;
-            kflow.outputPort = (kflow.srcEthMac & 0xFFFF) as u32;
+            kflow.outputPort = port(ctr.ifindex, kflow.srcEthMac);
             kflow.vlanOut    = ctr.ethernet.vlan.unwrap_or(0) as u32;
         },
     }
@@ -287,12 +288,18 @@ pub fn flow(key: &Key, ctr: &Counter, sample_rate: u32, app_protocol: u32) -> kf
     kflow
 }
 
+// libkflow isn't safe to call concurrently, and every capture thread
+// sends its own flows, so calls into it are serialised.
+static LOCK: Mutex<()> = Mutex::new(());
+
 pub fn send(mut kflow: kflow, cs: Option<&[kflowCustom]>) -> Result<(), Error> {
     if let Some(cs) = cs {
         kflow.customs    = cs.as_ptr();
         kflow.numCustoms = cs.len() as u32;
     }
 
+    let _lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
     unsafe {
         match kflowSend(&kflow) {
             0 => Ok(()),
@@ -302,6 +309,8 @@ pub fn send(mut kflow: kflow, cs: Option<&[kflowCustom]>) -> Result<(), Error> {
 }
 
 pub fn stop(timeout: Duration) -> Result<(), Error> {
+    let _lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
     unsafe {
         match kflowStop(timeout.whole_milliseconds() as c_int) {
             0 => Ok(()),
@@ -311,6 +320,8 @@ pub fn stop(timeout: Duration) -> Result<(), Error> {
 }
 
 pub fn error() -> Option<String> {
+    let _lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
     unsafe {
         let cstr = kflowError();
         if cstr.is_null() {
@@ -438,6 +449,10 @@ pub struct kflowCustom {
     pub value: kflowCustomValue,
 }
 
+// Custom column names point into memory owned by libkflow for the
+// life of the process, so they may be shared across capture threads.
+unsafe impl Send for kflowCustom {}
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub union kflowCustomValue {
@@ -568,6 +583,15 @@ impl From<::std::ffi::NulError> for Error {
     }
 }
 
+// Prefer the capture interface's ifindex, falling back to the low
+// bits of the MAC address when it is unknown.
+fn port(ifindex: u32, mac: u64) -> u32 {
+    match ifindex {
+        0 => (mac & 0xFFFF) as u32,
+        n => n,
+    }
+}
+
 fn pack_mac(mac: &MacAddr) -> u64 {
     let prims = mac.to_primitive_values();
     (prims.0 as u64) << 40 |
@@ -0,0 +1,226 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+use anyhow::{anyhow, Error, Result};
+use byteorder::{BigEndian as BE, ByteOrder};
+use pnet::packet::ipv4::Ipv4Packet;
+use crate::packet::Packet;
+use crate::reasm::Reassembler;
+use crate::time::Timestamp;
+
+// Decapsulation of traffic delivered by a remote mirror session, where
+// the original frame or packet is carried inside another packet.
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Juniper,
+    Erspan,
+    Trailer(usize),
+    Fixed(usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct Mirror {
+    pub format: Format,
+    pub ports:  Vec<u16>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Frame<'a> {
+    Ethernet(&'a [u8]),
+    IP(&'a [u8]),
+}
+
+// Juniper port mirroring to a remote analyzer prefixes each mirrored
+// IP packet with an 8-byte header inside a UDP datagram.
+const JUNIPER_HEADER: usize = 8;
+
+// Arista and most TAP aggregators append a 64-bit timestamp.
+const ARISTA_TRAILER: usize = 8;
+
+const ERSPAN_II:  u16 = 0x88BE;
+const ERSPAN_III: u16 = 0x22EB;
+
+const GRE: u8 = 47;
+const UDP: u8 = 17;
+
+impl Mirror {
+    pub fn new(format: Format, ports: Vec<u16>) -> Self {
+        Mirror {
+            format: format,
+            ports:  ports,
+        }
+    }
+
+    // The mirrored frame carried by a captured frame, or None when it
+    // isn't part of the mirror session.
+    pub fn decap<'a>(&self, frame: &'a [u8]) -> Option<Frame<'a>> {
+        match self.format {
+            Format::Juniper    => self.udp(frame, JUNIPER_HEADER).map(Frame::IP),
+            Format::Fixed(n)   => self.udp(frame, n).map(Frame::IP),
+            Format::Erspan     => erspan(frame).map(Frame::Ethernet),
+            Format::Trailer(n) => {
+                let n = frame.len().checked_sub(n)?;
+                Some(Frame::Ethernet(&frame[..n]))
+            },
+        }
+    }
+
+    // UDP payload following a fixed-length header, from one of the
+    // configured source ports if any.
+    fn udp<'a>(&self, frame: &'a [u8], offset: usize) -> Option<&'a [u8]> {
+        let (proto, payload) = ip(frame)?;
+        if proto != UDP || payload.len() < 8 {
+            return None;
+        }
+
+        let port = BE::read_u16(&payload[0..2]);
+        let len  = BE::read_u16(&payload[4..6]) as usize;
+
+        if !self.ports.is_empty() && !self.ports.contains(&port) {
+            return None;
+        }
+
+        payload.get(8..len.min(payload.len()))?.get(offset..)
+    }
+
+    // Reassemble a fragmented outer IPv4 packet, returning the frame
+    // rebuilt as a single unfragmented packet once all fragments have
+    // arrived. Other frames are returned unchanged.
+    pub fn reassemble<'a>(&self, asm: &mut Reassembler, ts: Timestamp, frame: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        let offset = match (self.format, l3(frame)) {
+            (Format::Trailer(..), _)    => return Some(Cow::from(frame)),
+            (_, Some((0x0800, offset))) => offset,
+            _                           => return Some(Cow::from(frame)),
+        };
+
+        let ihl  = (*frame.get(offset)? & 0x0F) as usize * 4;
+        let frag = BE::read_u16(frame.get(offset + 6..offset + 8)?) & 0x3FFF;
+        if frag == 0 {
+            return Some(Cow::from(frame));
+        }
+
+        let pkt = Packet::IPv4(Ipv4Packet::new(&frame[offset..])?);
+        let out = asm.reassemble(ts, &pkt)?;
+
+        let mut buf = frame.get(..offset + ihl)?.to_vec();
+        buf.extend_from_slice(&out.data);
+
+        let total = (ihl + out.data.len()).min(0xFFFF) as u16;
+        BE::write_u16(&mut buf[offset + 2..offset + 4], total);
+        BE::write_u16(&mut buf[offset + 6..offset + 8], 0);
+
+        Some(Cow::from(buf))
+    }
+}
+
+// Decapsulate a captured frame when a mirror session is configured,
+// otherwise the frame is passed through as-is.
+pub fn frame<'a>(mirror: Option<&Mirror>, data: &'a [u8]) -> Option<Frame<'a>> {
+    match mirror {
+        Some(mirror) => mirror.decap(data),
+        None         => Some(Frame::Ethernet(data)),
+    }
+}
+
+// Ethernet frame carried by ERSPAN type I, II or III over GRE. Type I
+// shares the type II protocol but has no sequence number or header, and
+// type III may be followed by an 8-byte platform specific subheader.
+fn erspan(frame: &[u8]) -> Option<&[u8]> {
+    let (proto, gre) = ip(frame)?;
+    if proto != GRE {
+        return None;
+    }
+
+    let flags = BE::read_u16(gre.get(0..2)?);
+    let ptype = BE::read_u16(gre.get(2..4)?);
+    let seq   = flags & 0x1000 != 0;
+
+    let n = 4 + [0x8000, 0x2000, 0x1000].iter().filter(|&&bit| flags & bit != 0).count() * 4;
+    let rest = gre.get(n..)?;
+
+    match ptype {
+        ERSPAN_II if !seq => Some(rest),
+        ERSPAN_II         => rest.get(8..),
+        ERSPAN_III        => match rest.get(11)? & 0x01 {
+            0 => rest.get(12..),
+            _ => rest.get(20..),
+        },
+        _                 => None,
+    }
+}
+
+// Protocol and payload of an unfragmented IP packet in an Ethernet
+// frame, bounded by the IP length.
+fn ip(frame: &[u8]) -> Option<(u8, &[u8])> {
+    let (ethertype, offset) = l3(frame)?;
+    let rest = &frame[offset..];
+
+    match ethertype {
+        0x0800 => {
+            let ihl   = (*rest.first()? & 0x0F) as usize * 4;
+            let total = BE::read_u16(rest.get(2..4)?) as usize;
+            let frag  = BE::read_u16(rest.get(6..8)?) & 0x3FFF;
+            if frag != 0 {
+                return None;
+            }
+            Some((*rest.get(9)?, rest.get(ihl..total.min(rest.len()))?))
+        },
+        0x86DD => {
+            let len = BE::read_u16(rest.get(4..6)?) as usize;
+            ipv6(*rest.get(6)?, rest.get(40..(40 + len).min(rest.len()))?)
+        },
+        _      => None,
+    }
+}
+
+// Upper-layer protocol and payload following any IPv6 extension
+// headers, unless the packet is fragmented.
+fn ipv6(mut next: u8, mut rest: &[u8]) -> Option<(u8, &[u8])> {
+    loop {
+        let len = match next {
+            0 | 43 | 60 | 135 | 139 | 140 => (*rest.get(1)? as usize + 1) * 8,
+            51                            => (*rest.get(1)? as usize + 2) * 4,
+            44                            => match BE::read_u16(rest.get(2..4)?) & 0xFFF9 {
+                0 => 8,
+                _ => return None,
+            },
+            _                             => return Some((next, rest)),
+        };
+        next = *rest.first()?;
+        rest = rest.get(len..)?;
+    }
+}
+
+// Ethertype and offset of the packet in an Ethernet frame, following
+// any VLAN tags.
+fn l3(frame: &[u8]) -> Option<(u16, usize)> {
+    let mut ethertype = BE::read_u16(frame.get(12..14)?);
+    let mut offset    = 14;
+
+    while ethertype == 0x8100 || ethertype == 0x88A8 {
+        ethertype = BE::read_u16(frame.get(offset + 2..offset + 4)?);
+        offset   += 4;
+    }
+
+    Some((ethertype, offset))
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(format: &str) -> Result<Self> {
+        let (name, arg) = match format.split_once(':') {
+            Some((name, arg)) => (name, Some(arg.parse::<usize>()?)),
+            None              => (format, None),
+        };
+
+        match (name, arg) {
+            ("juniper", None)    => Ok(Format::Juniper),
+            ("erspan",  None)    => Ok(Format::Erspan),
+            ("arista",  None)    => Ok(Format::Trailer(ARISTA_TRAILER)),
+            ("trailer", Some(n)) => Ok(Format::Trailer(n)),
+            ("fixed",   Some(n)) => Ok(Format::Fixed(n)),
+            _                    => Err(anyhow!("invalid mirror format")),
+        }
+    }
+}
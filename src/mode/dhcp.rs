@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::mem::swap;
+use std::net::Ipv4Addr;
+use anyhow::Result;
+use log::warn;
+use nom::IResult::Done;
+use pcap::{Capture, Active};
+use pcap::Error::*;
+use pnet::packet::{Packet as PacketExt};
+use pnet::packet::ethernet::EthernetPacket;
+use pnet::util::MacAddr;
+use time::Duration;
+use kentik_api::tag::{self, *};
+use crate::packet::{self, Transport::UDP};
+use crate::protocol::dhcp::parser::{self, Opt};
+use crate::reasm::Reassembler;
+use crate::time::Timestamp;
+
+pub struct Dhcp {
+    asm:     Reassembler,
+    client:  Client,
+    leases:  HashMap<MacAddr, Lease>,
+    names:   HashMap<MacAddr, (String, Timestamp)>,
+    upserts: Vec<Upsert>,
+    deletes: Vec<Delete>,
+    last:    Timestamp,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum Request {
+    Name(MacAddr, String),
+    Ack(MacAddr, Ipv4Addr, Option<String>, Option<u32>),
+    Release(MacAddr),
+}
+
+// An address leased to a client and the tag value it was upserted as.
+#[derive(Eq, PartialEq, Debug)]
+pub struct Lease {
+    pub addr:    Ipv4Addr,
+    pub value:   String,
+    pub expires: Timestamp,
+}
+
+const ACK:     u8 = 5;
+const RELEASE: u8 = 7;
+
+const TAG: &str = "kt_dhcp_host";
+
+// Lease time assumed when an ACK doesn't include one.
+const DEFAULT_LEASE: u32 = 86400;
+
+pub fn run(mut cap: Capture<Active>, client: Client) -> Result<()> {
+    let mut dhcp = Dhcp::new(client);
+
+    cap.filter("udp port 67 or udp port 68", true)?;
+
+    loop {
+        match cap.next_packet() {
+            Ok(packet)          => dhcp.record(packet),
+            Err(TimeoutExpired) => dhcp.flush(Timestamp::now()),
+            Err(NoMorePackets)  => return Ok(()),
+            Err(e)              => return Err(e.into()),
+        }
+    }
+}
+
+impl Dhcp {
+    pub fn new(client: Client) -> Self {
+        Self {
+            asm:     Reassembler::new(),
+            client:  client,
+            leases:  HashMap::new(),
+            names:   HashMap::new(),
+            upserts: Vec::new(),
+            deletes: Vec::new(),
+            last:    Timestamp::zero(),
+        }
+    }
+
+    pub fn record<'a>(&mut self, packet: pcap::Packet<'a>) {
+        let eth = match EthernetPacket::new(packet.data) {
+            Some(pkt) => pkt,
+            None      => return,
+        };
+
+        if let (_vlan, Some(pkt)) = packet::decode(&eth) {
+            let ts = Timestamp::from(packet.header.ts);
+
+            if let Some(out) = self.asm.reassemble(ts, &pkt) {
+                if let Some(transport) = pkt.transport(&out.data) {
+                    let payload = match transport {
+                        UDP(ref udp) => udp.payload(),
+                        _            => return,
+                    };
+
+                    self.parse(payload).map(|r| self.update(r, ts));
+                }
+            }
+
+            self.flush(ts);
+        }
+    }
+
+    pub fn parse(&mut self, payload: &[u8]) -> Option<Request> {
+        let msg = match parser::message(payload) {
+            Done(_, msg) => msg,
+            _            => return None,
+        };
+
+        if msg.chaddr.len() != 6 {
+            return None;
+        }
+
+        let c   = msg.chaddr;
+        let mac = MacAddr::new(c[0], c[1], c[2], c[3], c[4], c[5]);
+
+        let mut kind  = None;
+        let mut host  = None;
+        let mut lease = None;
+
+        for opt in msg.opts {
+            match opt {
+                Opt::Type(t)  => kind  = Some(t),
+                Opt::Host(s)  => host  = Some(s.to_owned()),
+                Opt::Lease(n) => lease = Some(n),
+                _             => (),
+            }
+        }
+
+        match (msg.op, kind, host) {
+            (2, Some(ACK), host) if !msg.yiaddr.is_unspecified() => {
+                Some(Request::Ack(mac, msg.yiaddr, host, lease))
+            },
+            (1, Some(RELEASE), _) => Some(Request::Release(mac)),
+            (1, _, Some(host))    => Some(Request::Name(mac, host)),
+            _                     => None,
+        }
+    }
+
+    pub fn leases(&self) -> &HashMap<MacAddr, Lease> {
+        &self.leases
+    }
+
+    pub fn update(&mut self, r: Request, ts: Timestamp) {
+        match r {
+            Request::Name(mac, host)             => self.name(mac, host, ts),
+            Request::Ack(mac, addr, host, lease) => self.ack(mac, addr, host, lease, ts),
+            Request::Release(mac)                => self.release(mac),
+        }
+    }
+
+    // Hostnames are often only sent by the client, so remember them
+    // until the server's ACK.
+    fn name(&mut self, mac: MacAddr, host: String, ts: Timestamp) {
+        self.names.insert(mac, (host, ts));
+    }
+
+    // Tag the leased address with the client's hostname and MAC address,
+    // or only its MAC address when it has no hostname. Hostnames aren't
+    // unique so the MAC keeps one client's delete from removing another
+    // client's tag. Renewals without a hostname keep the existing value
+    // and only extend the lease.
+    fn ack(&mut self, mac: MacAddr, addr: Ipv4Addr, host: Option<String>, lease: Option<u32>, ts: Timestamp) {
+        let name  = self.names.remove(&mac).map(|(name, _)| name);
+        let known = self.leases.get(&mac).map(|lease| lease.value.clone());
+        let value = match (host.or(name), known) {
+            (Some(host), _)     => format!("{} ({})", host, mac),
+            (None, Some(known)) => known,
+            (None, None)        => mac.to_string(),
+        };
+        let secs  = lease.unwrap_or(DEFAULT_LEASE);
+        let lease = Lease {
+            addr:    addr,
+            value:   value,
+            expires: ts + Duration::seconds(secs as i64),
+        };
+
+        match self.leases.get(&mac).map(|old| (old.addr, old.value.clone())) {
+            Some((addr, value)) if value == lease.value => {
+                if addr != lease.addr {
+                    self.upsert(&lease);
+                }
+            },
+            Some((_, value)) => {
+                self.delete(value);
+                self.upsert(&lease);
+            },
+            None => self.upsert(&lease),
+        }
+
+        self.leases.insert(mac, lease);
+    }
+
+    fn release(&mut self, mac: MacAddr) {
+        if let Some(lease) = self.leases.remove(&mac) {
+            self.delete(lease.value);
+        }
+    }
+
+    fn upsert(&mut self, lease: &Lease) {
+        self.upserts.push(Upsert::Small(Small {
+            value:    lease.value.clone(),
+            criteria: (Rule {
+                addr: Some((lease.addr.to_string(),)),
+                ..Default::default()
+            },)
+        }));
+    }
+
+    fn delete(&mut self, value: String) {
+        self.deletes.push(Delete {
+            value: value,
+        });
+    }
+
+    // Leases which weren't renewed before they expired.
+    fn expire(&mut self, ts: Timestamp) {
+        let expired = self.leases.iter().filter(|(_, lease)| lease.expires <= ts).map(|(mac, _)| *mac);
+        for mac in expired.collect::<Vec<_>>() {
+            self.release(mac);
+        }
+        self.names.retain(|_, (_, seen)| ts - *seen < Duration::seconds(60));
+    }
+
+    pub fn flush(&mut self, ts: Timestamp) {
+        if (ts - self.last) >= Duration::seconds(1) {
+            self.expire(ts);
+
+            let mut upserts = Vec::with_capacity(self.upserts.len());
+            let mut deletes = Vec::with_capacity(self.deletes.len());
+
+            swap(&mut self.upserts, &mut upserts);
+            swap(&mut self.deletes, &mut deletes);
+
+            if !upserts.is_empty() || !deletes.is_empty() {
+                let req = tag::Request {
+                    replace_all: false,
+                    complete:    true,
+                    ttl_minutes: 0,
+                    upserts:     upserts,
+                    deletes:     deletes,
+                };
+
+                let timeout = Duration::milliseconds(10).unsigned_abs();
+                match self.client.send(TAG, req, timeout) {
+                    Ok(..) => (),
+                    Err(e) => warn!("tag queue full: {:?}", e),
+                };
+            }
+
+            self.asm.flush(ts);
+            self.last = ts;
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
 use std::mem::swap;
 use std::net::IpAddr;
 use anyhow::Result;
@@ -12,41 +14,88 @@ use pnet::packet::udp::UdpPacket;
 use time::Duration;
 use kentik_api::dns::*;
 use crate::flow::Addr;
+use crate::mirror::{self, Frame, Mirror};
 use crate::packet::{self, Packet, Transport::*};
-use crate::protocol::dns::parser::{self, Rdata};
+use crate::protocol::dns::parser::{self, Rdata, QQ, RR};
 use crate::reasm::Reassembler;
 use crate::time::Timestamp;
+use super::dnstap;
 
 pub struct Dns {
-    asm:    Reassembler,
-    buffer: Vec<Response>,
-    client: Client,
-    last:   Timestamp,
+    asm:     Reassembler,
+    buffer:  Vec<Response>,
+    streams: HashMap<(Addr, Addr), (Timestamp, Vec<u8>)>,
+    queries: HashMap<(IpAddr, String, u16), (IpAddr, Timestamp)>,
+    cache:   HashMap<(String, Vec<u8>), Timestamp>,
+    retry:   VecDeque<Batch>,
+    client:  Client,
+    empty:   bool,
+    mirror:  Option<Mirror>,
+    stats:   Stats,
+    last:    Timestamp,
+}
+
+// A batch which failed to send, retried with exponential backoff.
+struct Batch {
+    responses: Vec<Response>,
+    attempts:  u32,
+    next:      Timestamp,
 }
 
+// Totals of responses sent, suppressed as duplicates of a recently
+// sent (name, ip) pair, and dropped after failing to send.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+    pub sent:    u64,
+    pub deduped: u64,
+    pub dropped: u64,
+}
+
+const MAX_QUERIES:  usize = 1 << 16;
+const MAX_CHAIN:    usize = 16;
+const MAX_CACHE:    usize = 1 << 18;
+const MAX_RETRY:    usize = 16;
+const MAX_ATTEMPTS: u32   = 6;
+const REFRESH:      u32   = 300;
+
 pub fn run(
     cap: Capture<Active>,
     client: Client,
     filter_expr: Option<String>,
+    empty: bool,
+    mirror: Option<Mirror>,
 ) -> Result<()> {
-    run_base(cap, client, filter_expr, Dns::plain_parse)
+    run_base(cap, client, filter_expr, empty, mirror, Dns::plain_parse)
 }
 
-pub fn run_juniper(
-    cap: Capture<Active>,
+pub fn run_base<F>(
+    mut cap: Capture<Active>,
     client: Client,
     filter_expr: Option<String>,
-) -> Result<()> {
-    run_base(cap, client, filter_expr, Dns::parse_stripped)
-}
-
-pub fn run_base<F>(mut cap: Capture<Active>, client: Client, filter_expr: Option<String>, mut parser: F) -> Result<()>
-where F: FnMut(&mut Dns, Addr, Addr, & [u8], Timestamp)
+    empty: bool,
+    mirror: Option<Mirror>,
+    mut parser: F,
+) -> Result<()>
+where F: FnMut(&mut Dns, Addr, Addr, Segment, Timestamp)
 {
     let mut dns = Dns::new(client);
+    dns.forward_empty(empty);
+
+    // Queries are needed to attribute forwarded replies to the client.
+    // Mirrored DNS traffic is encapsulated so the default filter can't
+    // match it.
+    let filter_expr = match mirror {
+        Some(_) => filter_expr,
+        None    => filter_expr.or(Some("port 53 or ip[6:2] & 0x1fff != 0x0000".to_owned())),
+    };
+
+    if let Some(filter_expr) = filter_expr {
+        cap.filter(&filter_expr, true)?;
+    }
 
-    let filter_expr = filter_expr.unwrap_or("udp src port 53 or ip[6:2] & 0x1fff != 0x0000".to_owned());
-    cap.filter(&filter_expr, true)?;
+    if let Some(mirror) = mirror {
+        dns.mirror(mirror);
+    }
 
     loop {
         match cap.next_packet() {
@@ -61,131 +110,349 @@ where F: FnMut(&mut Dns, Addr, Addr, & [u8], Timestamp)
 impl Dns {
     pub fn new(client: Client) -> Self {
         Dns {
-            asm:    Reassembler::new(),
-            buffer: Vec::with_capacity(1024),
-            client: client,
-            last:   Timestamp::zero(),
+            asm:     Reassembler::new(),
+            buffer:  Vec::with_capacity(1024),
+            streams: HashMap::new(),
+            queries: HashMap::new(),
+            cache:   HashMap::new(),
+            retry:   VecDeque::new(),
+            client:  client,
+            empty:   false,
+            mirror:  None,
+            stats:   Stats::default(),
+            last:    Timestamp::zero(),
         }
     }
 
+    // Forward NXDOMAIN replies and replies without any addresses.
+    pub fn forward_empty(&mut self, enable: bool) {
+        self.empty = enable;
+    }
+
+    // Decapsulate mirrored traffic before parsing.
+    pub fn mirror(&mut self, mirror: Mirror) {
+        self.mirror = Some(mirror);
+    }
+
     pub fn record<'a, F>(&mut self, packet: pcap::Packet<'a>, consumer: &mut F)
-    where F: FnMut(&mut Self, Addr, Addr, & [u8], Timestamp),
+    where F: FnMut(&mut Self, Addr, Addr, Segment, Timestamp),
     {
-        let eth = match EthernetPacket::new(packet.data) {
-            Some(pkt) => pkt,
-            None => return,
-        };
+        let ts = Timestamp::from(packet.header.ts);
 
-        if let (_vlan, Some(pkt)) = packet::decode(&eth) {
-            let ts = Timestamp::from(packet.header.ts);
+        let data = match self.mirror {
+            Some(ref m) => m.reassemble(&mut self.asm, ts, packet.data),
+            None        => Some(Cow::from(packet.data)),
+        };
 
-            if let Some(out) = self.asm.reassemble(ts, &pkt) {
-                if let Some(transport) = pkt.transport(&out.data) {
-                    let (src, dst, payload) = match transport {
-                        TCP(ref tcp) => self.tcp(&pkt, tcp),
-                        UDP(ref udp) => self.udp(&pkt, udp),
-                        _ => return,
-                    };
+        let data = match data {
+            Some(data) => data,
+            None       => return self.flush(ts),
+        };
 
-                    consumer(self, src, dst, payload, ts);
+        match mirror::frame(self.mirror.as_ref(), &data) {
+            Some(Frame::Ethernet(data)) => {
+                if let Some(eth) = EthernetPacket::new(data) {
+                    if let (_vlan, Some(pkt)) = packet::decode(&eth) {
+                        self.packet(&pkt, ts, consumer);
+                    }
                 }
-            }
-
-            self.flush(ts);
+            },
+            Some(Frame::IP(data)) => {
+                if let Some(pkt) = packet::decode_from_l3(data) {
+                    self.packet(&pkt, ts, consumer);
+                }
+            },
+            None => (),
         }
-    }
 
-    pub fn plain_parse(&mut self, src: Addr, dst: Addr, payload: &[u8], _ts: Timestamp) {
-        self.parse(src, dst, payload).map(|r| {
-            self.buffer.push(r);
-        });
+        self.flush(ts);
     }
 
-    pub fn parse_stripped(&mut self, _src: Addr, _dst: Addr, payload: &[u8], ts: Timestamp) {
-        let pkt = packet::decode_from_l3(&payload[8..]);
-        if pkt.is_none() {
-            return
-        }
-        let pkt = pkt.unwrap();
-
-        if let Some(out) = self.asm.reassemble(ts, &pkt) {
+    fn packet<F>(&mut self, pkt: &Packet, ts: Timestamp, consumer: &mut F)
+    where F: FnMut(&mut Self, Addr, Addr, Segment, Timestamp),
+    {
+        if let Some(out) = self.asm.reassemble(ts, pkt) {
             if let Some(transport) = pkt.transport(&out.data) {
-                let (src, dst, payload) = match transport {
-                    TCP(ref tcp) => self.tcp(&pkt, tcp),
-                    UDP(ref udp) => self.udp(&pkt, udp),
+                let (src, dst, segment) = match transport {
+                    TCP(ref tcp) => self.tcp(pkt, tcp),
+                    UDP(ref udp) => self.udp(pkt, udp),
                     _ => return,
                 };
 
-                self.plain_parse(src, dst, payload, ts);
+                consumer(self, src, dst, segment, ts);
             }
         }
     }
 
-    pub fn parse(&mut self, _src: Addr, dst: Addr, payload: &[u8]) -> Option<Response> {
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    pub fn plain_parse(&mut self, src: Addr, dst: Addr, segment: Segment, ts: Timestamp) {
+        match segment {
+            Segment::Datagram(payload) => {
+                self.parse(src, dst, payload, ts).map(|r| {
+                    self.push(r, ts);
+                });
+            },
+            Segment::Stream(payload) => {
+                for r in self.parse_tcp(src, dst, payload, ts) {
+                    self.push(r, ts);
+                }
+            },
+        }
+    }
+
+    // TCP messages are length-prefixed and may span segments, so any
+    // partial message is kept until the rest of it arrives.
+    pub fn parse_tcp(&mut self, src: Addr, dst: Addr, payload: &[u8], ts: Timestamp) -> Vec<Response> {
+        let mut buf = match self.streams.remove(&(src, dst)) {
+            Some((_, mut buf)) => { buf.extend_from_slice(payload); buf },
+            None               => payload.to_vec(),
+        };
+
+        let (msgs, rest) = parser::frames(&buf);
+        let rs = msgs.into_iter().flat_map(|msg| self.parse(src, dst, msg, ts)).collect();
+        let n  = buf.len() - rest.len();
+
+        buf.drain(..n);
+        if !buf.is_empty() {
+            self.streams.insert((src, dst), (ts, buf));
+        }
+
+        rs
+    }
+
+    pub fn parse(&mut self, src: Addr, dst: Addr, payload: &[u8], ts: Timestamp) -> Option<Response> {
         let mut msg = match parser::parse_message(payload) {
             Done(_, msg) => msg,
             _            => return None,
         };
 
-        // msg.header.qr == 1 -> dns reply
         // msg.header.opcode == 0 -> standard query
-        // msg.answer.is_empty() -> nothing to look at
-        if msg.header.qr != 1 || msg.header.opcode != 0 || msg.answer.is_empty() {
+        if msg.header.opcode != 0 {
+            return None;
+        }
+
+        let qq = msg.query.pop()?;
+
+        // msg.header.qr == 0 -> dns query, remembered so a forwarder's
+        // upstream reply can be attributed to the original client
+        if msg.header.qr == 0 {
+            self.query(src, dst, qq, ts);
             return None;
         }
 
-        msg.query.pop().map(|qq| {
-            let answers = msg.answer.iter().flat_map(|rr| {
-                let name  = String::new();
-                let cname = String::new();
-                let ttl   = rr.ttl;
-                match rr.rdata {
-                    Rdata::A(ip)    => Some(Answer{name, cname, ip: addr(IpAddr::V4(ip)), ttl}),
-                    Rdata::Aaaa(ip) => Some(Answer{name, cname, ip: addr(IpAddr::V6(ip)), ttl}),
-                    _               => None,
+        let answers = answers(&qq.qname, &msg.answer);
+        if !self.empty && answers.iter().all(|a| a.ip.is_empty()) {
+            return None;
+        }
+
+        let key  = (dst.addr, qq.qname.to_ascii_lowercase(), qq.qtype);
+        let host = self.queries.get(&key).map(|&(client, _)| client).unwrap_or(dst.addr);
+
+        Some(Response {
+            question: Question{
+                name: qq.qname,
+                host: addr(host),
+            },
+            answers:  answers,
+        })
+    }
+
+    fn query(&mut self, src: Addr, dst: Addr, qq: QQ, ts: Timestamp) {
+        if self.queries.len() < MAX_QUERIES {
+            let key = (dst.addr, qq.qname.to_ascii_lowercase(), qq.qtype);
+            self.queries.insert(key, (src.addr, ts));
+        }
+    }
+
+    pub fn dnstap(&mut self, msg: dnstap::Message) {
+        let (src, dst) = (msg.response, msg.query);
+        if let Some(r) = self.parse(src, dst, &msg.payload, msg.time) {
+            self.push(r, msg.time);
+        }
+    }
+
+    // Answers whose (name, ip) pair was sent within the lesser of its
+    // TTL and the refresh interval are removed, and the response is
+    // dropped if none remain.
+    fn push(&mut self, mut r: Response, ts: Timestamp) {
+        if !r.answers.is_empty() {
+            let cache = &mut self.cache;
+            let full  = cache.len() >= MAX_CACHE;
+
+            r.answers.retain(|a| {
+                let key = (a.name.to_ascii_lowercase(), a.ip.clone());
+                match cache.get(&key) {
+                    Some(&expires) if expires > ts => false,
+                    _ if full                      => true,
+                    _                              => {
+                        let ttl = a.ttl.min(REFRESH);
+                        cache.insert(key, ts + Duration::seconds(ttl as i64));
+                        true
+                    },
                 }
-            }).collect();
-
-            Response {
-                question: Question{
-                    name: qq.qname,
-                    host: addr(dst.addr),
-                },
-                answers:  answers,
+            });
+
+            if r.answers.is_empty() {
+                self.stats.deduped += 1;
+                return;
             }
-        })
+        }
+
+        self.buffer.push(r);
     }
 
-    fn flush(&mut self, ts: Timestamp) {
+    pub fn flush(&mut self, ts: Timestamp) {
         if (ts - self.last) >= Duration::seconds(1) {
             let mut rs = Vec::with_capacity(self.buffer.len());
             swap(&mut self.buffer, &mut rs);
 
-            let timeout = Duration::milliseconds(10).unsigned_abs();
-            let len = rs.len();
-            match self.client.send(rs, timeout) {
-                Ok(..) => debug!("DNS batch sent: {}", len),
-                Err(e) => warn!("DNS queue full: {:?}", e),
-            };
+            self.resend(ts);
+            self.send(rs, 0, ts);
+
+            debug!("DNS totals: {:?}", self.stats);
 
             self.asm.flush(ts);
+            self.streams.retain(|_, (last, _)| ts - *last < Duration::seconds(60));
+            self.queries.retain(|_, (_, last)| ts - *last < Duration::seconds(10));
+            self.cache.retain(|_, expires| *expires > ts);
             self.last = ts;
         }
     }
 
-    fn tcp<'a>(&self, p: &Packet, tcp: &'a TcpPacket) -> (Addr, Addr, &'a [u8]) {
+    fn send(&mut self, rs: Vec<Response>, attempts: u32, ts: Timestamp) -> bool {
+        let timeout = Duration::milliseconds(10).unsigned_abs();
+        let len = rs.len();
+
+        match self.client.send(rs.clone(), timeout) {
+            Ok(..) => {
+                debug!("DNS batch sent: {}", len);
+                self.stats.sent += len as u64;
+                true
+            },
+            Err(e) => {
+                warn!("DNS batch of {} failed: {:?}", len, e);
+                if len > 0 {
+                    self.requeue(rs, attempts + 1, ts);
+                }
+                false
+            },
+        }
+    }
+
+    // Failed batches wait 2, 4, 8... seconds between attempts, and the
+    // oldest is dropped when the queue is full.
+    fn requeue(&mut self, rs: Vec<Response>, attempts: u32, ts: Timestamp) {
+        if attempts > MAX_ATTEMPTS {
+            self.discard(rs);
+            return;
+        }
+
+        if self.retry.len() == MAX_RETRY {
+            if let Some(batch) = self.retry.pop_front() {
+                self.discard(batch.responses);
+            }
+        }
+
+        self.retry.push_back(Batch {
+            responses: rs,
+            attempts:  attempts,
+            next:      ts + Duration::seconds(1 << attempts),
+        });
+    }
+
+    // Answers of a dropped batch are evicted from the dedup cache so
+    // they're sent again when next seen.
+    fn discard(&mut self, rs: Vec<Response>) {
+        self.stats.dropped += rs.len() as u64;
+        for a in rs.iter().flat_map(|r| &r.answers) {
+            self.cache.remove(&(a.name.to_ascii_lowercase(), a.ip.clone()));
+        }
+    }
+
+    fn resend(&mut self, ts: Timestamp) {
+        while self.retry.front().map(|batch| batch.next <= ts).unwrap_or(false) {
+            if let Some(batch) = self.retry.pop_front() {
+                if !self.send(batch.responses, batch.attempts, ts) {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn tcp<'a>(&self, p: &Packet, tcp: &'a TcpPacket) -> (Addr, Addr, Segment<'a>) {
         let src = Addr{addr: p.src(), port: tcp.get_source()};
         let dst = Addr{addr: p.dst(), port: tcp.get_destination()};
-        (src, dst, tcp.payload())
+        (src, dst, Segment::Stream(tcp.payload()))
     }
 
-    fn udp<'a>(&self, p: &Packet, udp: &'a UdpPacket) -> (Addr, Addr, &'a [u8]) {
+    fn udp<'a>(&self, p: &Packet, udp: &'a UdpPacket) -> (Addr, Addr, Segment<'a>) {
         let src = Addr{addr: p.src(), port: udp.get_source()};
         let dst = Addr{addr: p.dst(), port: udp.get_destination()};
-        (src, dst, udp.payload())
+        (src, dst, Segment::Datagram(udp.payload()))
     }
 }
 
+#[derive(Copy, Clone, Debug)]
+pub enum Segment<'a> {
+    Datagram(&'a [u8]),
+    Stream(&'a [u8]),
+}
+
+// Addresses in the answer section reached from the query name by
+// following any CNAME chain, each with the aliases leading to it. When
+// there are no addresses the chain itself is returned without an IP.
+fn answers(qname: &str, rrs: &[RR]) -> Vec<Answer> {
+    let aliases = rrs.iter().filter_map(|rr| match rr.rdata {
+        Rdata::Cname(ref target) => Some((rr.name.to_ascii_lowercase(), (target.as_str(), rr.ttl))),
+        _                        => None,
+    }).collect::<HashMap<_, _>>();
+
+    let mut owners = vec![qname.to_ascii_lowercase()];
+    let mut chain  = Vec::new();
+    let mut ttl    = u32::max_value();
+
+    while let Some(&(target, n)) = aliases.get(&owners[owners.len() - 1]) {
+        if chain.len() == MAX_CHAIN {
+            break;
+        }
+        chain.push(target);
+        owners.push(target.to_ascii_lowercase());
+        ttl = ttl.min(n);
+    }
+
+    let mut answers = rrs.iter().filter_map(|rr| {
+        let ip = match rr.rdata {
+            Rdata::A(ip)    => IpAddr::V4(ip),
+            Rdata::Aaaa(ip) => IpAddr::V6(ip),
+            _               => return None,
+        };
+
+        let owner = rr.name.to_ascii_lowercase();
+        let n     = owners.iter().position(|name| *name == owner)?;
+
+        Some(Answer{
+            name:  qname.to_owned(),
+            cname: chain[..n].join(","),
+            ip:    addr(ip),
+            ttl:   rr.ttl,
+        })
+    }).collect::<Vec<_>>();
+
+    if answers.is_empty() && !chain.is_empty() {
+        answers.push(Answer{
+            name:  qname.to_owned(),
+            cname: chain.join(","),
+            ip:    Vec::new(),
+            ttl:   ttl,
+        });
+    }
+
+    answers
+}
+
 fn addr(ip: IpAddr) -> Vec<u8> {
     match ip {
         IpAddr::V4(ip) => ip.octets().to_vec(),
@@ -0,0 +1,250 @@
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use byteorder::{BigEndian as BE, ByteOrder, ReadBytesExt, WriteBytesExt};
+use log::{debug, warn};
+use kentik_api::dns::Client;
+use crate::flow::Addr;
+use crate::time::Timestamp;
+use super::dns::Dns;
+
+// dnstap (https://dnstap.info) messages carried by Frame Streams, as
+// written by unbound, knot and bind to a Unix socket.
+
+#[derive(Debug)]
+pub struct Message {
+    pub kind:     u64,
+    pub query:    Addr,
+    pub response: Addr,
+    pub time:     Timestamp,
+    pub payload:  Vec<u8>,
+}
+
+pub const RESOLVER_RESPONSE: u64 = 4;
+pub const CLIENT_RESPONSE:   u64 = 6;
+
+pub const CONTENT_TYPE: &[u8] = b"protobuf:dnstap.Dnstap";
+
+// Frame Streams control frame types and fields.
+pub const ACCEPT: u32 = 1;
+pub const START:  u32 = 2;
+pub const STOP:   u32 = 3;
+pub const READY:  u32 = 4;
+pub const FINISH: u32 = 5;
+
+const FIELD_CONTENT_TYPE: u32 = 1;
+
+const MAX_CONTROL: usize = 512;
+const MAX_FRAME:   usize = 1 << 16;
+
+pub fn run(path: &Path, client: Client, empty: bool) -> Result<()> {
+    // Replace a socket left behind by a previous run, but never any
+    // other file that happens to be at the path.
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            return Err(anyhow!("{} exists and is not a socket", path.display()));
+        }
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e)     => return tx.send(Err(e)).unwrap_or(()),
+            };
+
+            let tx = tx.clone();
+            thread::spawn(move || {
+                match read(stream, |msg| { let _ = tx.send(Ok(msg)); }) {
+                    Ok(()) => debug!("dnstap writer finished"),
+                    Err(e) => warn!("dnstap connection failed: {:?}", e),
+                }
+            });
+        }
+    });
+
+    let mut dns = Dns::new(client);
+    dns.forward_empty(empty);
+
+    loop {
+        match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(Ok(msg))                         => dns.dnstap(msg),
+            Ok(Err(e))                          => return Err(anyhow!("dnstap accept failed: {}", e)),
+            Err(RecvTimeoutError::Timeout)      => (),
+            Err(RecvTimeoutError::Disconnected) => return Err(anyhow!("dnstap listener stopped")),
+        }
+        dns.flush(Timestamp::now());
+    }
+}
+
+// Read a bidirectional Frame Streams connection, accepting the
+// writer's READY and acknowledging its STOP. Writers which skip the
+// handshake and start with START are also accepted.
+pub fn read<S: Read + Write, F: FnMut(Message)>(mut stream: S, mut f: F) -> io::Result<()> {
+    loop {
+        let len = match stream.read_u32::<BE>() {
+            Ok(len)                                                => len as usize,
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e)                                                 => return Err(e),
+        };
+
+        if len == 0 {
+            let len = stream.read_u32::<BE>()? as usize;
+            if len < 4 || len > MAX_CONTROL {
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+
+            let mut frame = vec![0u8; len];
+            stream.read_exact(&mut frame)?;
+
+            match BE::read_u32(&frame) {
+                READY => control(&mut stream, ACCEPT, Some(CONTENT_TYPE))?,
+                STOP  => return control(&mut stream, FINISH, None),
+                _     => (),
+            }
+        } else {
+            // Skip frames too large to be a DNS response rather than
+            // dropping the writer's connection.
+            if len > MAX_FRAME {
+                debug!("skipping {} byte dnstap frame", len);
+                io::copy(&mut (&mut stream).take(len as u64), &mut io::sink())?;
+                continue;
+            }
+
+            let mut frame = vec![0u8; len];
+            stream.read_exact(&mut frame)?;
+
+            if let Some(msg) = decode(&frame) {
+                f(msg);
+            }
+        }
+    }
+}
+
+pub fn control<W: Write>(w: &mut W, kind: u32, content: Option<&[u8]>) -> io::Result<()> {
+    let fields = content.map(|c| 8 + c.len()).unwrap_or(0);
+
+    w.write_u32::<BE>(0)?;
+    w.write_u32::<BE>(4 + fields as u32)?;
+    w.write_u32::<BE>(kind)?;
+
+    if let Some(content) = content {
+        w.write_u32::<BE>(FIELD_CONTENT_TYPE)?;
+        w.write_u32::<BE>(content.len() as u32)?;
+        w.write_all(content)?;
+    }
+
+    w.flush()
+}
+
+// Dnstap { message: Message = 14, type: MESSAGE = 15 } containing a
+// CLIENT_RESPONSE or RESOLVER_RESPONSE.
+fn decode(frame: &[u8]) -> Option<Message> {
+    let message = fields(frame)?.into_iter().find_map(|field| match field {
+        (14, Field::Bytes(message)) => Some(message),
+        _                           => None,
+    })?;
+
+    let mut kind     = 0;
+    let mut query    = None;
+    let mut response = None;
+    let mut qport    = 0;
+    let mut rport    = 0;
+    let mut time     = Timestamp::zero();
+    let mut payload  = None;
+
+    for field in fields(message)? {
+        match field {
+            (1,  Field::Varint(n))  => kind      = n,
+            (4,  Field::Bytes(b))   => query     = ip(b),
+            (5,  Field::Bytes(b))   => response  = ip(b),
+            (6,  Field::Varint(n))  => qport     = n as u16,
+            (7,  Field::Varint(n))  => rport     = n as u16,
+            (12, Field::Varint(n))  => time.sec  = n,
+            (13, Field::Fixed32(n)) => time.nsec = n as u64,
+            (14, Field::Bytes(b))   => payload   = Some(b),
+            _                       => (),
+        }
+    }
+
+    if kind != CLIENT_RESPONSE && kind != RESOLVER_RESPONSE {
+        return None;
+    }
+
+    let unspecified = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
+    Some(Message {
+        kind:     kind,
+        query:    Addr{addr: query?, port: qport},
+        response: Addr{addr: response.unwrap_or(unspecified), port: rport},
+        time:     time,
+        payload:  payload?.to_vec(),
+    })
+}
+
+#[derive(Debug)]
+enum Field<'a> {
+    Varint(u64),
+    Fixed32(u32),
+    Fixed64,
+    Bytes(&'a [u8]),
+}
+
+// Protocol buffer fields as (number, value) pairs.
+fn fields(mut buf: &[u8]) -> Option<Vec<(u64, Field)>> {
+    let mut fields = Vec::new();
+
+    while !buf.is_empty() {
+        let (key, rest) = varint(buf)?;
+        let (field, rest) = match key & 0x07 {
+            0 => varint(rest).map(|(n, rest)| (Field::Varint(n), rest))?,
+            1 if rest.len() >= 8 => (Field::Fixed64, &rest[8..]),
+            2 => {
+                let (len, rest) = varint(rest)?;
+                let bytes = rest.get(..len as usize)?;
+                (Field::Bytes(bytes), &rest[bytes.len()..])
+            },
+            5 if rest.len() >= 4 => (Field::Fixed32(u32::from_le_bytes(rest[..4].try_into().ok()?)), &rest[4..]),
+            _ => return None,
+        };
+        fields.push((key >> 3, field));
+        buf = rest;
+    }
+
+    Some(fields)
+}
+
+fn varint(buf: &[u8]) -> Option<(u64, &[u8])> {
+    let mut value = 0u64;
+    for (i, &b) in buf.iter().enumerate().take(10) {
+        value |= ((b & 0x7f) as u64) << (i * 7);
+        if b & 0x80 == 0 {
+            return Some((value, &buf[i + 1..]));
+        }
+    }
+    None
+}
+
+fn ip(b: &[u8]) -> Option<IpAddr> {
+    match b.len() {
+        4  => Some(IpAddr::V4(Ipv4Addr::new(b[0], b[1], b[2], b[3]))),
+        16 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(b);
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        },
+        _  => None,
+    }
+}
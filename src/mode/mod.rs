@@ -1,2 +1,4 @@
+pub mod dhcp;
 pub mod dns;
+pub mod dnstap;
 pub mod radius;
@@ -4,7 +4,7 @@ use crate::flow::{Flow, Protocol};
 use crate::flow::Protocol::{TCP, UDP};
 use super::Decoder;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Classify {
     pub tcp: Vec<Decoder>,
     pub udp: Vec<Decoder>,
@@ -34,6 +34,16 @@ impl Classify {
         }
     }
 
+    pub fn ports(&self, p: Protocol, d: Decoder) -> Vec<u16> {
+        let ds = match p {
+            TCP => &self.tcp,
+            UDP => &self.udp,
+            _   => return Vec::new(),
+        };
+
+        ds.iter().enumerate().filter(|&(_, x)| *x == d).map(|(port, _)| port as u16).collect()
+    }
+
     fn search(ds: &[Decoder], src: u16, dst: u16) -> Decoder {
         let (x, y) = match src.cmp(&dst) {
             Less | Equal => (src as usize, dst as usize),
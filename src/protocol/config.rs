@@ -0,0 +1,18 @@
+use time::Duration;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub http_headers:    Vec<String>,
+    pub redis_key_depth: usize,
+    pub dns_timeout:     Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            http_headers:    Vec::new(),
+            redis_key_depth: 1,
+            dns_timeout:     Duration::seconds(5),
+        }
+    }
+}
@@ -4,22 +4,26 @@ use crate::flow::{FIN, SYN};
 use crate::flow::Protocol::{TCP, UDP};
 use crate::custom::Customs;
 use crate::protocol::*;
-use crate::protocol::dhcp;
+use crate::protocol::{dhcp, dhcpv6};
 use crate::time::Timestamp;
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Decoder {
-    DHCP, DNS, HTTP, Postgres, Radius, TLS, None
+    DHCP, DHCPv6, DNS, HTTP, MySQL, Postgres, QUIC, Radius, Redis, TLS, None
 }
 
 #[derive(Default)]
 pub struct Decoders {
     dns:      Option<dns::Decoder>,
     dhcp:     Option<dhcp::Decoder>,
+    dhcpv6:   Option<dhcpv6::Decoder>,
     http:     Option<http::Decoder>,
     tls:      Option<tls::Decoder>,
+    quic:     Option<quic::Decoder>,
+    mysql:    Option<mysql::Decoder>,
     postgres: Option<postgres::Decoder>,
     radius:   Option<radius::Decoder>,
+    redis:    Option<redis::Decoder>,
 }
 
 impl Decoders {
@@ -38,6 +42,12 @@ impl Decoders {
                 decoders.dhcp = Some(d);
             }
 
+            if let Ok(d) = dhcpv6::Decoder::new(cs) {
+                classify.add(UDP, 546, Decoder::DHCPv6);
+                classify.add(UDP, 547, Decoder::DHCPv6);
+                decoders.dhcpv6 = Some(d);
+            }
+
             if let Ok(d) = http::Decoder::new(cs) {
                 classify.add(TCP, 80, Decoder::HTTP);
                 decoders.http = Some(d);
@@ -48,6 +58,35 @@ impl Decoders {
                 decoders.tls = Some(d);
             }
 
+            if let Ok(d) = quic::Decoder::new(cs) {
+                classify.add(UDP, 443, Decoder::QUIC);
+                decoders.quic = Some(d);
+            }
+
+            let ports = default(classify.ports(TCP, Decoder::MySQL), 3306);
+            if let Ok(d) = mysql::Decoder::new(cs, ports.clone()) {
+                for port in ports {
+                    classify.add(TCP, port, Decoder::MySQL);
+                }
+                decoders.mysql = Some(d);
+            }
+
+            let ports = default(classify.ports(TCP, Decoder::Postgres), 5432);
+            if let Ok(d) = postgres::Decoder::new(cs, ports.clone()) {
+                for port in ports {
+                    classify.add(TCP, port, Decoder::Postgres);
+                }
+                decoders.postgres = Some(d);
+            }
+
+            let ports = default(classify.ports(TCP, Decoder::Redis), 6379);
+            if let Ok(d) = redis::Decoder::new(cs, ports.clone()) {
+                for port in ports {
+                    classify.add(TCP, port, Decoder::Redis);
+                }
+                decoders.redis = Some(d);
+            }
+
             if let Ok(d) = radius::Decoder::new(cs) {
                 // Populated in bin/kprobe.rs
                 decoders.radius = Some(d);
@@ -57,6 +96,12 @@ impl Decoders {
         decoders
     }
 
+    pub fn configure(&mut self, cfg: &Config) {
+        self.http.as_mut().map(|d| d.capture(&cfg.http_headers));
+        self.redis.as_mut().map(|d| d.key_depth(cfg.redis_key_depth));
+        self.dns.as_mut().map(|d| d.timeout(cfg.dns_timeout));
+    }
+
     pub fn decode(&mut self, d: Decoder, flow: &Flow, cs: &mut Customs) -> bool {
         if flow.payload.is_empty() && flow.tcp_flags() & (SYN|FIN) == 0 {
             return false
@@ -64,29 +109,68 @@ impl Decoders {
 
         match d {
             Decoder::DHCP     => self.dhcp.as_mut().map(|d| d.decode(flow, cs)),
+            Decoder::DHCPv6   => self.dhcpv6.as_mut().map(|d| d.decode(flow, cs)),
             Decoder::DNS      => self.dns.as_mut().map(|d| d.decode(flow, cs)),
             Decoder::HTTP     => self.http.as_mut().map(|d| d.decode(flow, cs)),
             Decoder::TLS      => self.tls.as_mut().map(|d| d.decode(flow, cs)),
+            Decoder::QUIC     => self.quic.as_mut().map(|d| d.decode(flow, cs)),
+            Decoder::MySQL    => self.mysql.as_mut().map(|d| d.decode(flow, cs)),
             Decoder::Postgres => self.postgres.as_mut().map(|d| d.decode(flow, cs)),
             Decoder::Radius   => self.radius.as_mut().map(|d| d.decode(flow, cs)),
+            Decoder::Redis    => self.redis.as_mut().map(|d| d.decode(flow, cs)),
             Decoder::None     => None,
         }.unwrap_or(false)
     }
 
-    pub fn append(&mut self, d: Decoder, key: &Key, cs: &mut Customs) {
+    // Further records completed by the last call to decode.
+    pub fn next(&mut self, d: Decoder, cs: &mut Customs) -> bool {
         match d {
-            Decoder::TLS => self.tls.as_mut().map(|d| d.append(key, cs)),
+            Decoder::DNS      => self.dns.as_mut().map(|d| d.next(cs)),
+            Decoder::HTTP     => self.http.as_mut().map(|d| d.next(cs)),
+            Decoder::MySQL    => self.mysql.as_mut().map(|d| d.next(cs)),
+            Decoder::Postgres => self.postgres.as_mut().map(|d| d.next(cs)),
+            Decoder::Redis    => self.redis.as_mut().map(|d| d.next(cs)),
+            _                 => None,
+        }.unwrap_or(false)
+    }
+
+    // Records owed for a flow without any new packets, such as DNS
+    // queries which were never answered.
+    pub fn expire(&mut self, d: Decoder, key: &Key, ts: Timestamp, cs: &mut Customs) -> bool {
+        match d {
+            Decoder::DNS => self.dns.as_mut().map(|d| d.expire(key, ts, cs)),
             _            => None,
+        }.unwrap_or(false)
+    }
+
+    pub fn append(&mut self, d: Decoder, key: &Key, cs: &mut Customs) {
+        match d {
+            Decoder::DNS  => self.dns.as_mut().map(|d| d.append(key, cs)),
+            Decoder::TLS  => self.tls.as_mut().map(|d| d.append(key, cs)),
+            Decoder::QUIC => self.quic.as_mut().map(|d| d.append(key, cs)),
+            _             => None,
         };
     }
 
     pub fn clear(&mut self, ts: Timestamp) {
         let timeout = Duration::seconds(60);
         self.dhcp.as_mut().map(|d| d.clear(ts, timeout));
+        self.dhcpv6.as_mut().map(|d| d.clear(ts, timeout));
         self.dns.as_mut().map(|d| d.clear(ts, timeout));
         self.http.as_mut().map(|d| d.clear(ts, timeout));
         self.tls.as_mut().map(|d| d.clear(ts, timeout));
+        self.quic.as_mut().map(|d| d.clear(ts, timeout));
         self.radius.as_mut().map(|d| d.clear(ts, timeout));
-        //self.postgres.as_mut().map(|d| d.clear(ts, timeout));
+        self.redis.as_mut().map(|d| d.clear(ts, timeout));
+        self.mysql.as_mut().map(|d| d.clear(ts, timeout));
+        self.postgres.as_mut().map(|d| d.clear(ts, timeout));
+    }
+}
+
+// Configured ports replace a decoder's default port.
+fn default(ports: Vec<u16>, port: u16) -> Vec<u16> {
+    match ports.is_empty() {
+        true  => vec![port],
+        false => ports,
     }
 }
@@ -6,7 +6,7 @@ use std::net::Ipv4Addr;
 use time::Duration;
 use nom::IResult::Done;
 use pnet::util::MacAddr;
-use super::parser::{self, Opt};
+use super::parser::{self, Opt, CIRCUIT_ID, REMOTE_ID, FQDN_ENCODED};
 use crate::time::Timestamp;
 
 pub struct Connection {
@@ -28,11 +28,20 @@ pub struct Message {
     pub ciaddr:  Ipv4Addr,
     pub yiaddr:  Ipv4Addr,
     pub siaddr:  Ipv4Addr,
+    pub giaddr:  Ipv4Addr,
     pub chaddr:  CString,
     pub host:    Option<CString>,
     pub domain:  Option<CString>,
     pub lease:   Option<Duration>,
     pub latency: Option<Duration>,
+    pub request: Option<Ipv4Addr>,
+    pub server:  Option<Ipv4Addr>,
+    pub vendor:  Option<CString>,
+    pub client:  Option<CString>,
+    pub fqdn:    Option<CString>,
+    pub circuit: Option<CString>,
+    pub remote:  Option<CString>,
+    pub params:  Option<CString>,
 }
 
 impl Connection {
@@ -73,11 +82,20 @@ impl State {
             ciaddr:  m.ciaddr,
             yiaddr:  m.yiaddr,
             siaddr:  m.siaddr,
+            giaddr:  m.giaddr,
             chaddr:  chaddr(m.chaddr),
             host:    None,
             domain:  None,
             lease:   None,
             latency: latency,
+            request: None,
+            server:  None,
+            vendor:  None,
+            client:  None,
+            fqdn:    None,
+            circuit: None,
+            remote:  None,
+            params:  None,
         }, m.opts)
     }
 
@@ -92,16 +110,80 @@ impl State {
 fn options(mut msg: Message, opts: Vec<Opt>) -> Message {
     for o in opts {
         match o {
-            Opt::Type(t)   => msg.msg    = t,
-            Opt::Host(s)   => msg.host   = CString::new(s).ok(),
-            Opt::Domain(s) => msg.domain = CString::new(s).ok(),
-            Opt::Lease(s)  => msg.lease  = Some(Duration::seconds(s as i64)),
-            _              => (),
+            Opt::Type(t)      => msg.msg     = t,
+            Opt::Host(s)      => msg.host    = CString::new(s).ok(),
+            Opt::Domain(s)    => msg.domain  = CString::new(s).ok(),
+            Opt::Lease(s)     => msg.lease   = Some(Duration::seconds(s as i64)),
+            Opt::Params(p)    => msg.params  = Some(fingerprint(p)),
+            Opt::Requested(a) => msg.request = Some(a),
+            Opt::Server(a)    => msg.server  = Some(a),
+            Opt::Vendor(v)    => msg.vendor  = Some(text(v)),
+            Opt::ClientId(c)  => msg.client  = Some(hex(c)),
+            Opt::Fqdn(f, n)   => msg.fqdn    = fqdn(f, n),
+            Opt::Relay(subs)  => {
+                for (code, data) in subs {
+                    match code {
+                        CIRCUIT_ID => msg.circuit = Some(text(data)),
+                        REMOTE_ID  => msg.remote  = Some(text(data)),
+                        _          => (),
+                    }
+                }
+            },
+            _                 => (),
         }
     }
     msg
 }
 
+// The parameter request list in order, which identifies the DHCP
+// client implementation and so the type of device.
+fn fingerprint(params: &[u8]) -> CString {
+    let codes = params.iter().map(u8::to_string).collect::<Vec<_>>();
+    CString::new(codes.join(",")).unwrap()
+}
+
+// Client FQDN, either ASCII or in DNS wire format.
+fn fqdn(flags: u8, name: &[u8]) -> Option<CString> {
+    let name = match flags & FQDN_ENCODED {
+        0 => name.to_vec(),
+        _ => labels(name)?,
+    };
+
+    let name = name.strip_suffix(b".").unwrap_or(&name);
+    match name.iter().all(|b| b.is_ascii_graphic()) {
+        true  => CString::new(name).ok(),
+        false => None,
+    }
+}
+
+pub fn labels(mut buf: &[u8]) -> Option<Vec<u8>> {
+    let mut name = Vec::with_capacity(buf.len());
+    while let Some((&n, rest)) = buf.split_first() {
+        if n == 0 {
+            break;
+        }
+        if !name.is_empty() {
+            name.push(b'.');
+        }
+        name.extend_from_slice(rest.get(..n as usize)?);
+        buf = &rest[n as usize..];
+    }
+    Some(name)
+}
+
+// Printable values as-is, anything else as hex.
+fn text(data: &[u8]) -> CString {
+    match data.iter().all(|b| *b == b' ' || b.is_ascii_graphic()) {
+        true  => CString::new(data).unwrap(),
+        false => hex(data),
+    }
+}
+
+pub fn hex(data: &[u8]) -> CString {
+    let hex = data.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>();
+    CString::new(hex.join(":")).unwrap()
+}
+
 fn chaddr(addr: &[u8]) -> CString {
     if addr.len() == 6 {
         let (a, b, c) = (addr[0], addr[1], addr[2]);
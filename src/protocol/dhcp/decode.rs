@@ -15,6 +15,15 @@ pub struct Decoder {
     domain:  u64,
     lease:   u64,
     latency: u64,
+    giaddr:  Option<u64>,
+    request: Option<u64>,
+    server:  Option<u64>,
+    vendor:  Option<u64>,
+    client:  Option<u64>,
+    fqdn:    Option<u64>,
+    circuit: Option<u64>,
+    remote:  Option<u64>,
+    params:  Option<u64>,
     message: Option<Message>,
     conn:    Connection,
 }
@@ -32,6 +41,15 @@ impl Decoder {
             domain:  cs.get(DHCP_DOMAIN)?,
             lease:   cs.get(DHCP_LEASE)?,
             latency: cs.get(APP_LATENCY)?,
+            giaddr:  cs.get(DHCP_GI_ADDR).ok(),
+            request: cs.get(DHCP_REQUESTED_ADDR).ok(),
+            server:  cs.get(DHCP_SERVER_ID).ok(),
+            vendor:  cs.get(DHCP_VENDOR_CLASS).ok(),
+            client:  cs.get(DHCP_CLIENT_ID).ok(),
+            fqdn:    cs.get(DHCP_CLIENT_FQDN).ok(),
+            circuit: cs.get(DHCP_CIRCUIT_ID).ok(),
+            remote:  cs.get(DHCP_REMOTE_ID).ok(),
+            params:  cs.get(DHCP_FINGERPRINT).ok(),
             message: None,
             conn:    Connection::new(),
         })
@@ -50,10 +68,39 @@ impl Decoder {
             msg.domain.as_ref().map(|s| cs.add_str(self.domain, s));
             msg.lease.map(|d| cs.add_u32(self.lease, d.whole_seconds() as u32));
             msg.latency.map(|d| cs.add_latency(self.latency, d));
+            self.options(msg, cs);
             true
         }).unwrap_or(false)
     }
 
+    fn options(&self, msg: &Message, cs: &mut Customs) {
+        if let Some(id) = self.giaddr.filter(|_| !msg.giaddr.is_unspecified()) {
+            cs.add_addr(id, msg.giaddr.into());
+        }
+
+        let addrs = [(self.request, msg.request), (self.server, msg.server)];
+        for (id, addr) in addrs.iter() {
+            if let (Some(id), Some(addr)) = (id, addr) {
+                cs.add_addr(*id, (*addr).into());
+            }
+        }
+
+        let strs = [
+            (self.vendor,  &msg.vendor),
+            (self.client,  &msg.client),
+            (self.fqdn,    &msg.fqdn),
+            (self.circuit, &msg.circuit),
+            (self.remote,  &msg.remote),
+            (self.params,  &msg.params),
+        ];
+
+        for (id, str) in strs.iter() {
+            if let (Some(id), Some(str)) = (id, str) {
+                cs.add_str(*id, str);
+            }
+        }
+    }
+
     pub fn clear(&mut self, _ts: Timestamp, _timeout: Duration) {
         return
     }
@@ -28,9 +28,22 @@ pub enum Opt<'a> {
     Domain(&'a str),
     Lease(u32),
     Params(&'a [u8]),
+    Requested(Ipv4Addr),
+    Server(Ipv4Addr),
+    Vendor(&'a [u8]),
+    ClientId(&'a [u8]),
+    Fqdn(u8, &'a [u8]),
+    Relay(Vec<(u8, &'a [u8])>),
     Other(u8, &'a [u8]),
 }
 
+// Relay agent information sub-options.
+pub const CIRCUIT_ID: u8 = 1;
+pub const REMOTE_ID:  u8 = 2;
+
+// Client FQDN flag indicating the name is in DNS wire format.
+pub const FQDN_ENCODED: u8 = 0x04;
+
 named!(pub message<&[u8], Message>, do_parse!(
     op:     be_u8
  >> htype:  be_u8
@@ -72,6 +85,12 @@ named!(opts<&[u8], Option<Opt>>, alt!(
     opt_lease  |
     opt_type   |
     opt_params |
+    opt_requested |
+    opt_server |
+    opt_vendor |
+    opt_client |
+    opt_fqdn   |
+    opt_relay  |
     opt_pad    |
     opt_end    |
     opt_other
@@ -107,6 +126,55 @@ named!(opt_params<&[u8], Option<Opt>>, do_parse!(
  >> (Some(Opt::Params(data)))
 ));
 
+named!(opt_requested<&[u8], Option<Opt>>, do_parse!(
+    tag!(&[0x32, 0x04])
+ >> addr: map!(be_u32, Ipv4Addr::from)
+ >> (Some(Opt::Requested(addr)))
+));
+
+named!(opt_server<&[u8], Option<Opt>>, do_parse!(
+    tag!(&[0x36, 0x04])
+ >> addr: map!(be_u32, Ipv4Addr::from)
+ >> (Some(Opt::Server(addr)))
+));
+
+named!(opt_vendor<&[u8], Option<Opt>>, do_parse!(
+    tag!(&[0x3C])
+ >> data: length_bytes!(be_u8)
+ >> (Some(Opt::Vendor(data)))
+));
+
+named!(opt_client<&[u8], Option<Opt>>, do_parse!(
+    tag!(&[0x3D])
+ >> data: length_bytes!(be_u8)
+ >> (Some(Opt::ClientId(data)))
+));
+
+named!(opt_fqdn<&[u8], Option<Opt>>, do_parse!(
+    tag!(&[0x51])
+ >> fqdn: flat_map!(length_bytes!(be_u8), fqdn)
+ >> (Some(fqdn))
+));
+
+named!(fqdn<&[u8], Opt>, do_parse!(
+    flags: be_u8
+ >> take!(2)
+ >> name:  rest
+ >> (Opt::Fqdn(flags, name))
+));
+
+named!(opt_relay<&[u8], Option<Opt>>, do_parse!(
+    tag!(&[0x52])
+ >> subs: flat_map!(length_bytes!(be_u8), many0!(complete!(suboption)))
+ >> (Some(Opt::Relay(subs)))
+));
+
+named!(suboption<&[u8], (u8, &[u8])>, do_parse!(
+    code: be_u8
+ >> data: length_bytes!(be_u8)
+ >> ((code, data))
+));
+
 named!(opt_pad<&[u8], Option<Opt>>, do_parse!(tag!(&[0x00]) >> (None)));
 named!(opt_end<&[u8], Option<Opt>>, do_parse!(tag!(&[0xFF]) >> (None)));
 
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::ffi::CString;
+use time::Duration;
+use nom::IResult::Done;
+use crate::protocol::dhcp::conn::{hex, labels};
+use crate::time::Timestamp;
+use super::parser::{self, Opt, Packet, ADVERTISE, REPLY};
+
+pub struct Connection {
+    state:  State,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    pending: HashMap<u64, Timestamp>,
+    hasher:  RandomState,
+}
+
+#[derive(Debug)]
+pub struct Message {
+    pub kind:     u8,
+    pub xid:      u32,
+    pub duid:     Option<CString>,
+    pub addrs:    Option<CString>,
+    pub prefixes: Option<CString>,
+    pub fqdn:     Option<CString>,
+    pub latency:  Option<Duration>,
+}
+
+impl Connection {
+    pub fn new() -> Self {
+        Connection {
+            state: Default::default(),
+        }
+    }
+
+    pub fn parse(&mut self, ts: Timestamp, buf: &[u8]) -> Option<Message> {
+        match parser::packet(buf) {
+            Done(_, packet) => self.state.update(unwrap(packet)?, ts),
+            _               => None,
+        }
+    }
+
+    // Forget requests which were never answered.
+    pub fn clear(&mut self, ts: Timestamp, timeout: Duration) {
+        self.state.pending.retain(|_, sent| ts - *sent < timeout);
+    }
+}
+
+impl State {
+    fn update(&mut self, m: parser::Message, ts: Timestamp) -> Option<Message> {
+        let mut msg = options(Message{
+            kind:     m.kind,
+            xid:      m.xid,
+            duid:     None,
+            addrs:    None,
+            prefixes: None,
+            fqdn:     None,
+            latency:  None,
+        }, m.opts);
+
+        // replies carry the client's DUID so it and the transaction
+        // ID identify the exchange.
+        let key = self.key(&msg);
+        msg.latency = match msg.kind {
+            ADVERTISE | REPLY => self.pending.remove(&key).map(|ts0| ts - ts0),
+            _                 => { self.pending.insert(key, ts); None },
+        };
+
+        Some(msg)
+    }
+
+    fn key(&mut self, m: &Message) -> u64 {
+        let mut s = self.hasher.build_hasher();
+        m.duid.hash(&mut s);
+        m.xid.hash(&mut s);
+        s.finish()
+    }
+}
+
+// The client or server message carried by any number of relays.
+fn unwrap(packet: Packet) -> Option<parser::Message> {
+    match packet {
+        Packet::Message(msg) => Some(msg),
+        Packet::Relay(relay) => relay.opts.into_iter().find_map(|o| match o {
+            Opt::RelayMsg(packet) => unwrap(*packet),
+            _                     => None,
+        }),
+    }
+}
+
+fn options(mut msg: Message, opts: Vec<Opt>) -> Message {
+    let mut addrs    = Vec::new();
+    let mut prefixes = Vec::new();
+
+    for o in opts {
+        match o {
+            Opt::ClientId(duid) => msg.duid = Some(hex(duid)),
+            Opt::Fqdn(_, name)  => msg.fqdn = labels(name).and_then(|n| CString::new(n).ok()),
+            Opt::IaNa(_, ias)   => addrs.extend(ias.iter().map(|a| {
+                format!("{} {}/{}", a.addr, a.preferred, a.valid)
+            })),
+            Opt::IaPd(_, ias)   => prefixes.extend(ias.iter().map(|p| {
+                format!("{}/{} {}/{}", p.prefix, p.len, p.preferred, p.valid)
+            })),
+            _                   => (),
+        }
+    }
+
+    msg.addrs    = list(addrs);
+    msg.prefixes = list(prefixes);
+    msg
+}
+
+// Leases as "address preferred/valid" separated by ';'.
+fn list(leases: Vec<String>) -> Option<CString> {
+    match leases.is_empty() {
+        true  => None,
+        false => CString::new(leases.join(";")).ok(),
+    }
+}
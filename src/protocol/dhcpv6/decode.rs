@@ -0,0 +1,48 @@
+use time::Duration;
+use crate::flow::Flow;
+use crate::custom::*;
+use crate::time::Timestamp;
+use super::conn::{Connection, Message};
+
+pub struct Decoder {
+    msg:      u64,
+    duid:     u64,
+    addrs:    u64,
+    prefixes: u64,
+    fqdn:     u64,
+    latency:  u64,
+    message:  Option<Message>,
+    conn:     Connection,
+}
+
+impl Decoder {
+    pub fn new(cs: &Customs) -> Result<Decoder, ()> {
+        Ok(Decoder{
+            msg:      cs.get(DHCPV6_MSG_TYPE)?,
+            duid:     cs.get(DHCPV6_DUID)?,
+            addrs:    cs.get(DHCPV6_ADDRS)?,
+            prefixes: cs.get(DHCPV6_PREFIXES)?,
+            fqdn:     cs.get(DHCPV6_FQDN)?,
+            latency:  cs.get(APP_LATENCY)?,
+            message:  None,
+            conn:     Connection::new(),
+        })
+    }
+
+    pub fn decode(&mut self, flow: &Flow, cs: &mut Customs) -> bool {
+        self.message = self.conn.parse(flow.timestamp, flow.payload);
+        self.message.as_ref().map(|msg| {
+            cs.add_u32(self.msg, msg.kind as u32);
+            msg.duid.as_ref().map(|s| cs.add_str(self.duid, s));
+            msg.addrs.as_ref().map(|s| cs.add_str(self.addrs, s));
+            msg.prefixes.as_ref().map(|s| cs.add_str(self.prefixes, s));
+            msg.fqdn.as_ref().map(|s| cs.add_str(self.fqdn, s));
+            msg.latency.map(|d| cs.add_latency(self.latency, d));
+            true
+        }).unwrap_or(false)
+    }
+
+    pub fn clear(&mut self, ts: Timestamp, timeout: Duration) {
+        self.conn.clear(ts, timeout);
+    }
+}
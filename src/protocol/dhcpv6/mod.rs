@@ -0,0 +1,5 @@
+pub mod conn;
+pub mod decode;
+pub mod parser;
+
+pub use self::decode::*;
@@ -0,0 +1,204 @@
+use std::net::Ipv6Addr;
+use nom::*;
+use nom::IResult::Done;
+
+#[derive(Debug)]
+pub enum Packet<'a> {
+    Message(Message<'a>),
+    Relay(Relay<'a>),
+}
+
+#[derive(Debug)]
+pub struct Message<'a> {
+    pub kind: u8,
+    pub xid:  u32,
+    pub opts: Vec<Opt<'a>>,
+}
+
+#[derive(Debug)]
+pub struct Relay<'a> {
+    pub kind: u8,
+    pub hops: u8,
+    pub link: Ipv6Addr,
+    pub peer: Ipv6Addr,
+    pub opts: Vec<Opt<'a>>,
+}
+
+#[derive(Debug)]
+pub enum Opt<'a> {
+    ClientId(&'a [u8]),
+    ServerId(&'a [u8]),
+    IaNa(u32, Vec<Addr>),
+    IaPd(u32, Vec<Prefix>),
+    Fqdn(u8, &'a [u8]),
+    RelayMsg(Box<Packet<'a>>),
+    Other(u16, &'a [u8]),
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Addr {
+    pub addr:      Ipv6Addr,
+    pub preferred: u32,
+    pub valid:     u32,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Prefix {
+    pub prefix:    Ipv6Addr,
+    pub len:       u8,
+    pub preferred: u32,
+    pub valid:     u32,
+}
+
+pub const SOLICIT:     u8 = 1;
+pub const ADVERTISE:   u8 = 2;
+pub const REQUEST:     u8 = 3;
+pub const RENEW:       u8 = 5;
+pub const REPLY:       u8 = 7;
+pub const RELEASE:     u8 = 8;
+pub const RELAY_FORW:  u8 = 12;
+pub const RELAY_REPL:  u8 = 13;
+
+// Relays may be nested at most HOP_COUNT_LIMIT deep (RFC 8415, 7.6).
+pub const HOP_COUNT_LIMIT: u8 = 32;
+
+pub fn packet(i: &[u8]) -> IResult<&[u8], Packet> {
+    nested(i, 0)
+}
+
+fn nested(i: &[u8], depth: u8) -> IResult<&[u8], Packet> {
+    switch!(i, peek!(be_u8),
+        RELAY_FORW => map!(call!(relay, depth), Packet::Relay)   |
+        RELAY_REPL => map!(call!(relay, depth), Packet::Relay)   |
+        _          => map!(call!(message, depth), Packet::Message)
+    )
+}
+
+fn message(i: &[u8], depth: u8) -> IResult<&[u8], Message> {
+    do_parse!(i,
+        kind: be_u8
+     >> xid:  be_u24
+     >> opts: many0!(complete!(call!(opt, depth)))
+     >> eof!()
+     >> (Message{
+         kind: kind,
+         xid:  xid,
+         opts: opts,
+        })
+    )
+}
+
+fn relay(i: &[u8], depth: u8) -> IResult<&[u8], Relay> {
+    do_parse!(i,
+        kind: be_u8
+     >> hops: be_u8
+     >> link: ipv6
+     >> peer: ipv6
+     >> opts: many0!(complete!(call!(opt, depth)))
+     >> eof!()
+     >> (Relay{
+         kind: kind,
+         hops: hops,
+         link: link,
+         peer: peer,
+         opts: opts,
+        })
+    )
+}
+
+fn opt(i: &[u8], depth: u8) -> IResult<&[u8], Opt> {
+    do_parse!(i,
+        code: be_u16
+     >> opt:  flat_map!(length_bytes!(be_u16), call!(value, code, depth))
+     >> (opt)
+    )
+}
+
+fn value(i: &[u8], code: u16, depth: u8) -> IResult<&[u8], Opt> {
+    match code {
+        1  => Done(&i[i.len()..], Opt::ClientId(i)),
+        2  => Done(&i[i.len()..], Opt::ServerId(i)),
+        3  => ia_na(i),
+        9  => relay_msg(i, depth + 1),
+        25 => ia_pd(i),
+        39 => fqdn(i),
+        _  => Done(&i[i.len()..], Opt::Other(code, i)),
+    }
+}
+
+fn relay_msg(i: &[u8], depth: u8) -> IResult<&[u8], Opt> {
+    if depth > HOP_COUNT_LIMIT {
+        return IResult::Error(ErrorKind::Custom(0));
+    }
+    map!(i, call!(nested, depth), |p| Opt::RelayMsg(Box::new(p)))
+}
+
+named!(ia_na<&[u8], Opt>, do_parse!(
+    iaid:  be_u32
+ >> take!(8)
+ >> addrs: fold_many0!(complete!(suboption), Vec::new(), addrs)
+ >> (Opt::IaNa(iaid, addrs))
+));
+
+named!(ia_pd<&[u8], Opt>, do_parse!(
+    iaid:     be_u32
+ >> take!(8)
+ >> prefixes: fold_many0!(complete!(suboption), Vec::new(), prefixes)
+ >> (Opt::IaPd(iaid, prefixes))
+));
+
+named!(fqdn<&[u8], Opt>, do_parse!(
+    flags: be_u8
+ >> name:  rest
+ >> (Opt::Fqdn(flags, name))
+));
+
+named!(suboption<&[u8], (u16, &[u8])>, do_parse!(
+    code: be_u16
+ >> data: length_bytes!(be_u16)
+ >> ((code, data))
+));
+
+named!(iaaddr<&[u8], Addr>, do_parse!(
+    addr:      ipv6
+ >> preferred: be_u32
+ >> valid:     be_u32
+ >> (Addr{
+     addr:      addr,
+     preferred: preferred,
+     valid:     valid,
+    })
+));
+
+named!(iaprefix<&[u8], Prefix>, do_parse!(
+    preferred: be_u32
+ >> valid:     be_u32
+ >> len:       be_u8
+ >> prefix:    ipv6
+ >> (Prefix{
+     prefix:    prefix,
+     len:       len,
+     preferred: preferred,
+     valid:     valid,
+    })
+));
+
+named!(ipv6<&[u8], Ipv6Addr>, map!(take!(16), |b| {
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(b);
+    Ipv6Addr::from(octets)
+}));
+
+fn addrs(mut vec: Vec<Addr>, (code, data): (u16, &[u8])) -> Vec<Addr> {
+    if let (5, Done(_, addr)) = (code, iaaddr(data)) {
+        vec.push(addr);
+    }
+    vec
+}
+
+fn prefixes(mut vec: Vec<Prefix>, (code, data): (u16, &[u8])) -> Vec<Prefix> {
+    if let (26, Done(_, prefix)) = (code, iaprefix(data)) {
+        vec.push(prefix);
+    }
+    vec
+}
@@ -1,31 +1,64 @@
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::fmt::Write;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use time::Duration;
 use nom::IResult::Done;
-use super::parser::{self, QQ, Rdata};
+use super::parser::{self, Edns, Header, QQ, Rdata};
 use crate::protocol::buf::Buffer;
 use crate::time::Timestamp;
 
 pub struct Connection {
     buffer: Buffer,
+    server: Buffer,
     last:   Timestamp,
     state:  State,
 }
 
 struct State {
-    pending: HashMap<u16, Timestamp>,
+    pending: HashMap<Question, Pending>,
+}
+
+// Replies are matched on transaction ID and question so that reused
+// IDs don't pair a reply with the wrong query.
+type Question = (u16, String, u16, u16);
+
+struct Pending {
+    sent:        Timestamp,
+    query:       QQ,
+    retransmits: u32,
 }
 
 #[derive(Debug)]
 pub enum Message {
-    Query(QQ),
-    Reply(QQ, u8, String, Duration),
+    Query(QQ, Meta),
+    Reply(QQ, u8, String, Duration, Meta),
+}
+
+// Header flags in dig order, any EDNS parameters and how the message
+// matched up with the other side of the exchange.
+#[derive(Debug)]
+pub struct Meta {
+    pub flags:       String,
+    pub edns:        Option<Edns>,
+    pub rcode:       Option<u16>,
+    pub retransmits: u32,
+    pub unmatched:   Option<Unmatched>,
+}
+
+// Replies without an outstanding query, either because the ID matches
+// a query for a different question or doesn't match at all.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Unmatched {
+    Question,
+    Unsolicited,
 }
 
 impl Connection {
     pub fn new() -> Self {
         Connection {
             buffer: Buffer::new(),
+            server: Buffer::new(),
             last:   Timestamp::zero(),
             state:  State {
                 pending: HashMap::new(),
@@ -52,22 +85,103 @@ impl Connection {
         completed
     }
 
+    // Each direction of a TCP connection is a stream of length-prefixed
+    // messages, returning each one completed in order.
+    pub fn parse_tcp(&mut self, ts: Timestamp, client: bool, buf: &[u8]) -> Vec<Message> {
+        self.last = ts;
+        let state = &mut self.state;
+        let mut buf = match client {
+            true  => self.buffer.buf(buf),
+            false => self.server.buf(buf),
+        };
+        let mut completed = Vec::new();
+
+        let (msgs, rest) = parser::frames(&buf);
+        for msg in msgs {
+            if let Done(_, msg) = parser::parse_message(msg) {
+                completed.extend(state.update(msg, ts));
+            }
+        }
+
+        let remainder = rest.len();
+        buf.keep(remainder);
+
+        completed
+    }
+
+    // Remove and return queries which have gone unanswered for longer
+    // than the window, in the order they were sent.
+    pub fn expire(&mut self, ts: Timestamp, window: Duration) -> Vec<QQ> {
+        let mut expired = Vec::new();
+
+        self.state.pending.retain(|_, pending| {
+            let waiting = ts - pending.sent <= window;
+            if !waiting {
+                expired.push((pending.sent, pending.query.clone()));
+            }
+            waiting
+        });
+
+        expired.sort_by_key(|&(sent, _)| sent);
+        expired.into_iter().map(|(_, query)| query).collect()
+    }
+
     pub fn is_idle(&self, ts: Timestamp, timeout: Duration) -> bool {
-        let idle = self.buffer.is_empty() && self.state.pending.is_empty();
+        let empty = self.buffer.is_empty() && self.server.is_empty();
+        let idle  = empty && self.state.pending.is_empty();
         idle || (ts - self.last) > timeout
     }
 }
 
 impl State {
     fn update(&mut self, mut msg: parser::Message, ts: Timestamp) -> Option<Message> {
+        let edns = msg.edns();
+        let mut meta = Meta {
+            flags:       flags(&msg.header),
+            rcode:       edns.as_ref().map(|edns| edns.rcode(&msg.header)),
+            edns:        edns,
+            retransmits: 0,
+            unmatched:   None,
+        };
+
+        let id = msg.header.id;
+
         if msg.header.qr == 0 {
-            self.pending.insert(msg.header.id, ts);
-            msg.query.pop().map(Message::Query)
+            msg.query.pop().map(|qq| {
+                match self.pending.entry(question(id, &qq)) {
+                    Entry::Occupied(mut e) => {
+                        e.get_mut().retransmits += 1;
+                        meta.retransmits = e.get().retransmits;
+                    },
+                    Entry::Vacant(e) => {
+                        e.insert(Pending {
+                            sent:        ts,
+                            query:       qq.clone(),
+                            retransmits: 0,
+                        });
+                    },
+                }
+                Message::Query(qq, meta)
+            })
         } else {
             let reply_ts = ts;
-            let query_ts = self.pending.remove(&msg.header.id).unwrap_or(reply_ts);
+            let pending  = &mut self.pending;
 
             msg.query.pop().map(|qq| {
+                let query_ts = match pending.remove(&question(id, &qq)) {
+                    Some(query) => {
+                        meta.retransmits = query.retransmits;
+                        query.sent
+                    },
+                    None => {
+                        meta.unmatched = match pending.keys().any(|q| q.0 == id) {
+                            true  => Some(Unmatched::Question),
+                            false => Some(Unmatched::Unsolicited),
+                        };
+                        reply_ts
+                    },
+                };
+
                 let mut s = String::new();
                 for (i, rr) in msg.answer.iter().enumerate() {
                     if i > 0 {
@@ -82,12 +196,115 @@ impl State {
                         Rdata::Mx(_, ref mx) => write!(&mut s, "{}/MX", mx),
                         Rdata::Ns(ref ns)    => write!(&mut s, "{}/NS", ns),
                         Rdata::Txt(ref txt)  => write!(&mut s, "{}/TXT", txt.join("")),
+                        Rdata::Srv{priority, weight, port, ref target} => {
+                            write!(&mut s, "{} {} {} {}/SRV", priority, weight, port, target)
+                        },
+                        Rdata::Caa{flags, tag, value} => {
+                            let tag   = String::from_utf8_lossy(tag);
+                            let value = String::from_utf8_lossy(value);
+                            write!(&mut s, "{} {} {}/CAA", flags, tag, value)
+                        },
+                        Rdata::Svcb{priority, ref target, ref params} => {
+                            let kind = match rr.rtype {
+                                64 => "SVCB",
+                                _  => "HTTPS",
+                            };
+                            write!(&mut s, "{} {}", priority, root(target)).and_then(|_| {
+                                params.iter().try_for_each(|&(key, value)| {
+                                    write!(&mut s, " {}", param(key, value))
+                                })
+                            }).and_then(|_| write!(&mut s, "/{}", kind))
+                        },
+                        Rdata::Ds{key_tag, algorithm, digest_type, digest} => {
+                            write!(&mut s, "{} {} {} {}/DS", key_tag, algorithm, digest_type, hex(digest))
+                        },
+                        Rdata::Dnskey{flags, protocol, algorithm, ..} => {
+                            write!(&mut s, "{} {} {}/DNSKEY", flags, protocol, algorithm)
+                        },
+                        Rdata::Rrsig{covered, algorithm, key_tag, ref signer, ..} => {
+                            write!(&mut s, "{} {} {} {}/RRSIG", covered, algorithm, key_tag, root(signer))
+                        },
+                        Rdata::Opt(..)       => Ok(()),
                         Rdata::Other(..)     => Ok(()),
                         Rdata::Soa{..}       => Ok(()),
                     }.expect("failed decoding DNS reply");
                 }
-                Message::Reply(qq, msg.header.rcode, s, reply_ts - query_ts)
+                Message::Reply(qq, msg.header.rcode, s, reply_ts - query_ts, meta)
             })
         }
     }
 }
+
+impl Unmatched {
+    pub fn id(&self) -> u32 {
+        match self {
+            Unmatched::Question    => 1,
+            Unmatched::Unsolicited => 2,
+        }
+    }
+}
+
+// Names are compared case-insensitively since resolvers may randomize
+// the case of a query name (draft-vixie-dnsext-dns0x20).
+fn question(id: u16, qq: &QQ) -> Question {
+    (id, qq.qname.to_ascii_lowercase(), qq.qtype, qq.qclass)
+}
+
+fn flags(header: &Header) -> String {
+    let flags = [
+        (header.qr == 1, "qr"),
+        (header.aa,      "aa"),
+        (header.tc,      "tc"),
+        (header.rd,      "rd"),
+        (header.ra,      "ra"),
+        (header.ad,      "ad"),
+        (header.cd,      "cd"),
+    ];
+
+    flags.iter().filter(|f| f.0).map(|f| f.1).collect::<Vec<_>>().join(" ")
+}
+
+// SvcParams (RFC 9460 section 7) in presentation format.
+fn param(key: u16, value: &[u8]) -> String {
+    match key {
+        1 => {
+            let mut alpn = Vec::new();
+            let mut rest = value;
+            while let Some((&len, tail)) = rest.split_first() {
+                let len = (len as usize).min(tail.len());
+                alpn.push(String::from_utf8_lossy(&tail[..len]).into_owned());
+                rest = &tail[len..];
+            }
+            format!("alpn={}", alpn.join(","))
+        },
+        2 => "no-default-alpn".to_owned(),
+        3 if value.len() == 2 => format!("port={}", (value[0] as u16) << 8 | value[1] as u16),
+        4 => {
+            let ips = value.chunks_exact(4).map(|b| {
+                Ipv4Addr::new(b[0], b[1], b[2], b[3]).to_string()
+            }).collect::<Vec<_>>();
+            format!("ipv4hint={}", ips.join(","))
+        },
+        5 => "ech".to_owned(),
+        6 => {
+            let ips = value.chunks_exact(16).map(|b| {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(b);
+                Ipv6Addr::from(octets).to_string()
+            }).collect::<Vec<_>>();
+            format!("ipv6hint={}", ips.join(","))
+        },
+        n => format!("key{}", n),
+    }
+}
+
+fn root(name: &str) -> &str {
+    match name {
+        "" => ".",
+        n  => n,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
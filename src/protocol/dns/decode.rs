@@ -1,10 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::ffi::CString;
+use std::net::IpAddr;
 use time::Duration;
-use crate::flow::{Addr, Flow};
+use crate::flow::{Addr, Flow, Key, Transport};
 use crate::custom::*;
 use crate::time::Timestamp;
-use super::conn::{Connection, Message};
+use super::conn::{Connection, Message, Meta};
+use super::parser::QQ;
 
 pub struct Decoder {
     query_name: u64,
@@ -12,12 +14,48 @@ pub struct Decoder {
     reply_code: u64,
     reply_data: u64,
     latency:    u64,
+    flags:      Option<u64>,
+    edns_size:  Option<u64>,
+    dnssec_ok:  Option<u64>,
+    ext_rcode:  Option<u64>,
+    ecs_addr:   Option<u64>,
+    ecs_prefix: Option<u64>,
+    resolver:   Option<u64>,
+    servfails:  Option<u64>,
+    nxdomains:  Option<u64>,
+    timeouts:   Option<u64>,
+    retransmit: Option<u64>,
+    unmatched:  Option<u64>,
+    window:     Duration,
     name_str:   Option<CString>,
     data_str:   Option<CString>,
+    flags_str:  Option<CString>,
     empty:      CString,
     conns:      HashMap<(Addr, Addr), Connection>,
+    resolvers:  HashMap<IpAddr, Stats>,
+    pending:    VecDeque<Record>,
 }
 
+// Records waiting to be exported along with the resolver involved.
+enum Record {
+    Message(IpAddr, Message),
+    Timeout(IpAddr, QQ),
+}
+
+// Failures seen from a resolver since its counts were last exported.
+#[derive(Default)]
+struct Stats {
+    servfail: u32,
+    nxdomain: u32,
+    timeout:  u32,
+}
+
+// Reply code exported for queries which were never answered.
+pub const TIMED_OUT: u32 = 0xFFFF;
+
+const SERVFAIL: u8 = 2;
+const NXDOMAIN: u8 = 3;
+
 impl Decoder {
     pub fn new(cs: &Customs) -> Result<Decoder, ()> {
         Ok(Decoder{
@@ -26,47 +64,201 @@ impl Decoder {
             reply_code: cs.get(DNS_REPLY_CODE)?,
             reply_data: cs.get(DNS_REPLY_DATA)?,
             latency:    cs.get(APP_LATENCY)?,
+            flags:      cs.get(DNS_FLAGS).ok(),
+            edns_size:  cs.get(DNS_EDNS_SIZE).ok(),
+            dnssec_ok:  cs.get(DNS_EDNS_DO).ok(),
+            ext_rcode:  cs.get(DNS_EXT_RCODE).ok(),
+            ecs_addr:   cs.get(DNS_ECS_ADDR).ok(),
+            ecs_prefix: cs.get(DNS_ECS_PREFIX).ok(),
+            resolver:   cs.get(DNS_RESOLVER).ok(),
+            servfails:  cs.get(DNS_SERVFAIL_COUNT).ok(),
+            nxdomains:  cs.get(DNS_NXDOMAIN_COUNT).ok(),
+            timeouts:   cs.get(DNS_TIMEOUT_COUNT).ok(),
+            retransmit: cs.get(DNS_RETRANSMITS).ok(),
+            unmatched:  cs.get(DNS_UNMATCHED).ok(),
+            window:     Duration::seconds(5),
             name_str:   None,
             data_str:   None,
+            flags_str:  None,
             empty:      Default::default(),
             conns:      HashMap::new(),
+            resolvers:  HashMap::new(),
+            pending:    VecDeque::new(),
         })
     }
 
+    pub fn timeout(&mut self, window: Duration) {
+        self.window = window;
+    }
+
     pub fn decode(&mut self, flow: &Flow, cs: &mut Customs) -> bool {
         //println!("extracting DNS flow from {:?}", flow);
-        self.parse(flow).map(move |msg| {
-            match msg {
-                Message::Query(qq) => {
-                    self.name_str = CString::new(qq.qname).ok();
-                    cs.add_str(self.query_name, self.name_str.as_ref().unwrap_or(&self.empty));
-                    cs.add_u32(self.query_type, qq.qtype as u32);
-                    true
-                },
-                Message::Reply(qq, rc, data, d) => {
-                    self.name_str = CString::new(qq.qname).ok();
-                    self.data_str = CString::new(data).ok();
-                    cs.add_str(self.query_name, self.name_str.as_ref().unwrap_or(&self.empty));
-                    cs.add_u32(self.query_type, qq.qtype as u32);
-                    cs.add_u32(self.reply_code, rc as u32);
-                    cs.add_str(self.reply_data, self.data_str.as_ref().unwrap_or(&self.empty));
-                    cs.add_u32(self.latency, d.whole_milliseconds() as u32);
-                    true
-                },
+        let addr = match endpoints(flow.src, flow.dst) {
+            Some(addr) => addr,
+            None       => return false,
+        };
+        let server = addr.1.addr;
+        self.pending = self.parse(flow, addr).into_iter().map(|msg| Record::Message(server, msg)).collect();
+        self.next(cs)
+    }
+
+    // Messages completed by the same segment of a TCP stream, and
+    // queries which timed out together, are exported one per record.
+    pub fn next(&mut self, cs: &mut Customs) -> bool {
+        match self.pending.pop_front() {
+            Some(Record::Message(server, msg)) => self.message(server, msg, cs),
+            Some(Record::Timeout(server, qq))  => self.timed_out(server, qq, cs),
+            None                               => false,
+        }
+    }
+
+    // Export each query from the client to resolver flow which has
+    // gone unanswered for longer than the window.
+    pub fn expire(&mut self, key: &Key, ts: Timestamp, cs: &mut Customs) -> bool {
+        let addr = match endpoints(key.1, key.2) {
+            Some((client, server)) if client == key.1 => (client, server),
+            _                                         => return false,
+        };
+
+        let expired = match self.conns.get_mut(&addr) {
+            Some(conn) => conn.expire(ts, self.window),
+            None       => return false,
+        };
+
+        if !expired.is_empty() {
+            let stats = self.resolvers.entry(addr.1.addr).or_default();
+            stats.timeout += expired.len() as u32;
+        }
+
+        let server = addr.1.addr;
+        self.pending = expired.into_iter().map(|qq| Record::Timeout(server, qq)).collect();
+        self.next(cs)
+    }
+
+    // Resolver failure counts are exported with the next client to
+    // resolver flow and then reset.
+    pub fn append(&mut self, key: &Key, cs: &mut Customs) {
+        let server = match endpoints(key.1, key.2) {
+            Some((client, server)) if client == key.1 => server,
+            _                                         => return,
+        };
+
+        if let Some(stats) = self.resolvers.remove(&server.addr) {
+            let counts = [
+                (self.servfails, stats.servfail),
+                (self.nxdomains, stats.nxdomain),
+                (self.timeouts,  stats.timeout),
+            ];
+
+            for &(id, count) in &counts {
+                if let Some(id) = id {
+                    cs.add_u32(id, count);
+                }
             }
-        }).unwrap_or(false)
+        }
     }
 
     pub fn clear(&mut self, ts: Timestamp, timeout: Duration) {
         self.conns.retain(|_, c| !c.is_idle(ts, timeout))
     }
 
-    fn parse(&mut self, flow: &Flow) -> Option<Message> {
-        let addr = match (flow.src, flow.dst) {
-            (src, dst) if dst.port == 53 => (src, dst),
-            (src, dst) if src.port == 53 => (dst, src),
-            _                            => unreachable!(),
+    fn message(&mut self, server: IpAddr, msg: Message, cs: &mut Customs) -> bool {
+        match msg {
+            Message::Query(qq, meta) => {
+                self.name_str = CString::new(qq.qname).ok();
+                cs.add_str(self.query_name, self.name_str.as_ref().unwrap_or(&self.empty));
+                cs.add_u32(self.query_type, qq.qtype as u32);
+                self.meta(meta, cs);
+                true
+            },
+            Message::Reply(qq, rc, data, d, meta) => {
+                if rc == SERVFAIL || rc == NXDOMAIN {
+                    let stats = self.resolvers.entry(server).or_default();
+                    stats.servfail += (rc == SERVFAIL) as u32;
+                    stats.nxdomain += (rc == NXDOMAIN) as u32;
+                }
+
+                self.name_str = CString::new(qq.qname).ok();
+                self.data_str = CString::new(data).ok();
+                cs.add_str(self.query_name, self.name_str.as_ref().unwrap_or(&self.empty));
+                cs.add_u32(self.query_type, qq.qtype as u32);
+                cs.add_u32(self.reply_code, rc as u32);
+                cs.add_str(self.reply_data, self.data_str.as_ref().unwrap_or(&self.empty));
+                cs.add_u32(self.latency, d.whole_milliseconds() as u32);
+                if let (Some(id), Some(rcode)) = (self.ext_rcode, meta.rcode) {
+                    cs.add_u32(id, rcode as u32);
+                }
+                self.meta(meta, cs);
+                true
+            },
+        }
+    }
+
+    fn timed_out(&mut self, server: IpAddr, qq: QQ, cs: &mut Customs) -> bool {
+        self.name_str = CString::new(qq.qname).ok();
+        cs.add_str(self.query_name, self.name_str.as_ref().unwrap_or(&self.empty));
+        cs.add_u32(self.query_type, qq.qtype as u32);
+        cs.add_u32(self.reply_code, TIMED_OUT);
+        if let Some(id) = self.resolver {
+            cs.add_addr(id, server);
+        }
+        true
+    }
+
+    fn meta(&mut self, meta: Meta, cs: &mut Customs) {
+        if let Some(id) = self.flags {
+            self.flags_str = CString::new(meta.flags).ok();
+            cs.add_str(id, self.flags_str.as_ref().unwrap_or(&self.empty));
+        }
+
+        if let Some(id) = self.retransmit {
+            if meta.retransmits > 0 {
+                cs.add_u32(id, meta.retransmits);
+            }
+        }
+
+        if let (Some(id), Some(unmatched)) = (self.unmatched, meta.unmatched) {
+            cs.add_u32(id, unmatched.id());
+        }
+
+        let edns = match meta.edns {
+            Some(edns) => edns,
+            None       => return,
         };
-        self.conns.entry(addr).or_insert_with(Connection::new).parse(flow.timestamp, flow.payload)
+
+        if let Some(id) = self.edns_size {
+            cs.add_u32(id, edns.size as u32);
+        }
+
+        if let Some(id) = self.dnssec_ok {
+            cs.add_u32(id, edns.dnssec as u32);
+        }
+
+        if let Some(ecs) = edns.ecs {
+            if let Some(id) = self.ecs_addr {
+                cs.add_addr(id, ecs.addr);
+            }
+            if let Some(id) = self.ecs_prefix {
+                cs.add_u32(id, ecs.source as u32);
+            }
+        }
+    }
+
+    fn parse(&mut self, flow: &Flow, addr: (Addr, Addr)) -> Vec<Message> {
+        let conn = self.conns.entry(addr).or_insert_with(Connection::new);
+
+        match flow.transport {
+            Transport::TCP{..} => conn.parse_tcp(flow.timestamp, flow.src == addr.0, flow.payload),
+            _                  => conn.parse(flow.timestamp, flow.payload).into_iter().collect(),
+        }
+    }
+}
+
+// Client and server addresses of a DNS flow in either direction.
+fn endpoints(src: Addr, dst: Addr) -> Option<(Addr, Addr)> {
+    match (src, dst) {
+        (src, dst) if dst.port == 53 => Some((src, dst)),
+        (src, dst) if src.port == 53 => Some((dst, src)),
+        _                            => None,
     }
 }
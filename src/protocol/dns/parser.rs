@@ -1,6 +1,6 @@
 #![allow(unused_variables)]
 
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::str;
 use nom::*;
 use nom::IResult::Done;
@@ -23,6 +23,8 @@ pub struct Header {
     pub tc:      bool,
     pub rd:      bool,
     pub ra:      bool,
+    pub ad:      bool,
+    pub cd:      bool,
     pub rcode:   u8,
     pub qdcount: u16,
     pub ancount: u16,
@@ -30,7 +32,7 @@ pub struct Header {
     pub arcount: u16,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct QQ {
     pub qname:  String,
     pub qtype:  u16,
@@ -55,8 +57,54 @@ pub enum Rdata<'a> {
     Mx(u16, String),
     Ns(String),
     Txt(Vec<&'a str>),
+    Opt(Vec<(u16, &'a [u8])>),
     Other(&'a [u8]),
 
+    Srv {
+        priority: u16,
+        weight:   u16,
+        port:     u16,
+        target:   String,
+    },
+
+    Caa {
+        flags: u8,
+        tag:   &'a [u8],
+        value: &'a [u8],
+    },
+
+    Svcb {
+        priority: u16,
+        target:   String,
+        params:   Vec<(u16, &'a [u8])>,
+    },
+
+    Ds {
+        key_tag:     u16,
+        algorithm:   u8,
+        digest_type: u8,
+        digest:      &'a [u8],
+    },
+
+    Dnskey {
+        flags:     u16,
+        protocol:  u8,
+        algorithm: u8,
+        key:       &'a [u8],
+    },
+
+    Rrsig {
+        covered:    u16,
+        algorithm:  u8,
+        labels:     u8,
+        ttl:        u32,
+        expiration: u32,
+        inception:  u32,
+        key_tag:    u16,
+        signer:     String,
+        signature:  &'a [u8],
+    },
+
     Soa {
         mname:   String,
         rname:   String,
@@ -68,10 +116,96 @@ pub enum Rdata<'a> {
     },
 }
 
+// EDNS(0) parameters carried by an OPT pseudo-RR (RFC 6891).
+#[derive(Debug)]
+pub struct Edns {
+    pub size:    u16,
+    pub rcode:   u8,
+    pub version: u8,
+    pub dnssec:  bool,
+    pub ecs:     Option<Ecs>,
+}
+
+// EDNS Client Subnet option (RFC 7871).
+#[derive(Debug)]
+pub struct Ecs {
+    pub addr:   IpAddr,
+    pub source: u8,
+    pub scope:  u8,
+}
+
+const ECS: u16 = 8;
+
+impl<'a> Message<'a> {
+    pub fn edns(&self) -> Option<Edns> {
+        self.additional.iter().find_map(|rr| match rr.rdata {
+            Rdata::Opt(ref options) => Some(Edns {
+                size:    rr.class,
+                rcode:   (rr.ttl >> 24) as u8,
+                version: (rr.ttl >> 16) as u8,
+                dnssec:  rr.ttl & 0x8000 != 0,
+                ecs:     options.iter().find(|o| o.0 == ECS).and_then(|o| ecs(o.1)),
+            }),
+            _ => None,
+        })
+    }
+}
+
+impl Edns {
+    // Full 12-bit RCODE, combining the header's lower 4 bits.
+    pub fn rcode(&self, header: &Header) -> u16 {
+        (self.rcode as u16) << 4 | header.rcode as u16
+    }
+}
+
+fn ecs(buf: &[u8]) -> Option<Ecs> {
+    let family = (*buf.get(0)? as u16) << 8 | *buf.get(1)? as u16;
+    let source = *buf.get(2)?;
+    let scope  = *buf.get(3)?;
+    let addr   = &buf[4..];
+
+    let addr = match family {
+        1 if addr.len() <= 4 => {
+            let mut octets = [0u8; 4];
+            octets[..addr.len()].copy_from_slice(addr);
+            IpAddr::V4(Ipv4Addr::from(octets))
+        },
+        2 if addr.len() <= 16 => {
+            let mut octets = [0u8; 16];
+            octets[..addr.len()].copy_from_slice(addr);
+            IpAddr::V6(Ipv6Addr::from(octets))
+        },
+        _ => return None,
+    };
+
+    Some(Ecs {
+        addr:   addr,
+        source: source,
+        scope:  scope,
+    })
+}
+
 pub fn parse_message(buf: &[u8]) -> IResult<&[u8], Message> {
     message(buf, buf)
 }
 
+// DNS over TCP prefixes each message with a 2-byte length (RFC 1035
+// section 4.2.2). Returns the complete messages and the remainder.
+pub fn frames(mut buf: &[u8]) -> (Vec<&[u8]>, &[u8]) {
+    let mut msgs = Vec::new();
+
+    while buf.len() >= 2 {
+        let len = (buf[0] as usize) << 8 | buf[1] as usize;
+        if buf.len() < len + 2 {
+            break;
+        }
+        msgs.push(&buf[2..len + 2]);
+        buf = &buf[len + 2..];
+    }
+
+    (msgs, buf)
+}
+
 const MAX_POINTER_DEPTH: u16 = 32;
 
 knamed_args!(message<'a>(msg: &'a [u8]) <Message<'a>>, do_parse!(
@@ -112,6 +246,8 @@ named!(header<&[u8], Header>, do_parse!(
      tc:      bits.3 == 1,
      rd:      bits.4 == 1,
      ra:      bits.5 == 1,
+     ad:      bits.6 & 0b010 != 0,
+     cd:      bits.6 & 0b001 != 0,
      rcode:   bits.7,
      qdcount: qdcount,
      ancount: ancount,
@@ -148,15 +284,23 @@ knamed_args!(rr<'a>(msg: &'a [u8]) <RR<'a>>, do_parse!(
 
 fn rdata<'a>(buf: &'a [u8], msg: &'a [u8], rtype: u16) -> IResult<&'a [u8], Rdata<'a>> {
     match rtype {
-        1  => rdata_a(buf),
-        2  => rdata_ns(buf, msg),
-        5  => rdata_cname(buf, msg),
-        6  => rdata_soa(buf, msg),
-        12 => rdata_ptr(buf, msg),
-        15 => rdata_mx(buf, msg),
-        16 => rdata_txt(buf),
-        28 => rdata_aaaa(buf),
-        _  => rdata_other(buf)
+        1   => rdata_a(buf),
+        2   => rdata_ns(buf, msg),
+        5   => rdata_cname(buf, msg),
+        6   => rdata_soa(buf, msg),
+        12  => rdata_ptr(buf, msg),
+        15  => rdata_mx(buf, msg),
+        16  => rdata_txt(buf),
+        28  => rdata_aaaa(buf),
+        33  => rdata_srv(buf, msg),
+        41  => rdata_opt(buf),
+        43  => rdata_ds(buf),
+        46  => rdata_rrsig(buf, msg),
+        48  => rdata_dnskey(buf),
+        64  => rdata_svcb(buf, msg),
+        65  => rdata_svcb(buf, msg),
+        257 => rdata_caa(buf),
+        _   => rdata_other(buf)
     }
 }
 
@@ -217,6 +361,125 @@ knamed_args!(rdata_soa<'a>(msg: &'a [u8]) <Rdata<'a>>, do_parse!(
  })
 ));
 
+knamed_args!(rdata_srv<'a>(msg: &'a [u8]) <Rdata<'a>>, do_parse!(
+    be_u16
+ >> priority: be_u16
+ >> weight:   be_u16
+ >> port:     be_u16
+ >> target:   call!(name, msg)
+ >> (Rdata::Srv {
+     priority: priority,
+     weight:   weight,
+     port:     port,
+     target:   target,
+ })
+));
+
+knamed_args!(rdata_opt<'a>() <Rdata<'a>>, do_parse!(
+    len:     be_u16
+ >> options: flat_map!(take!(len), many0!(option))
+ >> (Rdata::Opt(options))
+));
+
+knamed_args!(rdata_ds<'a>() <Rdata<'a>>, do_parse!(
+    len: be_u16
+ >> ds:  flat_map!(take!(len), do_parse!(
+         key_tag:     be_u16
+      >> algorithm:   be_u8
+      >> digest_type: be_u8
+      >> digest:      rest
+      >> (Rdata::Ds {
+          key_tag:     key_tag,
+          algorithm:   algorithm,
+          digest_type: digest_type,
+          digest:      digest,
+      })
+     ))
+ >> (ds)
+));
+
+knamed_args!(rdata_dnskey<'a>() <Rdata<'a>>, do_parse!(
+    len: be_u16
+ >> key: flat_map!(take!(len), do_parse!(
+         flags:     be_u16
+      >> protocol:  be_u8
+      >> algorithm: be_u8
+      >> key:       rest
+      >> (Rdata::Dnskey {
+          flags:     flags,
+          protocol:  protocol,
+          algorithm: algorithm,
+          key:       key,
+      })
+     ))
+ >> (key)
+));
+
+knamed_args!(rdata_rrsig<'a>(msg: &'a [u8]) <Rdata<'a>>, do_parse!(
+    len: be_u16
+ >> sig: flat_map!(take!(len), do_parse!(
+         covered:    be_u16
+      >> algorithm:  be_u8
+      >> labels:     be_u8
+      >> ttl:        be_u32
+      >> expiration: be_u32
+      >> inception:  be_u32
+      >> key_tag:    be_u16
+      >> signer:     call!(name, msg)
+      >> signature:  rest
+      >> (Rdata::Rrsig {
+          covered:    covered,
+          algorithm:  algorithm,
+          labels:     labels,
+          ttl:        ttl,
+          expiration: expiration,
+          inception:  inception,
+          key_tag:    key_tag,
+          signer:     signer,
+          signature:  signature,
+      })
+     ))
+ >> (sig)
+));
+
+knamed_args!(rdata_svcb<'a>(msg: &'a [u8]) <Rdata<'a>>, do_parse!(
+    len:  be_u16
+ >> svcb: flat_map!(take!(len), do_parse!(
+         priority: be_u16
+      >> target:   call!(name, msg)
+      >> params:   many0!(option)
+      >> (Rdata::Svcb {
+          priority: priority,
+          target:   target,
+          params:   params,
+      })
+     ))
+ >> (svcb)
+));
+
+knamed_args!(rdata_caa<'a>() <Rdata<'a>>, do_parse!(
+    len: be_u16
+ >> caa: flat_map!(take!(len), do_parse!(
+         flags: be_u8
+      >> tag:   length_bytes!(be_u8)
+      >> value: rest
+      >> (Rdata::Caa {
+          flags: flags,
+          tag:   tag,
+          value: value,
+      })
+     ))
+ >> (caa)
+));
+
+// EDNS options and SVCB parameters share the same key, length,
+// value encoding.
+named!(option<&[u8], (u16, &[u8])>, do_parse!(
+    code: be_u16
+ >> data: length_bytes!(be_u16)
+ >> ((code, data))
+));
+
 knamed_args!(rdata_other<'a>() <Rdata<'a>>, do_parse!(
     len: be_u16
  >> buf: take!(len)
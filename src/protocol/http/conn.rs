@@ -1,11 +1,14 @@
 use std::collections::VecDeque;
 use std::ffi::CString;
-use std::slice;
+use std::mem;
+use std::net::IpAddr;
+use std::sync::Arc;
 use http_muncher::{Parser, ParserHandler};
 use time::Duration;
 use crate::flow::Flow;
 use crate::time::Timestamp;
 use crate::protocol::buf::Buffer;
+use super::h2::{self, PREFACE};
 
 pub struct Connection {
     server:    u16,
@@ -14,27 +17,50 @@ pub struct Connection {
     last:      Timestamp,
     pending:   VecDeque<Req>,
     response:  Option<Res>,
+    capture:   Arc<Vec<Vec<u8>>>,
+    h2:        Option<h2::Connection>,
 }
 
 #[derive(Debug)]
 pub struct Req {
+    pub method:   Option<CString>,
+    pub version:  Option<CString>,
     pub url:      Option<CString>,
     pub host:     Option<CString>,
     pub referer:  Option<CString>,
     pub ua:       Option<CString>,
+    pub client:   Option<IpAddr>,
+    pub length:   u64,
+    pub headers:  Vec<(usize, CString)>,
+    pub h2c:      bool,
     pub ts:       Timestamp,
 }
 
 #[derive(Debug)]
 pub struct Res {
     pub status:   u16,
+    pub method:   Option<CString>,
+    pub version:  Option<CString>,
     pub url:      Option<CString>,
     pub host:     Option<CString>,
     pub referer:  Option<CString>,
     pub ua:       Option<CString>,
+    pub client:   Option<IpAddr>,
+    pub req_len:  Option<u64>,
+    pub res_len:  Option<u64>,
+    pub content:  Option<CString>,
+    pub headers:  Vec<(usize, CString)>,
+    pub grpc:     Option<Grpc>,
     pub latency:  Duration,
 }
 
+#[derive(Debug)]
+pub struct Grpc {
+    pub service:  CString,
+    pub method:   CString,
+    pub status:   Option<u32>,
+}
+
 struct ReqState {
     buffer: Buffer,
     parser: Parser,
@@ -54,8 +80,18 @@ struct State {
     host:     Option<CString>,
     referer:  Option<CString>,
     ua:       Option<CString>,
+    h2c:      bool,
     status:   u16,
-    header:   Option<Header>,
+    client:   Option<IpAddr>,
+    length:   Option<u64>,
+    body:     u64,
+    content:  Option<CString>,
+    headers:  Vec<(usize, CString)>,
+    capture:  Arc<Vec<Vec<u8>>>,
+    target:   Vec<u8>,
+    name:     Vec<u8>,
+    value:    Vec<u8>,
+    in_value: bool,
     complete: bool,
 }
 
@@ -64,19 +100,33 @@ enum Header {
     Host,
     Referer,
     UserAgent,
+    Upgrade,
+    ContentLength,
+    ContentType,
+    XForwardedFor,
+    Forwarded,
+    Capture(usize),
 }
 
 type Result<T> = ::std::result::Result<Option<T>, String>;
 
 impl Connection {
     pub fn new(server: u16) -> Self {
+        Self::with_headers(server, Default::default())
+    }
+
+    // Connection which also captures the named request and response
+    // headers, which must be lowercase.
+    pub fn with_headers(server: u16, headers: Arc<Vec<Vec<u8>>>) -> Self {
         Connection {
             server:    server,
-            req_state: ReqState::new(),
-            res_state: ResState::new(),
+            req_state: ReqState::new(headers.clone()),
+            res_state: ResState::new(headers.clone()),
             last:      Timestamp::zero(),
             pending:   VecDeque::new(),
             response:  None,
+            capture:   headers,
+            h2:        None,
         }
     }
 
@@ -84,6 +134,27 @@ impl Connection {
         flow.dst.port == self.server
     }
 
+    // HTTP/2 with prior knowledge starts with the client preface,
+    // otherwise it follows a successful Upgrade: h2c.
+    pub fn is_h2(&self, flow: &Flow) -> bool {
+        self.h2.is_some() || self.is_client(flow) && flow.payload.starts_with(PREFACE)
+    }
+
+    // Every stream completed by a server segment is returned, in the
+    // order each finished.
+    pub fn parse_h2(&mut self, ts: Timestamp, client: bool, buf: &[u8]) -> Vec<Res> {
+        self.last = ts;
+
+        let capture = &self.capture;
+        let conn = self.h2.get_or_insert_with(|| h2::Connection::new(capture.clone()));
+        if client {
+            conn.client_msg(ts, buf);
+            return Vec::new();
+        }
+
+        conn.server_msg(ts, buf)
+    }
+
     pub fn parse_req(&mut self, ts: Timestamp, buf: &[u8]) -> Option<&Req> {
         self.last = ts;
         self.req_state.parse(buf, ts).unwrap_or_else(|_err| {
@@ -105,17 +176,39 @@ impl Connection {
             self.res_state.buffer.clear();
             self.pending.clear();
             None
-        }).and_then(move |status| {
-            self.response = self.pending.pop_front().map(move |req| {
-                let latency = ts - req.ts;
-                Res{
-                    status:  status,
-                    url:     req.url,
-                    host:    req.host,
-                    referer: req.referer,
-                    ua:      req.ua,
-                    latency: latency,
-                }
+        }).and_then(move |res| {
+            let mut req = self.pending.pop_front()?;
+            let status = res.status;
+
+            if status == 101 && req.h2c {
+                let mut stream = h2::Stream::new(req.ts);
+                stream.path      = req.url;
+                stream.authority = req.host;
+                stream.referer   = req.referer;
+                stream.ua        = req.ua;
+                stream.req_len   = Some(req.length);
+                stream.headers   = req.headers;
+                self.h2 = Some(h2::Connection::upgrade(stream, self.capture.clone()));
+                return None;
+            }
+
+            req.headers.extend(res.headers);
+
+            self.response = Some(Res{
+                status:  status,
+                method:  req.method,
+                version: req.version,
+                url:     req.url,
+                host:    req.host,
+                referer: req.referer,
+                ua:      req.ua,
+                client:  req.client,
+                req_len: Some(req.length),
+                res_len: Some(res.length.unwrap_or(res.body)),
+                content: res.content,
+                headers: req.headers,
+                grpc:    None,
+                latency: ts - req.ts,
             });
             self.response.as_ref()
         })
@@ -127,12 +220,12 @@ impl Connection {
 }
 
 impl ReqState {
-    pub fn new() -> Self {
+    pub fn new(headers: Arc<Vec<Vec<u8>>>) -> Self {
         Self {
             buffer: Buffer::new(),
             parser: Parser::request(),
             ts:     None,
-            state:  Default::default(),
+            state:  State::new(headers),
         }
     }
 
@@ -153,14 +246,21 @@ impl ReqState {
         buf.keep(len);
 
         if self.state.complete {
+            let state = self.state.reset();
+            let (major, minor) = self.parser.http_version();
             req = Some(Req{
-                url:     self.state.url.take(),
-                host:    self.state.host.take(),
-                referer: self.state.referer.take(),
-                ua:      self.state.ua.take(),
+                method:  CString::new(self.parser.http_method()).ok(),
+                version: CString::new(format!("{}.{}", major, minor)).ok(),
+                url:     state.url,
+                host:    state.host,
+                referer: state.referer,
+                ua:      state.ua,
+                client:  state.client,
+                length:  state.length.unwrap_or(state.body),
+                headers: state.headers,
+                h2c:     state.h2c,
                 ts:      self.ts.take().unwrap(),
             });
-            self.state.complete = false;
             self.parser = Parser::request();
         }
         //println!("req buffer {:p} currently {:?} bytes", &buf, buf.len());
@@ -170,15 +270,15 @@ impl ReqState {
 }
 
 impl ResState {
-    pub fn new() -> Self {
+    pub fn new(headers: Arc<Vec<Vec<u8>>>) -> Self {
         Self {
             buffer: Buffer::new(),
             parser: Parser::response(),
-            state:  Default::default(),
+            state:  State::new(headers),
         }
     }
 
-    pub fn parse(&mut self, buf: &[u8]) -> Result<u16> {
+    fn parse(&mut self, buf: &[u8]) -> Result<State> {
         let mut buf = self.buffer.buf(buf);
         let mut len = buf.len();
         let mut res = None;
@@ -191,8 +291,9 @@ impl ResState {
         buf.keep(len);
 
         if self.state.complete {
-            res = Some(self.parser.status_code());
-            self.state.complete = false;
+            let mut state = self.state.reset();
+            state.status = self.parser.status_code();
+            res = Some(state);
             self.parser = Parser::response();
         }
         //println!("res buffer {:p} currently {:?} bytes", &buf, buf.len());
@@ -201,38 +302,90 @@ impl ResState {
     }
 }
 
+impl State {
+    fn new(capture: Arc<Vec<Vec<u8>>>) -> Self {
+        State {
+            capture: capture,
+            ..Default::default()
+        }
+    }
+
+    // Take the completed message, leaving a new state for the next.
+    fn reset(&mut self) -> State {
+        let capture = self.capture.clone();
+        mem::replace(self, State::new(capture))
+    }
+}
+
+impl State {
+    // Dispatch a complete header, whose name and value may have been
+    // delivered across several callbacks.
+    fn header(&mut self) {
+        self.name.make_ascii_lowercase();
+
+        let header = match &self.name[..] {
+            b"host"            => Some(Header::Host),
+            b"referer"         => Some(Header::Referer),
+            b"user-agent"      => Some(Header::UserAgent),
+            b"upgrade"         => Some(Header::Upgrade),
+            b"content-length"  => Some(Header::ContentLength),
+            b"content-type"    => Some(Header::ContentType),
+            b"x-forwarded-for" => Some(Header::XForwardedFor),
+            b"forwarded"       => Some(Header::Forwarded),
+            name               => self.capture.iter().position(|h| h == name).map(Header::Capture),
+        };
+
+        let value = mem::take(&mut self.value);
+        match header {
+            Some(Header::Host)          => self.host = CString::new(value).ok(),
+            Some(Header::Referer)       => self.referer = CString::new(value).ok(),
+            Some(Header::UserAgent)     => self.ua = CString::new(value).ok(),
+            Some(Header::Upgrade)       => self.h2c = value.eq_ignore_ascii_case(b"h2c"),
+            Some(Header::ContentLength) => self.length = number(&value),
+            Some(Header::ContentType)   => self.content = CString::new(value).ok(),
+            Some(Header::XForwardedFor) => self.client = forwarded_for(&value).or(self.client),
+            Some(Header::Forwarded)     => self.client = self.client.or_else(|| forwarded(&value)),
+            Some(Header::Capture(n))    => self.headers.extend(CString::new(value).ok().map(|v| (n, v))),
+            None                        => (),
+        };
+
+        self.name.clear();
+        self.in_value = false;
+    }
+}
+
 impl ParserHandler for State {
     fn on_url(&mut self, _: &mut Parser, url: &[u8]) -> bool {
-        self.url = CString::new(url).ok();
+        self.target.extend_from_slice(url);
         true
     }
 
-    // FIXME: name might be partial, collect in vec
     fn on_header_field(&mut self, _: &mut Parser, name: &[u8]) -> bool {
-        let name = unsafe {
-            let s = slice::from_raw_parts_mut(name.as_ptr() as *mut u8, name.len());
-            s.make_ascii_lowercase();
-            &s[..]
-        };
+        if self.in_value {
+            self.header();
+        }
+        self.name.extend_from_slice(name);
+        true
+    }
 
-        self.header = match name {
-            b"host"       => Some(Header::Host),
-            b"referer"    => Some(Header::Referer),
-            b"user-agent" => Some(Header::UserAgent),
-            _             => None
-        };
+    fn on_header_value(&mut self, _: &mut Parser, value: &[u8]) -> bool {
+        self.value.extend_from_slice(value);
+        self.in_value = true;
+        true
+    }
 
+    fn on_headers_complete(&mut self, _: &mut Parser) -> bool {
+        if !self.name.is_empty() {
+            self.header();
+        }
+        if !self.target.is_empty() {
+            self.url = CString::new(mem::take(&mut self.target)).ok();
+        }
         true
     }
 
-    // FIXME: value might be partial, collect in vec
-    fn on_header_value(&mut self, _: &mut Parser, value: &[u8]) -> bool {
-        match self.header {
-            Some(Header::Host)      => self.host = CString::new(value).ok(),
-            Some(Header::Referer)   => self.referer = CString::new(value).ok(),
-            Some(Header::UserAgent) => self.ua = CString::new(value).ok(),
-            _                       => (),
-        };
+    fn on_body(&mut self, _: &mut Parser, body: &[u8]) -> bool {
+        self.body += body.len() as u64;
         true
     }
 
@@ -245,3 +398,38 @@ impl ParserHandler for State {
 fn error(p: &Parser) -> String {
     format!("{} {}", p.error(), p.error_description())
 }
+
+// The original client is the first address in X-Forwarded-For.
+pub fn forwarded_for(value: &[u8]) -> Option<IpAddr> {
+    let value = ::std::str::from_utf8(value).ok()?;
+    addr(value.split(',').next()?)
+}
+
+// The original client is the for= parameter of the first element of
+// a Forwarded header, e.g. for="[2001:db8::17]:4711";proto=http.
+pub fn forwarded(value: &[u8]) -> Option<IpAddr> {
+    let value = ::std::str::from_utf8(value).ok()?;
+    value.split(',').next()?.split(';').find_map(|pair| {
+        let mut split = pair.splitn(2, '=');
+        match (split.next()?.trim(), split.next()) {
+            (key, Some(addr)) if key.eq_ignore_ascii_case("for") => self::addr(addr),
+            _                                                    => None,
+        }
+    })
+}
+
+fn addr(s: &str) -> Option<IpAddr> {
+    let s = s.trim().trim_matches('"');
+    if let Ok(addr) = s.parse() {
+        return Some(addr);
+    }
+
+    match s.strip_prefix('[') {
+        Some(s) => s.split(']').next()?.parse().ok(),
+        None    => s.rsplitn(2, ':').last()?.parse().ok(),
+    }
+}
+
+fn number(value: &[u8]) -> Option<u64> {
+    ::std::str::from_utf8(value).ok()?.trim().parse().ok()
+}
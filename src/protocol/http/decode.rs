@@ -1,12 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::collections::hash_map::Entry::*;
 use std::collections::hash_map::VacantEntry;
 use std::ffi::CString;
+use std::sync::Arc;
 use time::Duration;
 use crate::flow::{Addr, Flow, SYN, ACK, FIN};
 use crate::custom::*;
 use crate::time::Timestamp;
-use super::conn::Connection;
+use super::conn::{Connection, Res};
 
 pub struct Decoder {
     req_url:     u64,
@@ -14,9 +15,22 @@ pub struct Decoder {
     req_referer: u64,
     req_ua:      u64,
     res_status:  u64,
+    method:      Option<u64>,
+    version:     Option<u64>,
+    req_len:     Option<u64>,
+    res_len:     Option<u64>,
+    content:     Option<u64>,
+    client:      Option<u64>,
+    grpc_svc:    Option<u64>,
+    grpc_method: Option<u64>,
+    grpc_status: Option<u64>,
     latency:     u64,
     empty:       CString,
+    headers:     Arc<Vec<Vec<u8>>>,
+    columns:     Vec<String>,
     conns:       HashMap<(Addr, Addr), Connection>,
+    pending:     VecDeque<Res>,
+    response:    Option<Res>,
 }
 
 impl Decoder {
@@ -27,15 +41,38 @@ impl Decoder {
             req_referer: cs.get(HTTP_REFERER)?,
             req_ua:      cs.get(HTTP_UA)?,
             res_status:  cs.get(HTTP_STATUS)?,
+            method:      cs.get(HTTP_METHOD).ok(),
+            version:     cs.get(HTTP_VERSION).ok(),
+            req_len:     cs.get(HTTP_REQ_LENGTH).ok(),
+            res_len:     cs.get(HTTP_RES_LENGTH).ok(),
+            content:     cs.get(HTTP_CONTENT_TYPE).ok(),
+            client:      cs.get(HTTP_CLIENT_IP).ok(),
+            grpc_svc:    cs.get(GRPC_SERVICE).ok(),
+            grpc_method: cs.get(GRPC_METHOD).ok(),
+            grpc_status: cs.get(GRPC_STATUS).ok(),
             latency:     cs.get(APP_LATENCY)?,
             empty:       Default::default(),
+            headers:     Default::default(),
+            columns:     Vec::new(),
             conns:       HashMap::new(),
+            pending:     VecDeque::new(),
+            response:    None,
         })
     }
 
+    // Capture additional headers, each exported to a column named
+    // HTTP_HEADER_ and the header name, e.g. HTTP_HEADER_X_REQUEST_ID.
+    pub fn capture(&mut self, headers: &[String]) {
+        self.headers = Arc::new(headers.iter().map(|h| h.to_ascii_lowercase().into_bytes()).collect());
+        self.columns = headers.iter().map(|h| {
+            format!("HTTP_HEADER_{}", h.to_ascii_uppercase().replace('-', "_"))
+        }).collect();
+    }
+
     pub fn decode(&mut self, flow: &Flow, cs: &mut Customs) -> bool {
         let flags = flow.tcp_flags();
         let decoded = match self.conn(flow.src, flow.dst, flags) {
+            Some(ref mut c) if c.is_h2(flow)     => self.parse_h2(c, flow, cs),
             Some(ref mut c) if c.is_client(flow) => self.parse_req(c, flow, cs),
             Some(ref mut c)                      => self.parse_res(c, flow, cs),
             None                                 => false,
@@ -50,6 +87,16 @@ impl Decoder {
         }
     }
 
+    // HTTP/2 streams completed by the same segment are exported one
+    // per record, each kept until the next since customs point into it.
+    pub fn next(&mut self, cs: &mut Customs) -> bool {
+        self.response = self.pending.pop_front();
+        match self.response {
+            Some(ref res) => { self.append(res, cs); true },
+            None          => false,
+        }
+    }
+
     pub fn clear(&mut self, ts: Timestamp, timeout: Duration) {
         self.conns.retain(|_, c| !c.is_idle(ts, timeout))
     }
@@ -60,16 +107,17 @@ impl Decoder {
             false => (dst, src),
         };
 
+        let headers = &self.headers;
         let maybe_insert = |e: VacantEntry<'a, _, _>| -> Option<&'a mut Connection> {
             const SYNACK: u8 = SYN|ACK;
             match flags & SYNACK {
-                SYN    => Some(e.insert(Connection::new(dst.port))),
-                SYNACK => Some(e.insert(Connection::new(src.port))),
+                SYN    => Some(e.insert(Connection::with_headers(dst.port, headers.clone()))),
+                SYNACK => Some(e.insert(Connection::with_headers(src.port, headers.clone()))),
                 _      => None,
             }
         };
 
-        // safe because self.conns will not be accessed in parse_req or parse_res
+        // safe because self.conns will not be accessed in parse_req, parse_res or parse_h2
         let conns: &'a mut HashMap<_, _> = unsafe {
             &mut *(&mut self.conns as *mut HashMap<_, _>)
         };
@@ -98,23 +146,65 @@ impl Decoder {
     }
 
     fn parse_res(&self, c: &mut Connection, flow: &Flow, cs: &mut Customs) -> bool {
-        let req_url     = self.req_url;
-        let req_host    = self.req_host;
-        let req_referer = self.req_referer;
-        let req_ua      = self.req_ua;
-        let res_status  = self.res_status;
-        let latency     = self.latency;
-        let empty       = &self.empty;
-
         c.parse_res(flow.timestamp, flow.payload).map(|res| {
             // println!("got http response {:#?}", res);
-            cs.add_str(req_url, res.url.as_ref().unwrap_or(empty));
-            cs.add_str(req_host, res.host.as_ref().unwrap_or(empty));
-            cs.add_str(req_referer, res.referer.as_ref().unwrap_or(empty));
-            cs.add_str(req_ua, res.ua.as_ref().unwrap_or(empty));
-            cs.add_u32(res_status, res.status as u32);
-            cs.add_u32(latency, res.latency.whole_milliseconds() as u32);
+            self.append(res, cs);
             true
         }).unwrap_or(false)
     }
+
+    fn parse_h2(&mut self, c: &mut Connection, flow: &Flow, cs: &mut Customs) -> bool {
+        let client = c.is_client(flow);
+        self.pending = c.parse_h2(flow.timestamp, client, flow.payload).into();
+        self.next(cs)
+    }
+
+    fn append(&self, res: &Res, cs: &mut Customs) {
+        let empty = &self.empty;
+
+        cs.add_str(self.req_url, res.url.as_ref().unwrap_or(empty));
+        cs.add_str(self.req_host, res.host.as_ref().unwrap_or(empty));
+        cs.add_str(self.req_referer, res.referer.as_ref().unwrap_or(empty));
+        cs.add_str(self.req_ua, res.ua.as_ref().unwrap_or(empty));
+        cs.add_u32(self.res_status, res.status as u32);
+        cs.add_u32(self.latency, res.latency.whole_milliseconds() as u32);
+
+        if let (Some(id), Some(method)) = (self.method, res.method.as_ref()) {
+            cs.add_str(id, method);
+        }
+
+        if let (Some(id), Some(version)) = (self.version, res.version.as_ref()) {
+            cs.add_str(id, version);
+        }
+
+        if let (Some(id), Some(len)) = (self.req_len, res.req_len) {
+            cs.add_u32(id, len.min(u32::max_value() as u64) as u32);
+        }
+
+        if let (Some(id), Some(len)) = (self.res_len, res.res_len) {
+            cs.add_u32(id, len.min(u32::max_value() as u64) as u32);
+        }
+
+        if let (Some(id), Some(content)) = (self.content, res.content.as_ref()) {
+            cs.add_str(id, content);
+        }
+
+        if let (Some(id), Some(client)) = (self.client, res.client) {
+            cs.add_addr(id, client);
+        }
+
+        for &(n, ref value) in &res.headers {
+            if let Some(id) = self.columns.get(n).and_then(|c| cs.get(c).ok()) {
+                cs.add_str(id, value);
+            }
+        }
+
+        if let Some(ref grpc) = res.grpc {
+            self.grpc_svc.map(|id| cs.add_str(id, &grpc.service));
+            self.grpc_method.map(|id| cs.add_str(id, &grpc.method));
+            if let (Some(id), Some(status)) = (self.grpc_status, grpc.status) {
+                cs.add_u32(id, status);
+            }
+        }
+    }
 }
@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::protocol::buf::Buffer;
+use crate::time::Timestamp;
+use super::conn::{Grpc, Res};
+use super::hpack::{self, Header};
+
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+pub struct Connection {
+    client:  Peer,
+    server:  Peer,
+    streams: HashMap<u32, Stream>,
+    capture: Arc<Vec<Vec<u8>>>,
+}
+
+struct Peer {
+    buffer: Buffer,
+    hpack:  hpack::Decoder,
+    skip:   usize,
+    block:  Option<Block>,
+}
+
+// A header block split across HEADERS or PUSH_PROMISE and
+// CONTINUATION frames.
+struct Block {
+    stream: u32,
+    end:    bool,
+    push:   bool,
+    data:   Vec<u8>,
+}
+
+#[derive(Debug)]
+enum Event {
+    Headers(u32, bool, Vec<Header>),
+    Data(u32, u64),
+    End(u32),
+    Reset(u32),
+}
+
+#[derive(Debug)]
+pub struct Stream {
+    pub method:    Option<CString>,
+    pub path:      Option<CString>,
+    pub authority: Option<CString>,
+    pub referer:   Option<CString>,
+    pub ua:        Option<CString>,
+    pub grpc:      bool,
+    pub status:    u16,
+    pub trailer:   Option<u32>,
+    pub req_len:   Option<u64>,
+    pub res_len:   Option<u64>,
+    pub req_body:  u64,
+    pub res_body:  u64,
+    pub content:   Option<CString>,
+    pub headers:   Vec<(usize, CString)>,
+    pub start:     Timestamp,
+}
+
+const DATA:         u8 = 0x0;
+const HEADERS:      u8 = 0x1;
+const RST_STREAM:   u8 = 0x3;
+const PUSH_PROMISE: u8 = 0x5;
+const CONTINUATION: u8 = 0x9;
+
+const END_STREAM:  u8 = 0x01;
+const END_HEADERS: u8 = 0x04;
+const PADDED:      u8 = 0x08;
+const PRIORITY:    u8 = 0x20;
+
+const MAX_BUFFER:  usize = 1 << 20;
+const MAX_BLOCK:   usize = 1 << 18;
+const MAX_STREAMS: usize = 1024;
+
+impl Connection {
+    // Connection which also captures the named request and response
+    // headers, which must be lowercase.
+    pub fn new(capture: Arc<Vec<Vec<u8>>>) -> Self {
+        Connection {
+            client:  Peer::new(PREFACE.len()),
+            server:  Peer::new(0),
+            streams: HashMap::new(),
+            capture: capture,
+        }
+    }
+
+    // Connection upgraded from HTTP/1.1, where the request which
+    // carried the Upgrade header becomes stream 1.
+    pub fn upgrade(stream: Stream, capture: Arc<Vec<Vec<u8>>>) -> Self {
+        let mut conn = Self::new(capture);
+        conn.streams.insert(1, stream);
+        conn
+    }
+
+    pub fn client_msg(&mut self, ts: Timestamp, buf: &[u8]) {
+        for event in self.client.read(buf).unwrap_or_else(|| self.fail()) {
+            match event {
+                Event::Headers(id, _, headers) => self.request(ts, id, headers),
+                Event::Data(id, len)           => {
+                    if let Some(stream) = self.streams.get_mut(&id) {
+                        stream.req_body += len;
+                    }
+                },
+                Event::Reset(id)               => { self.streams.remove(&id); },
+                Event::End(..)                 => (),
+            }
+        }
+    }
+
+    pub fn server_msg(&mut self, ts: Timestamp, buf: &[u8]) -> Vec<Res> {
+        let mut completed = Vec::new();
+
+        for event in self.server.read(buf).unwrap_or_else(|| self.fail()) {
+            match event {
+                Event::Headers(id, end, headers) => {
+                    if let Some(stream) = self.streams.get_mut(&id) {
+                        stream.response(headers, &self.capture);
+                    }
+                    if end {
+                        completed.extend(self.complete(ts, id));
+                    }
+                },
+                Event::Data(id, len) => {
+                    if let Some(stream) = self.streams.get_mut(&id) {
+                        stream.res_body += len;
+                    }
+                },
+                Event::End(id)   => completed.extend(self.complete(ts, id)),
+                Event::Reset(id) => { self.streams.remove(&id); },
+            }
+        }
+
+        completed
+    }
+
+    fn request(&mut self, ts: Timestamp, id: u32, headers: Vec<Header>) {
+        if self.streams.contains_key(&id) || self.streams.len() >= MAX_STREAMS {
+            return;
+        }
+        self.streams.insert(id, Stream::request(ts, headers, &self.capture));
+    }
+
+    fn complete(&mut self, ts: Timestamp, id: u32) -> Option<Res> {
+        let stream = self.streams.remove(&id)?;
+
+        let grpc = match stream.grpc {
+            true  => stream.path.as_ref().map(|path| grpc(path, stream.trailer)),
+            false => None,
+        };
+
+        Some(Res{
+            status:  stream.status,
+            method:  stream.method,
+            version: CString::new("2").ok(),
+            url:     stream.path,
+            host:    stream.authority,
+            referer: stream.referer,
+            ua:      stream.ua,
+            client:  None,
+            req_len: Some(stream.req_len.unwrap_or(stream.req_body)),
+            res_len: Some(stream.res_len.unwrap_or(stream.res_body)),
+            content: stream.content,
+            headers: stream.headers,
+            grpc:    grpc,
+            latency: ts - stream.start,
+        })
+    }
+
+    // Once HPACK state is lost the remainder of the connection can't
+    // be decoded.
+    fn fail(&mut self) -> Vec<Event> {
+        self.streams.clear();
+        self.client = Peer::failed();
+        self.server = Peer::failed();
+        Vec::new()
+    }
+}
+
+impl Stream {
+    pub fn new(ts: Timestamp) -> Self {
+        Stream {
+            method:    None,
+            path:      None,
+            authority: None,
+            referer:   None,
+            ua:        None,
+            grpc:      false,
+            status:    0,
+            trailer:   None,
+            req_len:   None,
+            res_len:   None,
+            req_body:  0,
+            res_body:  0,
+            content:   None,
+            headers:   Vec::new(),
+            start:     ts,
+        }
+    }
+
+    fn request(ts: Timestamp, headers: Vec<Header>, capture: &[Vec<u8>]) -> Self {
+        let mut stream = Stream::new(ts);
+
+        for (name, value) in headers {
+            match &name[..] {
+                b":method"        => stream.method    = CString::new(value).ok(),
+                b":path"          => stream.path      = CString::new(value).ok(),
+                b":authority"     => stream.authority = CString::new(value).ok(),
+                b"referer"        => stream.referer   = CString::new(value).ok(),
+                b"user-agent"     => stream.ua        = CString::new(value).ok(),
+                b"content-type"   => stream.grpc      = value.starts_with(b"application/grpc"),
+                b"content-length" => stream.req_len   = number(&value),
+                name              => stream.capture(capture, name, value),
+            }
+        }
+
+        stream
+    }
+
+    fn response(&mut self, headers: Vec<Header>, capture: &[Vec<u8>]) {
+        for (name, value) in headers {
+            match &name[..] {
+                b":status"        => self.status  = number(&value).unwrap_or(0),
+                b"grpc-status"    => self.trailer = number(&value),
+                b"content-type"   => self.content = CString::new(value).ok(),
+                b"content-length" => self.res_len = number(&value),
+                name              => self.capture(capture, name, value),
+            }
+        }
+    }
+
+    fn capture(&mut self, capture: &[Vec<u8>], name: &[u8], value: Vec<u8>) {
+        if let Some(n) = capture.iter().position(|h| h == name) {
+            self.headers.extend(CString::new(value).ok().map(|v| (n, v)));
+        }
+    }
+}
+
+impl Peer {
+    fn new(skip: usize) -> Self {
+        Peer {
+            buffer: Buffer::new(),
+            hpack:  hpack::Decoder::new(),
+            skip:   skip,
+            block:  None,
+        }
+    }
+
+    fn failed() -> Self {
+        Peer::new(usize::max_value())
+    }
+
+    fn read(&mut self, buf: &[u8]) -> Option<Vec<Event>> {
+        let skip = self.skip.min(buf.len());
+        self.skip -= skip;
+
+        let mut buf = self.buffer.buf(&buf[skip..]);
+        let mut rest = &buf[..];
+        let mut events = Vec::new();
+
+        while rest.len() >= 9 {
+            let len    = (rest[0] as usize) << 16 | (rest[1] as usize) << 8 | rest[2] as usize;
+            let kind   = rest[3];
+            let flags  = rest[4];
+            let stream = u32::from_be_bytes([rest[5], rest[6], rest[7], rest[8]]) & 0x7FFF_FFFF;
+
+            // DATA payloads are counted and skipped rather than
+            // buffered, less any padding.
+            if kind == DATA {
+                let pad = match flags & PADDED {
+                    PADDED => rest.get(9).map_or(0, |&n| n as usize + 1),
+                    _      => 0,
+                };
+                events.push(Event::Data(stream, len.saturating_sub(pad) as u64));
+                if flags & END_STREAM == END_STREAM {
+                    events.push(Event::End(stream));
+                }
+                if rest.len() < len + 9 {
+                    self.skip = len + 9 - rest.len();
+                    rest = &[];
+                    break;
+                }
+                rest = &rest[len + 9..];
+                continue;
+            }
+
+            if len > MAX_BLOCK {
+                return None;
+            } else if rest.len() < len + 9 {
+                break;
+            }
+
+            let payload = &rest[9..len + 9];
+            rest = &rest[len + 9..];
+
+            let block = match (kind, self.block.take()) {
+                (HEADERS, None) => {
+                    let offset = match flags & PRIORITY {
+                        PRIORITY => 5,
+                        _        => 0,
+                    };
+                    let data = padded(payload, flags)?.get(offset..)?;
+                    Block::new(stream, flags, false, data)
+                },
+                (PUSH_PROMISE, None) => {
+                    let data = padded(payload, flags)?.get(4..)?;
+                    Block::new(stream, flags, true, data)
+                },
+                (CONTINUATION, Some(mut block)) if block.stream == stream => {
+                    block.data.extend_from_slice(payload);
+                    block
+                },
+                (RST_STREAM, None) => {
+                    events.push(Event::Reset(stream));
+                    continue;
+                },
+                (CONTINUATION, _) | (_, Some(_)) => return None,
+                (_, None)                        => continue,
+            };
+
+            if block.data.len() > MAX_BLOCK {
+                return None;
+            }
+
+            match flags & END_HEADERS {
+                END_HEADERS => {
+                    let headers = self.hpack.decode(&block.data)?;
+                    if !block.push {
+                        events.push(Event::Headers(block.stream, block.end, headers));
+                    }
+                },
+                _ => self.block = Some(block),
+            }
+        }
+
+        let remainder = match rest.len() {
+            n if n > MAX_BUFFER => 0,
+            n                   => n,
+        };
+        buf.keep(remainder);
+
+        Some(events)
+    }
+}
+
+impl Block {
+    fn new(stream: u32, flags: u8, push: bool, data: &[u8]) -> Self {
+        Block {
+            stream: stream,
+            end:    flags & END_STREAM == END_STREAM,
+            push:   push,
+            data:   data.to_vec(),
+        }
+    }
+}
+
+fn padded(payload: &[u8], flags: u8) -> Option<&[u8]> {
+    match flags & PADDED {
+        PADDED => {
+            let (&pad, rest) = payload.split_first()?;
+            rest.get(..rest.len().checked_sub(pad as usize)?)
+        },
+        _      => Some(payload),
+    }
+}
+
+// gRPC requests are POSTs to /package.Service/Method.
+fn grpc(path: &CString, status: Option<u32>) -> Grpc {
+    let path = path.as_bytes();
+    let path = path.strip_prefix(b"/").unwrap_or(path);
+
+    let (service, method) = match path.iter().rposition(|&b| b == b'/') {
+        Some(n) => (&path[..n], &path[n + 1..]),
+        None    => (path, &b""[..]),
+    };
+
+    Grpc {
+        service: CString::new(service).unwrap_or_default(),
+        method:  CString::new(method).unwrap_or_default(),
+        status:  status,
+    }
+}
+
+fn number<T: FromStr>(value: &[u8]) -> Option<T> {
+    ::std::str::from_utf8(value).ok()?.parse().ok()
+}
@@ -0,0 +1,305 @@
+use std::collections::VecDeque;
+
+// HPACK header block decoder (RFC 7541). Each direction of an HTTP/2
+// connection has its own decoder whose dynamic table must see every
+// header block to stay in sync with the encoder.
+
+pub type Header = (Vec<u8>, Vec<u8>);
+
+pub struct Decoder {
+    table:   VecDeque<Header>,
+    size:    usize,
+    max:     usize,
+    huffman: Huffman,
+}
+
+struct Huffman {
+    counts:  [u16; MAX_BITS + 1],
+    symbols: [u16; 257],
+}
+
+const DEFAULT_SIZE: usize = 4096;
+const MAX_SIZE:     usize = 1 << 16;
+const MAX_BITS:     usize = 30;
+const EOS:          u16   = 256;
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder {
+            table:   VecDeque::new(),
+            size:    0,
+            max:     DEFAULT_SIZE,
+            huffman: Huffman::new(),
+        }
+    }
+
+    pub fn decode(&mut self, mut buf: &[u8]) -> Option<Vec<Header>> {
+        let mut headers = Vec::new();
+
+        while let Some(&b) = buf.first() {
+            match b {
+                b if b & 0x80 == 0x80 => {
+                    let (rest, index) = integer(buf, 7)?;
+                    headers.push(self.get(index)?);
+                    buf = rest;
+                },
+                b if b & 0xC0 == 0x40 => {
+                    let (rest, header) = self.literal(buf, 6)?;
+                    self.insert(header.clone());
+                    headers.push(header);
+                    buf = rest;
+                },
+                b if b & 0xE0 == 0x20 => {
+                    let (rest, max) = integer(buf, 5)?;
+                    if max > MAX_SIZE {
+                        return None;
+                    }
+                    self.max = max;
+                    self.evict(0);
+                    buf = rest;
+                },
+                _ => {
+                    let (rest, header) = self.literal(buf, 4)?;
+                    headers.push(header);
+                    buf = rest;
+                },
+            }
+        }
+
+        Some(headers)
+    }
+
+    fn literal<'a>(&self, buf: &'a [u8], prefix: u8) -> Option<(&'a [u8], Header)> {
+        let (rest, index) = integer(buf, prefix)?;
+        let (rest, name) = match index {
+            0 => self.string(rest)?,
+            n => (rest, self.get(n)?.0),
+        };
+        let (rest, value) = self.string(rest)?;
+        Some((rest, (name, value)))
+    }
+
+    fn string<'a>(&self, buf: &'a [u8]) -> Option<(&'a [u8], Vec<u8>)> {
+        let huffman = buf.first()? & 0x80 == 0x80;
+        let (rest, len) = integer(buf, 7)?;
+        if rest.len() < len {
+            return None;
+        }
+
+        let (s, rest) = rest.split_at(len);
+        match huffman {
+            true  => Some((rest, self.huffman.decode(s)?)),
+            false => Some((rest, s.to_vec())),
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<Header> {
+        match index {
+            0                      => None,
+            n if n <= STATIC.len() => STATIC.get(n - 1).map(|&(n, v)| (n.to_vec(), v.to_vec())),
+            n                      => self.table.get(n - STATIC.len() - 1).cloned(),
+        }
+    }
+
+    fn insert(&mut self, header: Header) {
+        let size = header.0.len() + header.1.len() + 32;
+        self.evict(size);
+        if size <= self.max {
+            self.size += size;
+            self.table.push_front(header);
+        }
+    }
+
+    fn evict(&mut self, room: usize) {
+        while self.size + room > self.max {
+            match self.table.pop_back() {
+                Some((n, v)) => self.size -= n.len() + v.len() + 32,
+                None         => break,
+            }
+        }
+    }
+}
+
+// HPACK integers have an N-bit prefix followed by 7-bit continuation
+// bytes.
+fn integer(buf: &[u8], prefix: u8) -> Option<(&[u8], usize)> {
+    let mask = (1u16 << prefix) as usize - 1;
+    let (first, mut rest) = buf.split_first()?;
+
+    let mut value = *first as usize & mask;
+    if value < mask {
+        return Some((rest, value));
+    }
+
+    let mut shift = 0;
+    loop {
+        let (b, more) = rest.split_first()?;
+        value += (*b as usize & 0x7F) << shift;
+        rest = more;
+        if b & 0x80 == 0 {
+            return Some((rest, value));
+        }
+        shift += 7;
+        if shift > 21 {
+            return None;
+        }
+    }
+}
+
+impl Huffman {
+    // The HPACK code is canonical, so codes are assigned in order of
+    // code length and then symbol.
+    fn new() -> Self {
+        let mut counts  = [0u16; MAX_BITS + 1];
+        let mut symbols = [0u16; 257];
+
+        for &len in LENGTHS.iter() {
+            counts[len as usize] += 1;
+        }
+
+        let mut n = 0;
+        for len in 1..=MAX_BITS as u8 {
+            for (sym, _) in LENGTHS.iter().enumerate().filter(|&(_, &l)| l == len) {
+                symbols[n] = sym as u16;
+                n += 1;
+            }
+        }
+
+        Huffman {
+            counts:  counts,
+            symbols: symbols,
+        }
+    }
+
+    fn decode(&self, buf: &[u8]) -> Option<Vec<u8>> {
+        let mut out = Vec::with_capacity(buf.len() * 8 / 5);
+
+        let mut code  = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        let mut len   = 0;
+        let mut bits  = 0u32;
+
+        for b in buf {
+            for shift in (0..8).rev() {
+                let bit = (b >> shift) & 1;
+                code |= bit as i32;
+                bits  = (bits << 1) | bit as u32;
+                len  += 1;
+
+                let count = self.counts[len] as i32;
+                if code - count < first {
+                    match self.symbols[(index + code - first) as usize] {
+                        EOS => return None,
+                        sym => out.push(sym as u8),
+                    }
+                    code  = 0;
+                    first = 0;
+                    index = 0;
+                    len   = 0;
+                    bits  = 0;
+                    continue;
+                }
+
+                if len == MAX_BITS {
+                    return None;
+                }
+
+                index += count;
+                first  = (first + count) << 1;
+                code <<= 1;
+            }
+        }
+
+        // padding must be a prefix of EOS, which is all ones
+        match len {
+            0..=7 if bits == (1 << len) - 1 => Some(out),
+            _                               => None,
+        }
+    }
+}
+
+const STATIC: &[(&[u8], &[u8])] = &[
+    (b":authority",                  b""),
+    (b":method",                     b"GET"),
+    (b":method",                     b"POST"),
+    (b":path",                       b"/"),
+    (b":path",                       b"/index.html"),
+    (b":scheme",                     b"http"),
+    (b":scheme",                     b"https"),
+    (b":status",                     b"200"),
+    (b":status",                     b"204"),
+    (b":status",                     b"206"),
+    (b":status",                     b"304"),
+    (b":status",                     b"400"),
+    (b":status",                     b"404"),
+    (b":status",                     b"500"),
+    (b"accept-charset",              b""),
+    (b"accept-encoding",             b"gzip, deflate"),
+    (b"accept-language",             b""),
+    (b"accept-ranges",               b""),
+    (b"accept",                      b""),
+    (b"access-control-allow-origin", b""),
+    (b"age",                         b""),
+    (b"allow",                       b""),
+    (b"authorization",               b""),
+    (b"cache-control",               b""),
+    (b"content-disposition",         b""),
+    (b"content-encoding",            b""),
+    (b"content-language",            b""),
+    (b"content-length",              b""),
+    (b"content-location",            b""),
+    (b"content-range",               b""),
+    (b"content-type",                b""),
+    (b"cookie",                      b""),
+    (b"date",                        b""),
+    (b"etag",                        b""),
+    (b"expect",                      b""),
+    (b"expires",                     b""),
+    (b"from",                        b""),
+    (b"host",                        b""),
+    (b"if-match",                    b""),
+    (b"if-modified-since",           b""),
+    (b"if-none-match",               b""),
+    (b"if-range",                    b""),
+    (b"if-unmodified-since",         b""),
+    (b"last-modified",               b""),
+    (b"link",                        b""),
+    (b"location",                    b""),
+    (b"max-forwards",                b""),
+    (b"proxy-authenticate",          b""),
+    (b"proxy-authorization",         b""),
+    (b"range",                       b""),
+    (b"referer",                     b""),
+    (b"refresh",                     b""),
+    (b"retry-after",                 b""),
+    (b"server",                      b""),
+    (b"set-cookie",                  b""),
+    (b"strict-transport-security",   b""),
+    (b"transfer-encoding",           b""),
+    (b"user-agent",                  b""),
+    (b"vary",                        b""),
+    (b"via",                         b""),
+    (b"www-authenticate",            b""),
+];
+
+// Huffman code lengths indexed by symbol, with EOS last.
+const LENGTHS: [u8; 257] = [
+    13, 23, 28, 28, 28, 28, 28, 28, 28, 24, 30, 28, 28, 30, 28, 28,
+    28, 28, 28, 28, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+     6, 10, 10, 12, 13,  6,  8, 11, 10, 10,  8, 11,  8,  6,  6,  6,
+     5,  5,  5,  6,  6,  6,  6,  6,  6,  6,  7,  8, 15,  6, 12, 10,
+    13,  6,  7,  7,  7,  7,  7,  7,  7,  7,  7,  7,  7,  7,  7,  7,
+     7,  7,  7,  7,  7,  7,  7,  7,  8,  7,  8, 13, 19, 13, 14,  6,
+    15,  5,  6,  5,  6,  5,  6,  6,  6,  5,  7,  7,  6,  6,  6,  5,
+     6,  7,  6,  5,  5,  6,  7,  7,  7,  7,  7, 15, 11, 14, 13, 28,
+    20, 22, 20, 20, 22, 22, 22, 23, 22, 23, 23, 23, 23, 23, 24, 23,
+    24, 24, 22, 23, 24, 23, 23, 23, 23, 21, 22, 23, 22, 23, 23, 24,
+    22, 21, 20, 22, 22, 23, 23, 21, 23, 22, 22, 24, 21, 22, 23, 23,
+    21, 21, 22, 21, 23, 22, 23, 23, 20, 22, 22, 22, 23, 22, 22, 23,
+    26, 26, 20, 19, 22, 23, 22, 25, 26, 26, 26, 27, 27, 26, 24, 25,
+    19, 21, 26, 27, 27, 26, 27, 24, 21, 21, 26, 26, 28, 27, 27, 27,
+    20, 24, 20, 21, 22, 21, 21, 23, 22, 22, 25, 25, 24, 24, 26, 23,
+    26, 27, 26, 26, 27, 27, 27, 27, 27, 28, 27, 27, 27, 27, 27, 26,
+    30,
+];
@@ -1,5 +1,7 @@
 pub mod conn;
 pub mod decode;
+pub mod h2;
+pub mod hpack;
 
 pub use self::decode::*;
 
@@ -1,4 +1,5 @@
 use std::ffi::CString;
+use std::sync::Arc;
 use super::conn::*;
 use crate::time::Timestamp;
 use libc::timeval;
@@ -60,6 +61,29 @@ fn test_header_case_insensitive() {
     }
 }
 
+#[test]
+fn test_header_split_across_segments() {
+    let mut c = Connection::new(80);
+
+    let segments: &[&[u8]] = &[
+        b"GET /a/very/lo",
+        b"ng/path HTTP/1.1\r\nUs",
+        b"er-Agent: curl/",
+        b"8.5.0\r\nHOST:",
+        b" example.com\r\n\r\n",
+    ];
+
+    let (last, segments) = segments.split_last().unwrap();
+    for segment in segments {
+        assert!(c.parse_req(ts(), segment).is_none());
+    }
+
+    let r = c.parse_req(ts(), last).unwrap();
+    assert_eq!(Some(cstr("/a/very/long/path")), r.url);
+    assert_eq!(Some(cstr("curl/8.5.0")), r.ua);
+    assert_eq!(Some(cstr("example.com")), r.host);
+}
+
 #[test]
 fn test_is_idle() {
     let timeout = Duration::seconds(15);
@@ -73,6 +97,75 @@ fn test_is_idle() {
     assert_eq!(true,  c.is_idle(now + Duration::seconds(16), timeout));
 }
 
+#[test]
+fn test_decode_fields() {
+    let mut c = Connection::new(80);
+
+    {
+        let b = b"POST /api HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 7\r\nX-Forwarded-For: 203.0.113.7, 10.0.0.1\r\n\r\n{\"a\":1}";
+        let r = c.parse_req(ts(), b).unwrap();
+        assert_eq!(Some(cstr("POST")), r.method);
+        assert_eq!(Some(cstr("1.1")), r.version);
+        assert_eq!(7, r.length);
+        assert_eq!(Some("203.0.113.7".parse().unwrap()), r.client);
+    }
+
+    {
+        let b = b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n";
+        let r = c.parse_res(ts(), b).unwrap();
+        assert_eq!(Some(cstr("POST")), r.method);
+        assert_eq!(Some(7), r.req_len);
+        assert_eq!(Some(9), r.res_len);
+        assert_eq!(Some(cstr("text/plain")), r.content);
+    }
+}
+
+#[test]
+fn test_capture_headers() {
+    let headers = vec![b"x-request-id".to_vec(), b"server".to_vec()];
+    let mut c = Connection::with_headers(80, Arc::new(headers));
+
+    {
+        let b = b"GET / HTTP/1.0\r\nX-Request-ID: abc123\r\nForwarded: for=\"[2001:db8::17]:4711\";proto=http\r\n\r\n";
+        let r = c.parse_req(ts(), b).unwrap();
+        assert_eq!(Some(cstr("1.0")), r.version);
+        assert_eq!(Some("2001:db8::17".parse().unwrap()), r.client);
+    }
+
+    {
+        let b = b"HTTP/1.0 204 No Content\r\nServer: nginx\r\n\r\n";
+        let r = c.parse_res(ts(), b).unwrap();
+        assert_eq!(vec![(0, cstr("abc123")), (1, cstr("nginx"))], r.headers);
+        assert_eq!(Some(0), r.res_len);
+    }
+}
+
+#[test]
+fn test_forwarded_client() {
+    let cases: &[(&[u8], Option<&str>)] = &[
+        (b"192.0.2.60",                         Some("192.0.2.60")),
+        (b" 192.0.2.60:8080 , 10.1.1.1",        Some("192.0.2.60")),
+        (b"[2001:db8::1]:443",                  Some("2001:db8::1")),
+        (b"2001:db8::2",                        Some("2001:db8::2")),
+        (b"unknown",                            None),
+    ];
+
+    for &(value, addr) in cases {
+        assert_eq!(addr.map(|a| a.parse().unwrap()), forwarded_for(value));
+    }
+
+    let cases: &[(&[u8], Option<&str>)] = &[
+        (b"for=192.0.2.43",                     Some("192.0.2.43")),
+        (b"proto=https;For=198.51.100.17:80",   Some("198.51.100.17")),
+        (b"for=\"_hidden\", for=192.0.2.43",    None),
+        (b"by=203.0.113.43",                    None),
+    ];
+
+    for &(value, addr) in cases {
+        assert_eq!(addr.map(|a| a.parse().unwrap()), forwarded(value));
+    }
+}
+
 fn ts() -> Timestamp {
     Timestamp::from(timeval{
         tv_sec:  0,
@@ -1,12 +1,18 @@
 pub mod buf;
 pub mod classify;
+pub mod config;
 pub mod decode;
 pub mod dhcp;
+pub mod dhcpv6;
 pub mod dns;
 pub mod http;
+pub mod mysql;
 pub mod postgres;
+pub mod quic;
 pub mod radius;
+pub mod redis;
 pub mod tls;
 
 pub use self::classify::Classify;
+pub use self::config::Config;
 pub use self::decode::{Decoder, Decoders};
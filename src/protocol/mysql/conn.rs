@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use time::Duration;
+use nom::IResult::{self, Done, Incomplete};
+use crate::protocol::buf::Buffer;
+use crate::time::Timestamp;
+use super::parser::{self, Command, Packet, Response};
+use super::parser::{CLIENT_DEPRECATE_EOF, CLIENT_SSL, SERVER_MORE_RESULTS};
+
+pub struct Connection {
+    buffer_c: Buffer,
+    buffer_s: Buffer,
+    state:    State,
+    last:     Timestamp,
+}
+
+struct State {
+    phase:       Phase,
+    version:     Option<String>,
+    server_caps: u32,
+    client_caps: u32,
+    statements:  HashMap<u32, String>,
+    pending:     VecDeque<Pending>,
+    reading:     Reading,
+    rows:        u64,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Phase {
+    Command,
+    Auth,
+    Encrypted,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Reading {
+    Start,
+    Columns(u64),
+    Eof,
+    Rows,
+    Skip(u32),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Kind {
+    Query,
+    Prepare,
+    Execute,
+    Other,
+}
+
+struct Pending {
+    kind:  Kind,
+    query: Option<String>,
+    start: Timestamp,
+}
+
+#[derive(Debug)]
+pub struct CompletedQuery {
+    pub query:    Option<String>,
+    pub version:  Option<String>,
+    pub error:    Option<u16>,
+    pub rows:     Option<u64>,
+    pub duration: Duration,
+}
+
+const MAX_BUFFER: usize = 1 << 20;
+
+impl Connection {
+    pub fn new() -> Self {
+        Connection {
+            buffer_c: Buffer::new(),
+            buffer_s: Buffer::new(),
+            state:    State {
+                phase:       Phase::Command,
+                version:     None,
+                server_caps: 0,
+                client_caps: 0,
+                statements:  HashMap::new(),
+                pending:     VecDeque::new(),
+                reading:     Reading::Start,
+                rows:        0,
+            },
+            last: Timestamp::zero(),
+        }
+    }
+
+    pub fn client_msg(&mut self, ts: Timestamp, buf: &[u8]) {
+        self.last = ts;
+
+        let state = &mut self.state;
+        if state.phase == Phase::Encrypted {
+            return;
+        }
+
+        let mut buf = self.buffer_c.buf(buf);
+        let remainder = match parser::packets(&buf[..]) {
+            Done(rest, pkts)   => {
+                pkts.iter().for_each(|p| state.client(ts, p));
+                rest.len()
+            },
+            Incomplete(..)     => buf.len(),
+            IResult::Error(..) => 0,
+        };
+        buf.keep(limit(remainder));
+    }
+
+    pub fn server_msg(&mut self, ts: Timestamp, buf: &[u8]) -> Option<Vec<CompletedQuery>> {
+        self.last = ts;
+
+        let state = &mut self.state;
+        if state.phase == Phase::Encrypted {
+            return None;
+        }
+
+        let mut buf = self.buffer_s.buf(buf);
+        let mut completed = None;
+
+        let remainder = match parser::packets(&buf[..]) {
+            Done(rest, pkts)   => {
+                completed = Some(pkts.iter().flat_map(|p| state.server(ts, p)).collect());
+                rest.len()
+            },
+            Incomplete(..)     => buf.len(),
+            IResult::Error(..) => 0,
+        };
+        buf.keep(limit(remainder));
+
+        completed
+    }
+
+    pub fn is_idle(&self, ts: Timestamp, timeout: Duration) -> bool {
+        (ts - self.last) > timeout
+    }
+}
+
+fn limit(remainder: usize) -> usize {
+    match remainder {
+        n if n > MAX_BUFFER => 0,
+        n                   => n,
+    }
+}
+
+impl State {
+    fn client(&mut self, ts: Timestamp, pkt: &Packet) {
+        match self.phase {
+            Phase::Auth if pkt.seq == 1    => self.login(pkt.payload),
+            Phase::Command if pkt.seq == 0 => self.command(ts, pkt.payload),
+            _                              => (),
+        }
+    }
+
+    fn login(&mut self, payload: &[u8]) {
+        if let Some(caps) = parser::handshake_response(payload) {
+            self.client_caps = caps;
+            if caps & CLIENT_SSL != 0 && payload.len() == 32 {
+                self.phase = Phase::Encrypted;
+            }
+        }
+    }
+
+    fn command(&mut self, ts: Timestamp, payload: &[u8]) {
+        let (kind, query) = match parser::command(payload) {
+            Some(Command::Query(q))    => (Kind::Query, Some(string(q))),
+            Some(Command::Prepare(q))  => (Kind::Prepare, Some(string(q))),
+            Some(Command::Execute(id)) => (Kind::Execute, self.statements.get(&id).cloned()),
+            Some(Command::Close(id))   => { self.statements.remove(&id); return },
+            Some(Command::LongData)    => return,
+            Some(Command::Quit)        => return,
+            Some(Command::Other(..))   => (Kind::Other, None),
+            None                       => return,
+        };
+
+        self.pending.push_back(Pending {
+            kind:  kind,
+            query: query,
+            start: ts,
+        });
+    }
+
+    fn server(&mut self, ts: Timestamp, pkt: &Packet) -> Option<CompletedQuery> {
+        match self.phase {
+            Phase::Command if pkt.seq == 0 => self.greeting(pkt.payload),
+            Phase::Command                 => self.respond(ts, pkt.payload),
+            Phase::Auth                    => self.authenticated(pkt.payload),
+            Phase::Encrypted               => None,
+        }
+    }
+
+    fn greeting(&mut self, payload: &[u8]) -> Option<CompletedQuery> {
+        if let Done(_, hs) = parser::handshake(payload) {
+            self.version     = Some(hs.version.to_owned());
+            self.server_caps = hs.caps;
+            self.phase       = Phase::Auth;
+            self.pending.clear();
+            self.reading     = Reading::Start;
+        }
+        None
+    }
+
+    // Authentication ends with an OK or ERR packet, anything else is
+    // an auth switch or additional auth data.
+    fn authenticated(&mut self, payload: &[u8]) -> Option<CompletedQuery> {
+        match payload.first() {
+            Some(0x00) | Some(0xFF) => self.phase = Phase::Command,
+            _                       => (),
+        };
+        None
+    }
+
+    fn respond(&mut self, ts: Timestamp, payload: &[u8]) -> Option<CompletedQuery> {
+        let kind = self.pending.front()?.kind;
+        let response = parser::response(payload, self.deprecate_eof());
+
+        match (self.reading, response) {
+            (Reading::Start, Response::Ok{..}) if kind == Kind::Prepare => {
+                self.prepared(payload)
+            },
+            (Reading::Start, Response::Ok{rows, status}) => {
+                self.rows += rows;
+                self.done(ts, status, None)
+            },
+            (_, Response::Err{code}) => {
+                self.done(ts, 0, Some(code))
+            },
+            (Reading::Start, Response::End{status}) => {
+                self.done(ts, status, None)
+            },
+            (Reading::Start, Response::Infile) => {
+                None
+            },
+            (Reading::Start, _) => {
+                let n = parser::lenenc(payload).map(|(n, _)| n).unwrap_or(0);
+                self.reading = Reading::Columns(n);
+                None
+            },
+            (Reading::Columns(n), _) => {
+                self.reading = match n.saturating_sub(1) {
+                    0 if self.deprecate_eof() => Reading::Rows,
+                    0                         => Reading::Eof,
+                    n                         => Reading::Columns(n),
+                };
+                None
+            },
+            (Reading::Eof, _) => {
+                self.reading = Reading::Rows;
+                None
+            },
+            (Reading::Rows, Response::End{status}) => {
+                self.done(ts, status, None)
+            },
+            (Reading::Rows, _) => {
+                self.rows += 1;
+                None
+            },
+            (Reading::Skip(n), _) => {
+                match n.saturating_sub(1) {
+                    0 => self.done(ts, 0, None),
+                    n => { self.reading = Reading::Skip(n); None },
+                }
+            },
+        }
+    }
+
+    // A successful COM_STMT_PREPARE response is followed by parameter
+    // and column definitions, each terminated by an EOF packet.
+    fn prepared(&mut self, payload: &[u8]) -> Option<CompletedQuery> {
+        let p = parser::prepared(payload)?;
+        let query = self.pending.front().and_then(|p| p.query.clone());
+        self.statements.insert(p.id, query.unwrap_or_default());
+
+        let eofs = match self.deprecate_eof() {
+            true  => 0,
+            false => (p.params > 0) as u32 + (p.columns > 0) as u32,
+        };
+
+        match u32::from(p.params) + u32::from(p.columns) + eofs {
+            0 => { self.pending.pop_front(); None },
+            n => { self.reading = Reading::Skip(n); None },
+        }
+    }
+
+    fn done(&mut self, ts: Timestamp, status: u16, error: Option<u16>) -> Option<CompletedQuery> {
+        self.reading = Reading::Start;
+
+        if error.is_none() && status & SERVER_MORE_RESULTS != 0 {
+            return None;
+        }
+
+        let rows = self.rows;
+        self.rows = 0;
+
+        let pending = self.pending.pop_front()?;
+        match (pending.kind, error) {
+            (Kind::Other, _)      => return None,
+            (Kind::Prepare, None) => return None,
+            _                     => (),
+        };
+
+        Some(CompletedQuery {
+            query:    pending.query,
+            version:  self.version.clone(),
+            error:    error,
+            rows:     error.map_or(Some(rows), |_| None),
+            duration: ts - pending.start,
+        })
+    }
+
+    fn deprecate_eof(&self) -> bool {
+        self.server_caps & self.client_caps & CLIENT_DEPRECATE_EOF != 0
+    }
+}
+
+fn string(query: &[u8]) -> String {
+    String::from_utf8_lossy(query).into_owned()
+}
+
+impl ::std::fmt::Debug for Connection {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        let mut s = fmt.debug_struct("Connection");
+        s.field("buffer_c", &self.buffer_c.len());
+        s.field("buffer_s", &self.buffer_s.len());
+        s.field("phase", &self.state.phase);
+        s.finish()
+    }
+}
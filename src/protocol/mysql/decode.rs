@@ -0,0 +1,135 @@
+use std::collections::{HashMap, VecDeque};
+use std::ffi::CString;
+use time::Duration;
+use crate::flow::{Addr, Flow, FIN};
+use crate::custom::*;
+use crate::time::Timestamp;
+use super::conn::{Connection, CompletedQuery};
+
+pub struct Decoder {
+    statement: u64,
+    version:   Option<u64>,
+    error:     Option<u64>,
+    rows:      Option<u64>,
+    latency:   u64,
+    ports:     Vec<u16>,
+    values:    Values,
+    conns:     HashMap<(Addr, Addr), Connection>,
+    pending:   VecDeque<CompletedQuery>,
+}
+
+#[derive(Default)]
+struct Values {
+    statement: CString,
+    version:   CString,
+}
+
+impl Decoder {
+    pub fn new(cs: &Customs, ports: Vec<u16>) -> Result<Decoder, ()> {
+        Ok(Decoder{
+            statement: cs.get(MYSQL_STATEMENT)?,
+            version:   cs.get(MYSQL_VERSION).ok(),
+            error:     cs.get(MYSQL_ERROR).ok(),
+            rows:      cs.get(MYSQL_ROWS).ok(),
+            latency:   cs.get(APP_LATENCY)?,
+            ports:     ports,
+            values:    Default::default(),
+            conns:     HashMap::new(),
+            pending:   VecDeque::new(),
+        })
+    }
+
+    pub fn decode(&mut self, flow: &Flow, cs: &mut Customs) -> bool {
+        let queries = match (self.server(flow.src.port), self.server(flow.dst.port)) {
+            (false, true) => { self.parse_client(flow); None },
+            (true, false) => self.parse_server(flow),
+            _             => None,
+        };
+
+        if flow.tcp_flags() & FIN == FIN {
+            self.conns.remove(&(flow.src, flow.dst));
+            self.conns.remove(&(flow.dst, flow.src));
+        }
+
+        self.pending = queries.unwrap_or_default().into();
+        self.next(cs)
+    }
+
+    // Queries completed by the same segment are exported one per
+    // record.
+    pub fn next(&mut self, cs: &mut Customs) -> bool {
+        match self.pending.pop_front() {
+            Some(query) => self.append(query, cs),
+            None        => false,
+        }
+    }
+
+    pub fn clear(&mut self, ts: Timestamp, timeout: Duration) {
+        self.conns.retain(|_, c| !c.is_idle(ts, timeout))
+    }
+
+    fn server(&self, port: u16) -> bool {
+        self.ports.contains(&port)
+    }
+
+    fn append(&mut self, q: CompletedQuery, cs: &mut Customs) -> bool {
+        let values = &mut self.values;
+
+        values.statement = cstring(match q.query {
+            Some(ref query) => statement(query),
+            None            => "EXECUTE".to_owned(),
+        });
+        cs.add_str(self.statement, &values.statement);
+        cs.add_latency(self.latency, q.duration);
+
+        if let (Some(id), Some(version)) = (self.version, q.version) {
+            values.version = cstring(version);
+            cs.add_str(id, &values.version);
+        }
+
+        if let (Some(id), Some(code)) = (self.error, q.error) {
+            cs.add_u32(id, code as u32);
+        }
+
+        if let (Some(id), Some(rows)) = (self.rows, q.rows) {
+            cs.add_u32(id, rows as u32);
+        }
+
+        true
+    }
+
+    fn parse_client(&mut self, flow: &Flow) {
+        let addr = (flow.src, flow.dst);
+        let conn = self.conns.entry(addr).or_insert_with(Connection::new);
+        conn.client_msg(flow.timestamp, flow.payload)
+    }
+
+    fn parse_server(&mut self, flow: &Flow) -> Option<Vec<CompletedQuery>> {
+        let addr = (flow.dst, flow.src);
+        let conn = self.conns.entry(addr).or_insert_with(Connection::new);
+        conn.server_msg(flow.timestamp, flow.payload)
+    }
+}
+
+// The statement type is the first keyword of the query, skipping
+// leading whitespace, comments, and parentheses.
+pub fn statement(query: &str) -> String {
+    let mut rest = query;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '(');
+        if rest.starts_with("/*") {
+            rest = rest.find("*/").map_or("", |n| &rest[n + 2..]);
+        } else if rest.starts_with("--") || rest.starts_with('#') {
+            rest = rest.find('\n').map_or("", |n| &rest[n + 1..]);
+        } else {
+            break;
+        }
+    }
+
+    rest.chars().take_while(char::is_ascii_alphabetic).take(32).collect::<String>().to_ascii_uppercase()
+}
+
+fn cstring(s: String) -> CString {
+    CString::new(s).unwrap_or_default()
+}
@@ -0,0 +1,208 @@
+use std::str;
+use nom::*;
+use nom::IResult::Done;
+
+#[derive(Debug)]
+pub struct Packet<'a> {
+    pub seq:     u8,
+    pub payload: &'a [u8],
+}
+
+#[derive(Debug)]
+pub enum Command<'a> {
+    Query(&'a [u8]),
+    Prepare(&'a [u8]),
+    Execute(u32),
+    Close(u32),
+    LongData,
+    Quit,
+    Other(u8),
+}
+
+#[derive(Debug)]
+pub struct Handshake<'a> {
+    pub version: &'a str,
+    pub caps:    u32,
+}
+
+#[derive(Debug)]
+pub enum Response {
+    Ok{rows: u64, status: u16},
+    Err{code: u16},
+    End{status: u16},
+    Infile,
+    Data,
+}
+
+#[derive(Debug)]
+pub struct Prepared {
+    pub id:      u32,
+    pub columns: u16,
+    pub params:  u16,
+}
+
+pub const CLIENT_SSL:           u32 = 0x0000_0800;
+pub const CLIENT_DEPRECATE_EOF: u32 = 0x0100_0000;
+
+pub const SERVER_MORE_RESULTS: u16 = 0x0008;
+
+const COM_QUIT:                u8 = 0x01;
+const COM_QUERY:               u8 = 0x03;
+const COM_STMT_PREPARE:        u8 = 0x16;
+const COM_STMT_EXECUTE:        u8 = 0x17;
+const COM_STMT_SEND_LONG_DATA: u8 = 0x18;
+const COM_STMT_CLOSE:          u8 = 0x19;
+
+named!(pub packets<&[u8],Vec<Packet>>, many1!(packet));
+
+named!(packet<&[u8],Packet>,
+       do_parse!(
+              len:     le_u24
+           >> seq:     be_u8
+           >> payload: take!(len)
+           >> (Packet {
+               seq:     seq,
+               payload: payload,
+           })
+       )
+);
+
+named!(le_u24<&[u8],u32>,
+       map!(take!(3), |b: &[u8]| u32::from(b[0]) | u32::from(b[1]) << 8 | u32::from(b[2]) << 16)
+);
+
+pub fn command(payload: &[u8]) -> Option<Command> {
+    let (&code, rest) = payload.split_first()?;
+    let id = || match le_u32(rest) {
+        Done(_, id) => Some(id),
+        _           => None,
+    };
+
+    Some(match code {
+        COM_QUERY               => Command::Query(rest),
+        COM_STMT_PREPARE        => Command::Prepare(rest),
+        COM_STMT_EXECUTE        => Command::Execute(id()?),
+        COM_STMT_CLOSE          => Command::Close(id()?),
+        COM_STMT_SEND_LONG_DATA => Command::LongData,
+        COM_QUIT                => Command::Quit,
+        code                    => Command::Other(code),
+    })
+}
+
+// The initial handshake packet sent by the server, protocol version
+// 10, which carries the server version and capability flags.
+named!(pub handshake<&[u8],Handshake>,
+       do_parse!(
+              tag!([0x0a])
+           >> version: map_res!(take_until_and_consume!("\0"), str::from_utf8)
+           >> _thread: le_u32
+           >> _auth:   take!(9)
+           >> lower:   le_u16
+           >> upper:   opt!(complete!(preceded!(take!(3), le_u16)))
+           >> (Handshake {
+               version: version,
+               caps:    u32::from(upper.unwrap_or(0)) << 16 | u32::from(lower),
+           })
+       )
+);
+
+// The client's HandshakeResponse41, or an SSLRequest which is the
+// same 32 byte prefix sent before switching to TLS.
+pub fn handshake_response(payload: &[u8]) -> Option<u32> {
+    match le_u32(payload) {
+        Done(_, caps) => Some(caps),
+        _             => None,
+    }
+}
+
+pub fn prepared(payload: &[u8]) -> Option<Prepared> {
+    match prepare_ok(payload) {
+        Done(_, p) => Some(p),
+        _          => None,
+    }
+}
+
+named!(prepare_ok<&[u8],Prepared>,
+       do_parse!(
+              tag!([0x00])
+           >> id:      le_u32
+           >> columns: le_u16
+           >> params:  le_u16
+           >> (Prepared {
+               id:      id,
+               columns: columns,
+               params:  params,
+           })
+       )
+);
+
+// Classify a response packet. An 0xFE header terminates a result
+// set, either as an EOF packet or, with CLIENT_DEPRECATE_EOF, an OK
+// packet; rows beginning with 0xFE are always longer than 0xFFFFFF.
+pub fn response(payload: &[u8], deprecate_eof: bool) -> Response {
+    match payload.split_first() {
+        Some((0x00, rest))                    => ok(rest),
+        Some((0xFF, rest))                    => err(rest),
+        Some((0xFE, rest)) if deprecate_eof   => end(ok(rest)),
+        Some((0xFE, rest)) if rest.len() < 8  => eof(rest),
+        Some((0xFB, _))                       => Response::Infile,
+        _                                     => Response::Data,
+    }
+}
+
+fn ok(rest: &[u8]) -> Response {
+    let fields = lenenc(rest).and_then(|(rows, rest)| {
+        let (_, rest) = lenenc(rest)?;
+        let status = match le_u16(rest) {
+            Done(_, status) => status,
+            _               => 0,
+        };
+        Some((rows, status))
+    });
+
+    match fields {
+        Some((rows, status)) => Response::Ok{rows, status},
+        None                 => Response::Ok{rows: 0, status: 0},
+    }
+}
+
+fn err(rest: &[u8]) -> Response {
+    match le_u16(rest) {
+        Done(_, code) => Response::Err{code},
+        _             => Response::Err{code: 0},
+    }
+}
+
+fn eof(rest: &[u8]) -> Response {
+    match rest.get(2..4) {
+        Some(&[a, b]) => Response::End{status: u16::from_le_bytes([a, b])},
+        _             => Response::End{status: 0},
+    }
+}
+
+fn end(ok: Response) -> Response {
+    match ok {
+        Response::Ok{status, ..} => Response::End{status},
+        other                    => other,
+    }
+}
+
+// Decode a length-encoded integer.
+pub fn lenenc(buf: &[u8]) -> Option<(u64, &[u8])> {
+    let (&first, rest) = buf.split_first()?;
+    let (n, len) = match first {
+        0xFC => (0, 2),
+        0xFD => (0, 3),
+        0xFE => (0, 8),
+        0xFB => return None,
+        0xFF => return None,
+        n    => (n as u64, 0),
+    };
+
+    if rest.len() < len {
+        return None;
+    }
+
+    let n = rest[..len].iter().rev().fold(n, |n, &b| n << 8 | b as u64);
+    Some((n, &rest[len..]))
+}
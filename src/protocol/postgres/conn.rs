@@ -1,24 +1,29 @@
 use std::collections::HashMap;
 use std::collections::VecDeque;
 use time::Duration;
-use nom::IResult::Done;
+use nom::IResult::{self, Done, Incomplete};
 use crate::protocol::buf::Buffer;
 use crate::time::Timestamp;
-use super::parser::{self, Message};
+use super::parser::{self, Message, SSL_REQUEST, GSSENC_REQUEST};
 use self::Message::*;
 
 pub struct Connection {
     buffer_fe: Buffer,
     buffer_be: Buffer,
     state:     State,
+    last:      Timestamp,
 }
 
 struct State {
     statements: HashMap<String, String>,
     portals:    HashMap<String, String>,
     executing:  VecDeque<Command>,
+    negotiate:  bool,
+    encrypted:  bool,
 }
 
+const MAX_BUFFER: usize = 1 << 20;
+
 impl Connection {
     pub fn new() -> Self {
         Connection {
@@ -28,70 +33,134 @@ impl Connection {
                 statements: HashMap::new(),
                 portals:    HashMap::new(),
                 executing:  VecDeque::new(),
+                negotiate:  false,
+                encrypted:  false,
             },
+            last: Timestamp::zero(),
         }
     }
 
     pub fn frontend_msg(&mut self, ts: Timestamp, buf: &[u8]) -> Option<Vec<CompletedQuery>> {
+        self.last = ts;
+
         let state = &mut self.state;
+        if state.encrypted {
+            return None;
+        }
+
         let mut buf = self.buffer_fe.buf(buf);
         let mut completed = None;
-        let mut remainder = buf.len();
 
-        if let Done(rest, msgs) = parser::parse_frontend(&buf[..]) {
-            completed = Some(msgs.iter().flat_map(|m| state.next(ts, m)).collect());
-            remainder = rest.len();
-        }
-        buf.keep(remainder);
+        let remainder = match parser::parse_frontend(&buf[..]) {
+            Done(rest, msgs)   => {
+                completed = Some(msgs.iter().flat_map(|m| state.next(ts, m)).collect());
+                rest.len()
+            },
+            Incomplete(..)     => buf.len(),
+            IResult::Error(..) => 0,
+        };
+        buf.keep(limit(remainder));
 
         completed
     }
 
     pub fn backend_msg(&mut self, ts: Timestamp, buf: &[u8]) -> Option<Vec<CompletedQuery>> {
+        self.last = ts;
+
         let state = &mut self.state;
+        let buf = match state.negotiated(buf) {
+            Some(buf) => buf,
+            None      => return None,
+        };
+
         let mut buf = self.buffer_be.buf(buf);
         let mut completed = None;
-        let mut remainder = buf.len();
 
-        if let Done(rest, msgs) = parser::parse_backend(&buf[..]) {
-            completed = Some(msgs.iter().flat_map(|m| state.next(ts, m)).collect());
-            remainder = rest.len();
-        }
-        buf.keep(remainder);
+        let remainder = match parser::parse_backend(&buf[..]) {
+            Done(rest, msgs)   => {
+                completed = Some(msgs.iter().flat_map(|m| state.next(ts, m)).collect());
+                rest.len()
+            },
+            Incomplete(..)     => buf.len(),
+            IResult::Error(..) => 0,
+        };
+        buf.keep(limit(remainder));
 
         completed
     }
+
+    pub fn is_idle(&self, ts: Timestamp, timeout: Duration) -> bool {
+        (ts - self.last) > timeout
+    }
+}
+
+// Drop buffered data that can't be a single reasonable message,
+// which will resynchronize on the next segment.
+fn limit(remainder: usize) -> usize {
+    match remainder {
+        n if n > MAX_BUFFER => 0,
+        n                   => n,
+    }
 }
 
 impl State {
     fn next(&mut self, ts: Timestamp, msg: &Message) -> Option<CompletedQuery> {
-        // println!("postgres msg {:#?}", msg);
         match *msg {
+            Startup(version)            => self.startup(version),
             Query(query)                => self.simple(ts, query),
-            Parse{statement, query, ..} => self.parse(statement, query),
+            Parse{statement, query, ..} => self.parse(ts, statement, query),
             Bind{portal, statement, ..} => self.bind(portal, statement),
             Execute{portal, ..}         => self.execute(ts, portal),
             Close{what, name}           => self.close(what, name),
+            ReadyForQuery(..)           => self.ready(ts, msg),
             RowDescription{..}          => None,
             DataRow{..}                 => None,
             Flush                       => None,
-            Sync                        => None,
+            Sync                        => self.sync(),
             ref msg                     => self.done(ts, msg)
         }
     }
 
+    fn startup(&mut self, version: i32) -> Option<CompletedQuery> {
+        self.negotiate = version == SSL_REQUEST || version == GSSENC_REQUEST;
+        None
+    }
+
+    // The server answers SSL and GSS encryption requests with a
+    // single unframed byte, 'S' or 'G' to accept or 'N' to refuse.
+    fn negotiated<'a>(&mut self, buf: &'a [u8]) -> Option<&'a [u8]> {
+        if self.encrypted {
+            return None;
+        }
+
+        if !self.negotiate {
+            return Some(buf);
+        }
+
+        self.negotiate = false;
+
+        match buf.split_first() {
+            Some((b'N', rest)) => Some(rest),
+            Some(_)            => { self.encrypted = true; None },
+            None               => Some(buf),
+        }
+    }
+
     fn simple(&mut self, ts: Timestamp, query: &str) -> Option<CompletedQuery> {
         self.executing.push_back(Command::Query{
             query: query.to_string(),
             start: ts,
+            tag:   None,
+            error: None,
         });
         None
     }
 
-    fn parse(&mut self, statement: &str, query: &str) -> Option<CompletedQuery> {
+    fn parse(&mut self, ts: Timestamp, statement: &str, query: &str) -> Option<CompletedQuery> {
         self.executing.push_back(Command::Parse{
             statement: statement.to_string(),
-            query:     query.to_string()
+            query:     query.to_string(),
+            start:     ts,
         });
         None
     }
@@ -116,20 +185,35 @@ impl State {
         match what {
             b'S' => self.statements.remove(name),
             b'P' => self.portals.remove(name),
-            _    => unreachable!(),
+            _    => None,
         };
         None
     }
 
+    fn sync(&mut self) -> Option<CompletedQuery> {
+        self.executing.push_back(Command::Sync);
+        None
+    }
+
+    // After an error the server skips extended query messages until
+    // the next Sync, so anything still pending before ReadyForQuery
+    // was aborted.
+    fn ready(&mut self, ts: Timestamp, m: &Message) -> Option<CompletedQuery> {
+        while let Some(cmd) = self.executing.front() {
+            match cmd {
+                Command::Query{..} => return self.done(ts, m),
+                Command::Sync      => return self.done(ts, m),
+                _                  => self.executing.pop_front(),
+            };
+        }
+        None
+    }
+
     fn done(&mut self, ts: Timestamp, m: &Message) -> Option<CompletedQuery> {
         self.executing.pop_front().and_then(|p| {
             match p.result(m) {
-                Result::QueryComplete{query, start} => {
-                    let duration = ts - start;
-                    Some(CompletedQuery{
-                        query:    query,
-                        duration: duration,
-                    })
+                Result::QueryComplete{query, start, tag, error} => {
+                    Some(CompletedQuery::new(query, tag, error, ts - start))
                 }
                 Result::Parsed{statement, query} => {
                     self.statements.insert(statement, query);
@@ -139,22 +223,22 @@ impl State {
                     self.portals.insert(portal, statement);
                     None
                 },
-                Result::Executed{portal, start} => {
+                Result::Executed{portal, start, tag, error} => {
                     let duration = ts - start;
                     self.portals.get(&portal).and_then(|statement| {
-                        self.statements.get(statement).and_then(|query| {
-                            Some(CompletedQuery{
-                                query:    query.clone(),
-                                duration: duration,
-                            })
+                        self.statements.get(statement).map(|query| {
+                            CompletedQuery::new(query.clone(), tag, error, duration)
                         })
                     })
                 },
+                Result::Failed{query, start, error} => {
+                    Some(CompletedQuery::new(query, None, error, ts - start))
+                },
                 Result::Continue(pending) => {
                     self.executing.push_front(pending);
                     None
                 }
-                Result::Failed => {
+                Result::Skipped => {
                     None
                 }
             }
@@ -163,10 +247,11 @@ impl State {
 }
 
 enum Command {
-    Query{query: String, start: Timestamp},
-    Parse{statement: String, query: String},
+    Query{query: String, start: Timestamp, tag: Option<String>, error: Option<String>},
+    Parse{statement: String, query: String, start: Timestamp},
     Bind{portal: String, statement: String},
     Execute{portal: String, start: Timestamp},
+    Sync,
 }
 
 impl Command {
@@ -174,36 +259,71 @@ impl Command {
         use self::Command::*;
         use self::Result::*;
 
+        let tagged = |tag: &str| Some(tag.to_string());
+        let failed = |code: Option<&str>| Some(code.unwrap_or("").to_string());
+
         match (self, m) {
-            (Query{query, start}, &ReadyForQuery(..))      => QueryComplete{query, start},
-            (query @ Query{..}, &CommandComplete(..))      => Continue(query),
-            (Query{..}, &Error(..))                        => Failed,
-            (Parse{statement, query}, &ParseComplete)      => Parsed{statement, query},
-            (Parse{..}, &Error(..))                        => Failed,
-            (Bind{portal, statement}, &BindComplete)       => Bound{portal, statement},
-            (Execute{portal, start}, &CommandComplete(..)) => Executed{portal, start},
-            (Execute{portal, start}, &EmptyQueryResponse)  => Executed{portal, start},
-            (Execute{..}, &Error(..))                      => Failed,
-            (this, _)                                      => Continue(this),
+            (Query{query, start, tag, error}, &ReadyForQuery(..)) => QueryComplete{query, start, tag, error},
+            (Query{query, start, error, ..}, &CommandComplete(t)) => Continue(Query{query, start, tag: tagged(t), error}),
+            (Query{query, start, tag, ..}, &Error(code))          => Continue(Query{query, start, tag, error: failed(code)}),
+            (Parse{statement, query, ..}, &ParseComplete)         => Parsed{statement, query},
+            (Parse{query, start, ..}, &Error(code))               => Failed{query, start, error: failed(code)},
+            (Bind{portal, statement}, &BindComplete)              => Bound{portal, statement},
+            (Bind{..}, &Error(..))                                => Skipped,
+            (Execute{portal, start}, &CommandComplete(t))         => Executed{portal, start, tag: tagged(t), error: None},
+            (Execute{portal, start}, &EmptyQueryResponse)         => Executed{portal, start, tag: None, error: None},
+            (Execute{portal, start}, &Error(code))                => Executed{portal, start, tag: None, error: failed(code)},
+            (Sync, &ReadyForQuery(..))                            => Skipped,
+            (this, _)                                             => Continue(this),
         }
     }
 }
 
 enum Result {
-    QueryComplete{query: String, start: Timestamp},
+    QueryComplete{query: String, start: Timestamp, tag: Option<String>, error: Option<String>},
     Parsed{statement: String, query: String},
     Bound{portal: String, statement: String},
-    Executed{portal: String, start: Timestamp},
+    Executed{portal: String, start: Timestamp, tag: Option<String>, error: Option<String>},
+    Failed{query: String, start: Timestamp, error: Option<String>},
     Continue(Command),
-    Failed,
+    Skipped,
 }
 
 #[derive(Debug)]
 pub struct CompletedQuery {
     pub query:    String,
+    pub command:  Option<String>,
+    pub rows:     Option<u32>,
+    pub sqlstate: Option<String>,
     pub duration: Duration,
 }
 
+impl CompletedQuery {
+    fn new(query: String, tag: Option<String>, sqlstate: Option<String>, duration: Duration) -> Self {
+        let (command, rows) = match tag {
+            Some(tag) => command(&tag),
+            None      => (None, None),
+        };
+
+        CompletedQuery {
+            query:    query,
+            command:  command,
+            rows:     rows,
+            sqlstate: sqlstate,
+            duration: duration,
+        }
+    }
+}
+
+// Split a command tag like "INSERT 0 5" or "CREATE TABLE" into
+// the command and the number of rows affected, if any.
+fn command(tag: &str) -> (Option<String>, Option<u32>) {
+    let words = tag.split(' ').collect::<Vec<_>>();
+    let name  = words.iter().take_while(|w| w.parse::<u64>().is_err()).cloned();
+    let rows  = words.last().and_then(|n| n.parse().ok());
+    (Some(name.collect::<Vec<_>>().join(" ")), rows)
+}
+
 impl ::std::fmt::Debug for Connection {
     fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
         let mut s = fmt.debug_struct("Connection");
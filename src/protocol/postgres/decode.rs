@@ -1,33 +1,102 @@
-use std::collections::HashMap;
-use crate::flow::{Addr, Flow};
-use crate::custom::Customs;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::CString;
+use time::Duration;
+use crate::flow::{Addr, Flow, FIN};
+use crate::custom::*;
+use crate::time::Timestamp;
 use super::conn::{Connection, CompletedQuery};
 
 pub struct Decoder {
-    conns: HashMap<(Addr, Addr), Connection>,
+    query:    u64,
+    command:  Option<u64>,
+    rows:     Option<u64>,
+    sqlstate: Option<u64>,
+    latency:  u64,
+    ports:    Vec<u16>,
+    values:   Values,
+    conns:    HashMap<(Addr, Addr), Connection>,
+    pending:  VecDeque<CompletedQuery>,
 }
 
+#[derive(Default)]
+struct Values {
+    query:    CString,
+    command:  CString,
+    sqlstate: CString,
+}
+
+const MAX_QUERY: usize = 512;
+
 impl Decoder {
-    pub fn new(cs: &Customs) -> Option<Decoder> {
-        let _ = cs;
-        // FIXME: WIP
-        None
+    pub fn new(cs: &Customs, ports: Vec<u16>) -> Result<Decoder, ()> {
+        Ok(Decoder{
+            query:    cs.get(POSTGRES_QUERY)?,
+            command:  cs.get(POSTGRES_COMMAND).ok(),
+            rows:     cs.get(POSTGRES_ROWS).ok(),
+            sqlstate: cs.get(POSTGRES_SQLSTATE).ok(),
+            latency:  cs.get(APP_LATENCY)?,
+            ports:    ports,
+            values:   Default::default(),
+            conns:    HashMap::new(),
+            pending:  VecDeque::new(),
+        })
     }
 
     pub fn decode(&mut self, flow: &Flow, cs: &mut Customs) -> bool {
-        let queries = match (flow.src.port, flow.dst.port) {
-            (_, 5432) => self.parse_fe(flow),
-            (5432, _) => self.parse_be(flow),
-            _         => None,
+        let queries = match (self.server(flow.src.port), self.server(flow.dst.port)) {
+            (false, true) => self.parse_fe(flow),
+            (true, false) => self.parse_be(flow),
+            _             => None,
         };
 
-        if let Some(queries) = queries {
-            println!("got PostgreSQL queries {:?}", queries);
+        if flow.tcp_flags() & FIN == FIN {
+            self.conns.remove(&(flow.src, flow.dst));
+            self.conns.remove(&(flow.dst, flow.src));
+        }
+
+        self.pending = queries.unwrap_or_default().into();
+        self.next(cs)
+    }
+
+    // Queries completed by the same segment are exported one per
+    // record.
+    pub fn next(&mut self, cs: &mut Customs) -> bool {
+        match self.pending.pop_front() {
+            Some(query) => self.append(query, cs),
+            None        => false,
+        }
+    }
+
+    pub fn clear(&mut self, ts: Timestamp, timeout: Duration) {
+        self.conns.retain(|_, c| !c.is_idle(ts, timeout))
+    }
+
+    fn server(&self, port: u16) -> bool {
+        self.ports.contains(&port)
+    }
+
+    fn append(&mut self, q: CompletedQuery, cs: &mut Customs) -> bool {
+        let values = &mut self.values;
+
+        values.query = cstring(normalize(&q.query));
+        cs.add_str(self.query, &values.query);
+        cs.add_latency(self.latency, q.duration);
+
+        if let (Some(id), Some(command)) = (self.command, q.command) {
+            values.command = cstring(command);
+            cs.add_str(id, &values.command);
+        }
+
+        if let (Some(id), Some(rows)) = (self.rows, q.rows) {
+            cs.add_u32(id, rows);
         }
 
-        // FIXME: WIP
-        let _ = cs;
-        false
+        if let (Some(id), Some(sqlstate)) = (self.sqlstate, q.sqlstate) {
+            values.sqlstate = cstring(sqlstate);
+            cs.add_str(id, &values.sqlstate);
+        }
+
+        true
     }
 
     fn parse_fe(&mut self, flow: &Flow) -> Option<Vec<CompletedQuery>> {
@@ -42,3 +111,85 @@ impl Decoder {
         conn.backend_msg(flow.timestamp, flow.payload)
     }
 }
+
+// Normalize a query for aggregation by replacing literals and
+// parameters with '?', dropping comments and collapsing whitespace.
+pub fn normalize(query: &str) -> String {
+    let mut out   = String::with_capacity(query.len().min(MAX_QUERY));
+    let mut chars = query.chars().peekable();
+    let mut space = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                while let Some(c) = chars.next() {
+                    if c == '\'' && chars.peek() != Some(&'\'') {
+                        break;
+                    } else if c == '\'' {
+                        chars.next();
+                    }
+                }
+                push(&mut out, '?', &mut space);
+            },
+            '-' if chars.peek() == Some(&'-') => {
+                while let Some(c) = chars.next() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                space = true;
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                while let Some(c) = chars.next() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+                space = true;
+            },
+            '$' if chars.peek().map_or(false, char::is_ascii_digit) => {
+                while chars.peek().map_or(false, char::is_ascii_digit) {
+                    chars.next();
+                }
+                push(&mut out, '?', &mut space);
+            },
+            c if c.is_ascii_digit() && !ident(out.chars().last(), space) => {
+                while chars.peek().map_or(false, |c| c.is_ascii_digit() || *c == '.') {
+                    chars.next();
+                }
+                push(&mut out, '?', &mut space);
+            },
+            c if c.is_whitespace() => space = true,
+            c                      => push(&mut out, c, &mut space),
+        }
+
+        if out.len() >= MAX_QUERY {
+            break;
+        }
+    }
+
+    out
+}
+
+fn push(out: &mut String, c: char, space: &mut bool) {
+    if *space && !out.is_empty() {
+        out.push(' ');
+    }
+    *space = false;
+    out.push(c);
+}
+
+// Digits following an identifier character are part of the identifier.
+fn ident(last: Option<char>, space: bool) -> bool {
+    match last {
+        Some(c) if !space => c.is_alphanumeric() || c == '_',
+        _                 => false,
+    }
+}
+
+fn cstring(s: String) -> CString {
+    CString::new(s).unwrap_or_default()
+}
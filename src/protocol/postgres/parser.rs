@@ -3,6 +3,8 @@ use nom::*;
 
 #[derive(Debug)]
 pub enum Message<'a> {
+    Startup(i32),
+
     Query(&'a str),
 
     Parse {
@@ -73,7 +75,7 @@ pub enum Message<'a> {
         secret_key: i32,
     },
 
-    Error(u8),
+    Error(Option<&'a str>),
 
     Unknown {
         tag: u8,
@@ -97,10 +99,18 @@ pub enum Auth<'a> {
     GSS,
     SSPI,
     GSSContinue(&'a [u8]),
+    SASL(&'a [u8]),
+    SASLContinue(&'a [u8]),
+    SASLFinal(&'a [u8]),
+    Other(i32),
 }
 
+pub const SSL_REQUEST:    i32 = 80877103;
+pub const GSSENC_REQUEST: i32 = 80877104;
+
 named!(pub parse_frontend<&[u8],Vec<Message>>,
        many1!(alt!(
+           startup               |
            query                 |
            parse                 |
            describe              |
@@ -137,21 +147,36 @@ named!(authentication<&[u8],Message>,
               tag!("R")
            >> len:   be_i32
            >> code:  be_i32
-           >> extra: take!(len as usize - 8)
+           >> extra: take!((len as usize).saturating_sub(8))
            >> (Message::Authentication(match code {
-               0 => Auth::Ok,
-               2 => Auth::KerberosV5,
-               3 => Auth::Cleartext,
-               5 => Auth::MD5(extra),
-               6 => Auth::SCM,
-               7 => Auth::GSS,
-               8 => Auth::GSSContinue(extra),
-               9 => Auth::SSPI,
-               _ => unreachable!(),
+               0  => Auth::Ok,
+               2  => Auth::KerberosV5,
+               3  => Auth::Cleartext,
+               5  => Auth::MD5(extra),
+               6  => Auth::SCM,
+               7  => Auth::GSS,
+               8  => Auth::GSSContinue(extra),
+               9  => Auth::SSPI,
+               10 => Auth::SASL(extra),
+               11 => Auth::SASLContinue(extra),
+               12 => Auth::SASLFinal(extra),
+               n  => Auth::Other(n),
            }))
        )
 );
 
+// StartupMessage, SSLRequest, and GSSENCRequest have no tag byte and
+// a length that always fits in the low 16 bits.
+named!(startup<&[u8],Message>,
+       do_parse!(
+              tag!("\0\0")
+           >> len:     be_u16
+           >> version: be_i32
+           >> rest:    take!((len as usize).saturating_sub(8))
+           >> (Message::Startup(version))
+       )
+);
+
 named!(query<&[u8],Message>,
        do_parse!(
               tag!("Q")
@@ -291,12 +316,25 @@ named!(error_response<&[u8],Message>,
        do_parse!(
               tag!("E")
            >> len:    be_i32
-           >> code:   be_u8
-           >> fields: take!(len as usize - 5)
-           >> (Message::Error(code))
+           >> fields: take!((len as usize).saturating_sub(4))
+           >> (Message::Error(sqlstate(fields)))
        )
 );
 
+// Find the SQLSTATE code among the error fields, each of which
+// is a type byte followed by a null-terminated string.
+fn sqlstate(fields: &[u8]) -> Option<&str> {
+    let mut fields = fields;
+    while let Some((&kind, rest)) = fields.split_first() {
+        let end = rest.iter().position(|&b| b == 0)?;
+        if kind == b'C' {
+            return str::from_utf8(&rest[..end]).ok();
+        }
+        fields = &rest[end + 1..];
+    }
+    None
+}
+
 named!(execute<&[u8],Message>,
        do_parse!(
               tag!("E")
@@ -0,0 +1,121 @@
+use std::ffi::CString;
+use time::Duration;
+use crate::protocol::tls::parser::{handshakes, Hello};
+use crate::time::Timestamp;
+use super::packet::{self, Kind};
+
+pub struct Connection {
+    crypto: Vec<(u64, Vec<u8>)>,
+    size:   usize,
+    last:   Timestamp,
+    state:  State,
+}
+
+#[derive(Debug)]
+pub struct State {
+    pub version:   u32,
+    pub dcid:      Option<CString>,
+    pub scid:      Option<CString>,
+    pub host_name: Option<CString>,
+    pub protocols: Option<CString>,
+    pub parsing:   bool,
+}
+
+// Limit on buffered CRYPTO data while waiting for a ClientHello.
+const MAX_CRYPTO: usize = 1 << 16;
+
+impl Connection {
+    pub fn new(version: u32, dcid: &[u8], scid: &[u8]) -> Self {
+        Connection {
+            crypto: Vec::new(),
+            size:   0,
+            last:   Timestamp::zero(),
+            state:  State {
+                version:   version,
+                dcid:      hex(dcid),
+                scid:      hex(scid),
+                host_name: None,
+                protocols: None,
+                parsing:   true,
+            },
+        }
+    }
+
+    pub fn parse(&mut self, ts: Timestamp, buf: &[u8]) {
+        self.last = ts;
+
+        if !self.state.parsing {
+            return;
+        }
+
+        for p in packet::packets(buf) {
+            if let (Kind::Initial, Some(frames)) = (p.kind, p.frames) {
+                for (offset, data) in packet::crypto(&frames) {
+                    self.size += data.len();
+                    self.crypto.push((offset, data.to_vec()));
+                }
+            }
+        }
+
+        self.hello();
+
+        if self.size > MAX_CRYPTO {
+            self.finish();
+        }
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    pub fn is_idle(&self, ts: Timestamp, timeout: Duration) -> bool {
+        (ts - self.last) > timeout
+    }
+
+    // CRYPTO frames may arrive out of order and span several Initial
+    // packets, so the stream is reassembled from offset zero.
+    fn hello(&mut self) {
+        self.crypto.sort_by_key(|&(offset, _)| offset);
+
+        let mut stream = Vec::new();
+        for (offset, data) in &self.crypto {
+            // The bound check runs before any arithmetic on the offset,
+            // which is an untrusted 62-bit value.
+            if *offset > stream.len() as u64 {
+                break;
+            }
+
+            let offset = *offset as usize;
+            if offset + data.len() > stream.len() {
+                stream.extend_from_slice(&data[stream.len() - offset..]);
+            }
+        }
+
+        let (hellos, _) = handshakes(&stream);
+        for hello in hellos {
+            if let Hello::Client(hello) = hello {
+                let protocols = hello.protocols().iter().map(|p| {
+                    p.to_string_lossy().into_owned()
+                }).collect::<Vec<_>>().join(",");
+
+                self.state.host_name = hello.server_name().cloned();
+                self.state.protocols = CString::new(protocols).ok().filter(|p| !p.as_bytes().is_empty());
+                self.finish();
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.crypto        = Vec::new();
+        self.size          = 0;
+        self.state.parsing = false;
+    }
+}
+
+// Connection IDs may be empty.
+fn hex(bytes: &[u8]) -> Option<CString> {
+    match bytes.is_empty() {
+        true  => None,
+        false => CString::new(bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>()).ok(),
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry::*;
+use time::Duration;
+use crate::flow::{Addr, Flow, Key};
+use crate::custom::*;
+use crate::time::Timestamp;
+use super::conn::Connection;
+use super::packet;
+
+pub struct Decoder {
+    server_name: u64,
+    columns:     Columns,
+    conns:       HashMap<(Addr, Addr), Connection>,
+}
+
+#[derive(Copy, Clone)]
+struct Columns {
+    version:   Option<u64>,
+    dcid:      Option<u64>,
+    scid:      Option<u64>,
+    protocols: Option<u64>,
+}
+
+impl Decoder {
+    pub fn new(cs: &Customs) -> Result<Decoder, ()> {
+        Ok(Decoder{
+            server_name: cs.get(TLS_SERVER_NAME)?,
+            columns:     Columns{
+                version:   cs.get(QUIC_VERSION).ok(),
+                dcid:      cs.get(QUIC_DCID).ok(),
+                scid:      cs.get(QUIC_SCID).ok(),
+                protocols: cs.get(TLS_ALPN).ok(),
+            },
+            conns:       HashMap::new(),
+        })
+    }
+
+    pub fn decode(&mut self, flow: &Flow, _cs: &mut Customs) -> bool {
+        let key = match flow.src.port < flow.dst.port {
+            true  => (flow.src, flow.dst),
+            false => (flow.dst, flow.src),
+        };
+
+        // connections start with a long header packet from the client
+        let conn = match self.conns.entry(key) {
+            Occupied(e) => e.into_mut(),
+            Vacant(e)   => match packet::packets(flow.payload).first() {
+                Some(p) if p.version != 0 => e.insert(Connection::new(p.version, p.dcid, p.scid)),
+                _                         => return false,
+            },
+        };
+
+        conn.parse(flow.timestamp, flow.payload);
+
+        false
+    }
+
+    pub fn append(&mut self, key: &Key, cs: &mut Customs) {
+        let pair = match key.1.port < key.2.port {
+            true  => (key.1, key.2),
+            false => (key.2, key.1),
+        };
+
+        let columns = self.columns;
+
+        if let Some(conn) = self.conns.get(&pair) {
+            let state = conn.state();
+
+            state.host_name.as_ref().map(|name| cs.add_str(self.server_name, name));
+            state.protocols.as_ref().and_then(|s| columns.protocols.map(|id| cs.add_str(id, s)));
+
+            columns.version.map(|id| cs.add_u32(id, state.version));
+            state.dcid.as_ref().and_then(|s| columns.dcid.map(|id| cs.add_str(id, s)));
+            state.scid.as_ref().and_then(|s| columns.scid.map(|id| cs.add_str(id, s)));
+        }
+    }
+
+    pub fn clear(&mut self, ts: Timestamp, timeout: Duration) {
+        self.conns.retain(|_, c| !c.is_idle(ts, timeout))
+    }
+}
@@ -0,0 +1,78 @@
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_128_GCM};
+use ring::aead::quic::{self, HeaderProtectionKey};
+use ring::hkdf::{KeyType, Prk, Salt, HKDF_SHA256};
+use super::packet::{V1, V2};
+
+// Initial packet protection keys (RFC 9001 section 5.2, RFC 9369
+// section 3.3), derived from the client's Destination Connection ID.
+
+const SALT_V1: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17,
+    0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad, 0xcc, 0xbb, 0x7f, 0x0a,
+];
+
+const SALT_V2: [u8; 20] = [
+    0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93,
+    0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb, 0xf9, 0xbd, 0x2e, 0xd9,
+];
+
+pub struct Keys {
+    key: LessSafeKey,
+    iv:  [u8; 12],
+    hp:  HeaderProtectionKey,
+}
+
+struct Len(usize);
+
+impl KeyType for Len {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+impl Keys {
+    pub fn client(version: u32, dcid: &[u8]) -> Option<Self> {
+        let (salt, key, iv, hp) = match version {
+            V1 => (&SALT_V1, "quic key",   "quic iv",   "quic hp"),
+            V2 => (&SALT_V2, "quicv2 key", "quicv2 iv", "quicv2 hp"),
+            _  => return None,
+        };
+
+        let initial = Salt::new(HKDF_SHA256, salt).extract(dcid);
+        let client  = Prk::new_less_safe(HKDF_SHA256, &expand(&initial, "client in", 32)?);
+
+        let mut iv_bytes = [0u8; 12];
+        iv_bytes.copy_from_slice(&expand(&client, iv, 12)?);
+
+        Some(Keys {
+            key: LessSafeKey::new(UnboundKey::new(&AES_128_GCM, &expand(&client, key, 16)?).ok()?),
+            iv:  iv_bytes,
+            hp:  HeaderProtectionKey::new(&quic::AES_128, &expand(&client, hp, 16)?).ok()?,
+        })
+    }
+
+    pub fn mask(&self, sample: &[u8]) -> Option<[u8; 5]> {
+        self.hp.new_mask(sample).ok()
+    }
+
+    pub fn open<'a>(&self, pn: u64, header: &[u8], payload: &'a mut [u8]) -> Option<&'a mut [u8]> {
+        let mut nonce = self.iv;
+        for (n, b) in nonce[4..].iter_mut().zip(pn.to_be_bytes().iter()) {
+            *n ^= b;
+        }
+
+        let nonce = Nonce::assume_unique_for_key(nonce);
+        self.key.open_in_place(nonce, Aad::from(header), payload).ok().map(|p| &mut p[..])
+    }
+}
+
+// HKDF-Expand-Label from TLS 1.3 with an empty context.
+fn expand(prk: &Prk, label: &str, len: usize) -> Option<Vec<u8>> {
+    let label  = [b"tls13 ", label.as_bytes()].concat();
+    let length = (len as u16).to_be_bytes();
+    let info   = [&length[..], &[label.len() as u8], &label, &[0]];
+
+    let mut out = vec![0u8; len];
+    prk.expand(&info, Len(len)).ok()?.fill(&mut out).ok()?;
+    Some(out)
+}
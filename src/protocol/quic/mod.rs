@@ -0,0 +1,7 @@
+pub mod conn;
+pub mod decode;
+pub mod packet;
+
+mod keys;
+
+pub use self::decode::*;
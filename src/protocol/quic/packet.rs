@@ -0,0 +1,175 @@
+use byteorder::{ByteOrder, BigEndian as BE};
+use super::keys::Keys;
+
+pub const V1: u32 = 0x0000_0001;
+pub const V2: u32 = 0x6b33_43cf;
+
+#[derive(Debug)]
+pub struct Packet<'a> {
+    pub version: u32,
+    pub kind:    Kind,
+    pub dcid:    &'a [u8],
+    pub scid:    &'a [u8],
+    pub frames:  Option<Vec<u8>>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Kind {
+    Initial,
+    ZeroRTT,
+    Handshake,
+    Retry,
+    Negotiation,
+    Unknown,
+}
+
+// Long header packets coalesced in a datagram (RFC 9000 section
+// 12.2), with the frames of any client Initial packet decrypted.
+pub fn packets(mut buf: &[u8]) -> Vec<Packet> {
+    let mut packets = Vec::new();
+
+    while let Some((packet, rest)) = packet(buf) {
+        packets.push(packet);
+        buf = rest;
+    }
+
+    packets
+}
+
+fn packet(buf: &[u8]) -> Option<(Packet, &[u8])> {
+    let first = *buf.first()?;
+    if first & 0x80 == 0 || buf.len() < 7 {
+        return None;
+    }
+
+    let version = BE::read_u32(&buf[1..5]);
+    let (dcid, rest) = cid(&buf[5..])?;
+    let (scid, rest) = cid(rest)?;
+
+    let kind = match (version, (first >> 4) & 0x03) {
+        (0,  _) => Kind::Negotiation,
+        (V1, 0) => Kind::Initial,
+        (V1, 1) => Kind::ZeroRTT,
+        (V1, 2) => Kind::Handshake,
+        (V1, _) => Kind::Retry,
+        (V2, 1) => Kind::Initial,
+        (V2, 2) => Kind::ZeroRTT,
+        (V2, 3) => Kind::Handshake,
+        (V2, _) => Kind::Retry,
+        _       => Kind::Unknown,
+    };
+
+    let mut packet = Packet {
+        version: version,
+        kind:    kind,
+        dcid:    dcid,
+        scid:    scid,
+        frames:  None,
+    };
+
+    let rest = match kind {
+        Kind::Initial => {
+            let (token, rest) = varint(rest)?;
+            rest.get(token as usize..)?
+        },
+        Kind::ZeroRTT | Kind::Handshake => rest,
+        _                               => return Some((packet, &[])),
+    };
+
+    let (len, rest) = varint(rest)?;
+    let offset = buf.len() - rest.len();
+    let end    = offset.checked_add(len as usize).filter(|&n| n <= buf.len())?;
+
+    if kind == Kind::Initial {
+        packet.frames = Keys::client(version, dcid).and_then(|keys| {
+            unprotect(&keys, &buf[..end], offset)
+        });
+    }
+
+    Some((packet, &buf[end..]))
+}
+
+// Remove header protection and decrypt the payload (RFC 9001
+// section 5.4). The truncated packet number is used as-is, which is
+// correct for the first packets of a connection.
+fn unprotect(keys: &Keys, packet: &[u8], offset: usize) -> Option<Vec<u8>> {
+    let sample = packet.get(offset + 4..offset + 20)?;
+    let mask   = keys.mask(sample)?;
+
+    let mut packet = packet.to_vec();
+    packet[0] ^= mask[0] & 0x0f;
+
+    let len = (packet[0] & 0x03) as usize + 1;
+    let mut pn = 0u64;
+    for (i, m) in mask[1..=len].iter().enumerate() {
+        packet[offset + i] ^= m;
+        pn = pn << 8 | packet[offset + i] as u64;
+    }
+
+    let (header, payload) = packet.split_at_mut(offset + len);
+    keys.open(pn, header, payload).map(|frames| frames.to_vec())
+}
+
+// CRYPTO frame offsets and data, skipping the other frames allowed
+// in Initial packets.
+pub fn crypto(mut buf: &[u8]) -> Vec<(u64, &[u8])> {
+    let mut frames = Vec::new();
+
+    while let Some((kind, rest)) = varint(buf) {
+        buf = match kind {
+            0x00 | 0x01 => Some(rest),
+            0x02 | 0x03 => ack(kind, rest),
+            0x06        => varint(rest).and_then(|(offset, rest)| {
+                let (len, rest) = varint(rest)?;
+                let data = rest.get(..len as usize)?;
+                frames.push((offset, data));
+                Some(&rest[data.len()..])
+            }),
+            0x1c | 0x1d => close(kind, rest),
+            _           => None,
+        }.unwrap_or(&[]);
+    }
+
+    frames
+}
+
+fn ack(kind: u64, buf: &[u8]) -> Option<&[u8]> {
+    let (_largest, rest) = varint(buf)?;
+    let (_delay,   rest) = varint(rest)?;
+    let (count,    rest) = varint(rest)?;
+    let (_first,   rest) = varint(rest)?;
+
+    let ecn = match kind {
+        0x03 => 3,
+        _    => 0,
+    };
+
+    (0..count * 2 + ecn).try_fold(rest, |rest, _| varint(rest).map(|(_, rest)| rest))
+}
+
+fn close(kind: u64, buf: &[u8]) -> Option<&[u8]> {
+    let (_code, mut rest) = varint(buf)?;
+    if kind == 0x1c {
+        rest = varint(rest)?.1;
+    }
+    let (len, rest) = varint(rest)?;
+    rest.get(len as usize..)
+}
+
+fn cid(buf: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (&len, rest) = buf.split_first()?;
+    match len as usize {
+        n if n <= 20 && rest.len() >= n => Some(rest.split_at(n)),
+        _                               => None,
+    }
+}
+
+// Variable-length integers with a 2-bit length prefix.
+pub fn varint(buf: &[u8]) -> Option<(u64, &[u8])> {
+    let first = *buf.first()?;
+    let len   = 1 << (first >> 6);
+    let bytes = buf.get(..len)?;
+
+    let value = bytes[1..].iter().fold((first & 0x3f) as u64, |v, &b| v << 8 | b as u64);
+    Some((value, &buf[len..]))
+}
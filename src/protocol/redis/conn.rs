@@ -0,0 +1,275 @@
+use std::collections::VecDeque;
+use time::Duration;
+use nom::IResult::{self, Done, Incomplete};
+use crate::protocol::buf::Buffer;
+use crate::time::Timestamp;
+use super::parser::{self, Value};
+
+pub struct Connection {
+    buffer_c:   Buffer,
+    buffer_s:   Buffer,
+    pending:    VecDeque<Request>,
+    subscribed: bool,
+    overflow:   bool,
+    last:       Timestamp,
+}
+
+#[derive(Debug)]
+pub struct Request {
+    pub command:  String,
+    pub key:      Option<Vec<u8>>,
+    pub start:    Timestamp,
+    pub confirms: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct Reply {
+    pub command:  String,
+    pub key:      Option<Vec<u8>>,
+    pub kind:     &'static str,
+    pub error:    Option<String>,
+    pub duration: Duration,
+}
+
+const MAX_BUFFER:  usize = 1 << 20;
+const MAX_PENDING: usize = 1024;
+const MAX_COMMAND: usize = 32;
+const MAX_ERROR:   usize = 128;
+
+// Commands whose first argument is not a key, or must not be
+// exported, such as AUTH and HELLO which may carry credentials.
+const KEYLESS: &[&str] = &[
+    "ACL", "ASKING", "AUTH", "BGREWRITEAOF", "BGSAVE", "CLIENT", "CLUSTER",
+    "COMMAND", "CONFIG", "DBSIZE", "DEBUG", "DISCARD", "ECHO", "EVAL",
+    "EVALSHA", "EVALSHA_RO", "EVAL_RO", "EXEC", "FCALL", "FCALL_RO",
+    "FLUSHALL", "FLUSHDB", "FUNCTION", "HELLO", "INFO", "KEYS", "LASTSAVE",
+    "LATENCY", "MEMORY", "MIGRATE", "MODULE", "MONITOR", "MULTI", "OBJECT",
+    "PING", "PSUBSCRIBE", "PSYNC", "PUBLISH", "PUBSUB", "PUNSUBSCRIBE",
+    "QUIT", "READONLY", "READWRITE", "REPLICAOF", "RESET", "ROLE", "SAVE",
+    "SCAN", "SCRIPT", "SELECT", "SHUTDOWN", "SLAVEOF", "SLOWLOG",
+    "SPUBLISH", "SSUBSCRIBE", "SUBSCRIBE", "SUNSUBSCRIBE", "SWAPDB", "SYNC",
+    "TIME", "UNSUBSCRIBE", "UNWATCH", "WAIT", "XREAD", "XREADGROUP",
+];
+
+impl Connection {
+    pub fn new() -> Self {
+        Connection {
+            buffer_c:   Buffer::new(),
+            buffer_s:   Buffer::new(),
+            pending:    VecDeque::new(),
+            subscribed: false,
+            overflow:   false,
+            last:       Timestamp::zero(),
+        }
+    }
+
+    pub fn client_msg(&mut self, ts: Timestamp, buf: &[u8]) {
+        self.last = ts;
+
+        if self.overflow {
+            return;
+        }
+
+        let pending = &mut self.pending;
+        let subscribed = &mut self.subscribed;
+
+        let mut buf = self.buffer_c.buf(buf);
+        let remainder = match parser::requests(&buf[..]) {
+            Done(rest, reqs) => {
+                for args in reqs {
+                    if let Some(req) = request(ts, &args) {
+                        match &req.command[..] {
+                            "SUBSCRIBE" | "PSUBSCRIBE" | "SSUBSCRIBE" => *subscribed = true,
+                            "RESET"                                   => *subscribed = false,
+                            _                                         => (),
+                        }
+                        pending.push_back(req);
+                    }
+                }
+                rest.len()
+            },
+            Incomplete(..)     => buf.len(),
+            IResult::Error(..) => 0,
+        };
+        buf.keep(limit(remainder));
+
+        // Replies can't be matched to requests once too many are
+        // outstanding, so the rest of the connection is ignored.
+        if self.pending.len() > MAX_PENDING {
+            self.overflow = true;
+            self.pending.clear();
+            self.buffer_c.clear();
+            self.buffer_s.clear();
+        }
+    }
+
+    pub fn server_msg(&mut self, ts: Timestamp, buf: &[u8]) -> Option<Vec<Reply>> {
+        self.last = ts;
+
+        if self.overflow {
+            return None;
+        }
+
+        let pending = &mut self.pending;
+        let subscribed = &mut self.subscribed;
+
+        let mut buf = self.buffer_s.buf(buf);
+        let mut replies = None;
+
+        let remainder = match parser::replies(&buf[..]) {
+            Done(rest, values) => {
+                let mut rs = Vec::new();
+                for v in &values {
+                    if push(v, *subscribed) {
+                        continue;
+                    }
+
+                    let done = unsubscribed(v);
+                    *subscribed &= !done;
+
+                    // (un)subscribe commands are confirmed once per
+                    // channel and answered by the last confirmation.
+                    if let Some(req) = pending.front_mut().filter(|_| confirmation(v)) {
+                        let more = match req.confirms {
+                            Some(n) => n > 1,
+                            None    => !done,
+                        };
+                        if more {
+                            req.confirms = req.confirms.map(|n| n - 1);
+                            continue;
+                        }
+                    }
+
+                    rs.extend(pending.pop_front().map(|req| reply(ts, req, v)));
+                }
+                replies = Some(rs);
+                rest.len()
+            },
+            Incomplete(..)     => buf.len(),
+            IResult::Error(..) => 0,
+        };
+        buf.keep(limit(remainder));
+
+        replies
+    }
+
+    pub fn is_idle(&self, ts: Timestamp, timeout: Duration) -> bool {
+        (ts - self.last) > timeout
+    }
+}
+
+fn request(ts: Timestamp, args: &[&[u8]]) -> Option<Request> {
+    let (command, args) = args.split_first()?;
+    let command = String::from_utf8_lossy(command).to_ascii_uppercase();
+    let command = command.chars().take(MAX_COMMAND).collect::<String>();
+
+    let key = match KEYLESS.contains(&&command[..]) {
+        true  => None,
+        false => args.first().map(|key| key.to_vec()),
+    };
+
+    // UNSUBSCRIBE without channels is confirmed once per remaining
+    // subscription, until none are left.
+    let confirms = match &command[..] {
+        "SUBSCRIBE" | "PSUBSCRIBE" | "SSUBSCRIBE"       => Some(args.len().max(1)),
+        "UNSUBSCRIBE" | "PUNSUBSCRIBE" | "SUNSUBSCRIBE" => Some(args.len()).filter(|&n| n > 0),
+        _                                               => Some(1),
+    };
+
+    Some(Request {
+        command:  command,
+        key:      key,
+        start:    ts,
+        confirms: confirms,
+    })
+}
+
+fn reply(ts: Timestamp, req: Request, value: &Value) -> Reply {
+    let error = match *value {
+        Value::Error(e)     => Some(e),
+        Value::BlobError(e) => Some(e),
+        _                   => None,
+    }.map(|e| String::from_utf8_lossy(&e[..e.len().min(MAX_ERROR)]).into_owned());
+
+    Reply {
+        command:  req.command,
+        key:      req.key,
+        kind:     kind(value),
+        error:    error,
+        duration: ts - req.start,
+    }
+}
+
+// Out-of-band messages which don't answer a request: RESP3 pushes
+// other than (un)subscribe confirmations, and RESP2 pub/sub messages
+// received while subscribed.
+fn push(value: &Value, subscribed: bool) -> bool {
+    match *value {
+        Value::Push(..)                    => !confirmation(value),
+        Value::Array(ref vs) if subscribed => match vs.first() {
+            Some(Value::Bulk(kind)) => [&b"message"[..], b"pmessage", b"smessage"].contains(kind),
+            _                       => false,
+        },
+        _                                  => false,
+    }
+}
+
+// Confirmation of a subscribe or unsubscribe, sent as an array by
+// RESP2 and as a push by RESP3.
+fn confirmation(value: &Value) -> bool {
+    match *value {
+        Value::Array(ref vs) | Value::Push(ref vs) => match &vs[..] {
+            [Value::Bulk(kind), _, Value::Integer(_)] => kind.ends_with(b"subscribe"),
+            _                                         => false,
+        },
+        _                                          => false,
+    }
+}
+
+// Confirmation of an unsubscribe leaving no subscriptions, after
+// which RESP2 arrays are replies again.
+fn unsubscribed(value: &Value) -> bool {
+    match *value {
+        Value::Array(ref vs) | Value::Push(ref vs) => match &vs[..] {
+            [Value::Bulk(kind), _, Value::Integer(0)] => kind.ends_with(b"unsubscribe"),
+            _                                         => false,
+        },
+        _                                          => false,
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match *value {
+        Value::Simple(..)    => "simple",
+        Value::Error(..)     => "error",
+        Value::Integer(..)   => "integer",
+        Value::Bulk(..)      => "bulk",
+        Value::BlobError(..) => "error",
+        Value::Verbatim(..)  => "verbatim",
+        Value::Double        => "double",
+        Value::Boolean       => "boolean",
+        Value::BigNumber     => "bignum",
+        Value::Null          => "null",
+        Value::Array(..)     => "array",
+        Value::Map(..)       => "map",
+        Value::Set(..)       => "set",
+        Value::Push(..)      => "push",
+    }
+}
+
+fn limit(remainder: usize) -> usize {
+    match remainder {
+        n if n > MAX_BUFFER => 0,
+        n                   => n,
+    }
+}
+
+impl ::std::fmt::Debug for Connection {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        let mut s = fmt.debug_struct("Connection");
+        s.field("buffer_c", &self.buffer_c.len());
+        s.field("buffer_s", &self.buffer_s.len());
+        s.field("pending", &self.pending.len());
+        s.finish()
+    }
+}
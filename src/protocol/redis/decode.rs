@@ -0,0 +1,136 @@
+use std::collections::{HashMap, VecDeque};
+use std::ffi::CString;
+use time::Duration;
+use crate::flow::{Addr, Flow, FIN};
+use crate::custom::*;
+use crate::time::Timestamp;
+use super::conn::{Connection, Reply};
+
+pub struct Decoder {
+    command: u64,
+    key:     Option<u64>,
+    kind:    Option<u64>,
+    error:   Option<u64>,
+    latency: u64,
+    depth:   usize,
+    ports:   Vec<u16>,
+    values:  Values,
+    conns:   HashMap<(Addr, Addr), Connection>,
+    pending: VecDeque<Reply>,
+}
+
+#[derive(Default)]
+struct Values {
+    command: CString,
+    key:     CString,
+    kind:    CString,
+    error:   CString,
+}
+
+impl Decoder {
+    pub fn new(cs: &Customs, ports: Vec<u16>) -> Result<Decoder, ()> {
+        Ok(Decoder{
+            command: cs.get(REDIS_COMMAND)?,
+            key:     cs.get(REDIS_KEY).ok(),
+            kind:    cs.get(REDIS_REPLY_TYPE).ok(),
+            error:   cs.get(REDIS_ERROR).ok(),
+            latency: cs.get(APP_LATENCY)?,
+            depth:   1,
+            ports:   ports,
+            values:  Default::default(),
+            conns:   HashMap::new(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    pub fn key_depth(&mut self, depth: usize) {
+        self.depth = depth;
+    }
+
+    pub fn decode(&mut self, flow: &Flow, cs: &mut Customs) -> bool {
+        let replies = match (self.server(flow.src.port), self.server(flow.dst.port)) {
+            (false, true) => { self.parse_client(flow); None },
+            (true, false) => self.parse_server(flow),
+            _             => None,
+        };
+
+        if flow.tcp_flags() & FIN == FIN {
+            self.conns.remove(&(flow.src, flow.dst));
+            self.conns.remove(&(flow.dst, flow.src));
+        }
+
+        self.pending = replies.unwrap_or_default().into();
+        self.next(cs)
+    }
+
+    // Replies completed by the same segment are exported one per
+    // record.
+    pub fn next(&mut self, cs: &mut Customs) -> bool {
+        match self.pending.pop_front() {
+            Some(reply) => self.append(reply, cs),
+            None        => false,
+        }
+    }
+
+    pub fn clear(&mut self, ts: Timestamp, timeout: Duration) {
+        self.conns.retain(|_, c| !c.is_idle(ts, timeout))
+    }
+
+    fn server(&self, port: u16) -> bool {
+        self.ports.contains(&port)
+    }
+
+    fn append(&mut self, r: Reply, cs: &mut Customs) -> bool {
+        let values = &mut self.values;
+
+        values.command = cstring(r.command.into_bytes());
+        cs.add_str(self.command, &values.command);
+        cs.add_latency(self.latency, r.duration);
+
+        if let (Some(id), Some(key)) = (self.key, r.key) {
+            if self.depth > 0 {
+                values.key = cstring(prefix(&key, self.depth).to_vec());
+                cs.add_str(id, &values.key);
+            }
+        }
+
+        if let Some(id) = self.kind {
+            values.kind = cstring(r.kind.as_bytes().to_vec());
+            cs.add_str(id, &values.kind);
+        }
+
+        if let (Some(id), Some(error)) = (self.error, r.error) {
+            values.error = cstring(error.into_bytes());
+            cs.add_str(id, &values.error);
+        }
+
+        true
+    }
+
+    fn parse_client(&mut self, flow: &Flow) {
+        let addr = (flow.src, flow.dst);
+        let conn = self.conns.entry(addr).or_insert_with(Connection::new);
+        conn.client_msg(flow.timestamp, flow.payload)
+    }
+
+    fn parse_server(&mut self, flow: &Flow) -> Option<Vec<Reply>> {
+        let addr = (flow.dst, flow.src);
+        let conn = self.conns.entry(addr).or_insert_with(Connection::new);
+        conn.server_msg(flow.timestamp, flow.payload)
+    }
+}
+
+// The key prefix is the first depth segments of a key using the
+// conventional ':' separator, e.g. "user:42:profile" at depth 2 is
+// "user:42".
+pub fn prefix(key: &[u8], depth: usize) -> &[u8] {
+    match key.iter().enumerate().filter(|&(_, &b)| b == b':').nth(depth.saturating_sub(1)) {
+        Some((n, _)) => &key[..n],
+        None         => key,
+    }
+}
+
+fn cstring(mut s: Vec<u8>) -> CString {
+    s.retain(|&b| b != 0);
+    CString::new(s).unwrap_or_default()
+}
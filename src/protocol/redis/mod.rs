@@ -0,0 +1,5 @@
+mod conn;
+mod decode;
+mod parser;
+
+pub use self::decode::*;
@@ -0,0 +1,178 @@
+use std::convert::TryFrom;
+use std::str;
+use nom::*;
+use nom::IResult::{Done, Incomplete};
+
+#[derive(Debug, PartialEq)]
+pub enum Value<'a> {
+    Simple(&'a [u8]),
+    Error(&'a [u8]),
+    Integer(i64),
+    Bulk(&'a [u8]),
+    BlobError(&'a [u8]),
+    Verbatim(&'a [u8]),
+    Double,
+    Boolean,
+    BigNumber,
+    Null,
+    Array(Vec<Value<'a>>),
+    Map(usize),
+    Set(usize),
+    Push(Vec<Value<'a>>),
+}
+
+const MAX_DEPTH: usize = 16;
+const MAX_ITEMS: i64   = 1 << 20;
+
+// Parse requests, which are arrays of bulk strings or inline
+// commands separated by whitespace.
+pub fn request(buf: &[u8]) -> IResult<&[u8], Vec<&[u8]>> {
+    match buf.first() {
+        Some(b'*') => match value(buf, 0) {
+            Done(rest, Value::Array(vs)) => Done(rest, vs.into_iter().flat_map(arg).collect()),
+            Done(rest, _)                => Done(rest, Vec::new()),
+            Incomplete(n)                => Incomplete(n),
+            IResult::Error(e)            => IResult::Error(e),
+        },
+        Some(_) => match line(buf) {
+            Done(rest, line)  => Done(rest, line.split(u8::is_ascii_whitespace).filter(|s| !s.is_empty()).collect()),
+            Incomplete(n)     => Incomplete(n),
+            IResult::Error(e) => IResult::Error(e),
+        },
+        None => Incomplete(Needed::Size(1)),
+    }
+}
+
+pub fn requests(buf: &[u8]) -> IResult<&[u8], Vec<Vec<&[u8]>>> {
+    many(buf, request)
+}
+
+pub fn replies(buf: &[u8]) -> IResult<&[u8], Vec<Value>> {
+    many(buf, reply)
+}
+
+pub fn reply(buf: &[u8]) -> IResult<&[u8], Value> {
+    value(buf, 0)
+}
+
+// Parse as many complete messages as are available, which is only
+// incomplete when not even one message could be parsed.
+fn many<'a, T, F>(buf: &'a [u8], f: F) -> IResult<&'a [u8], Vec<T>>
+    where F: Fn(&'a [u8]) -> IResult<&'a [u8], T>
+{
+    let mut rest = buf;
+    let mut vec  = Vec::new();
+
+    while !rest.is_empty() {
+        match f(rest) {
+            Done(more, v)                       => { rest = more; vec.push(v) },
+            Incomplete(n) if vec.is_empty()     => return Incomplete(n),
+            IResult::Error(e) if vec.is_empty() => return IResult::Error(e),
+            _                                   => break,
+        }
+    }
+
+    Done(rest, vec)
+}
+
+fn arg(v: Value) -> Option<&[u8]> {
+    match v {
+        Value::Bulk(s)   => Some(s),
+        Value::Simple(s) => Some(s),
+        _                => None,
+    }
+}
+
+fn value(buf: &[u8], depth: usize) -> IResult<&[u8], Value> {
+    if depth > MAX_DEPTH {
+        return IResult::Error(ErrorKind::Custom(0));
+    }
+
+    let (kind, rest) = match buf.split_first() {
+        Some((&kind, rest)) => (kind, rest),
+        None                => return Incomplete(Needed::Size(1)),
+    };
+
+    let (rest, line) = try_parse!(rest, line);
+
+    match kind {
+        b'+' => Done(rest, Value::Simple(line)),
+        b'-' => Done(rest, Value::Error(line)),
+        b':' => Done(rest, Value::Integer(try_parse!(rest, call!(number, line)).1)),
+        b',' => Done(rest, Value::Double),
+        b'#' => Done(rest, Value::Boolean),
+        b'(' => Done(rest, Value::BigNumber),
+        b'_' => Done(rest, Value::Null),
+        b'$' => blob(rest, line, Value::Bulk),
+        b'!' => blob(rest, line, Value::BlobError),
+        b'=' => blob(rest, line, Value::Verbatim),
+        b'*' => aggregate(rest, line, depth, 1, Value::Array),
+        b'>' => aggregate(rest, line, depth, 1, Value::Push),
+        b'~' => aggregate(rest, line, depth, 1, |vs| Value::Set(vs.len())),
+        b'%' => aggregate(rest, line, depth, 2, |vs| Value::Map(vs.len() / 2)),
+        b'|' => {
+            let (rest, _) = try_parse!(rest, call!(aggregate, line, depth, 2, |_| Value::Null));
+            value(rest, depth + 1)
+        },
+        _    => IResult::Error(ErrorKind::Custom(1)),
+    }
+}
+
+fn blob<'a, F>(buf: &'a [u8], len: &[u8], f: F) -> IResult<&'a [u8], Value<'a>>
+    where F: Fn(&'a [u8]) -> Value<'a>
+{
+    let (_, len) = try_parse!(buf, call!(number, len));
+    if len < 0 {
+        return Done(buf, Value::Null);
+    }
+
+    // Lengths are untrusted and may not fit in a usize on 32-bit.
+    let len = usize::try_from(len).ok();
+    let (len, end) = match len.and_then(|n| Some((n, n.checked_add(2)?))) {
+        Some((len, end)) => (len, end),
+        None             => return IResult::Error(ErrorKind::Custom(4)),
+    };
+
+    if buf.len() < end {
+        return Incomplete(Needed::Size(end - buf.len()));
+    }
+
+    Done(&buf[end..], f(&buf[..len]))
+}
+
+fn aggregate<'a, F>(buf: &'a [u8], len: &[u8], depth: usize, n: usize, f: F) -> IResult<&'a [u8], Value<'a>>
+    where F: Fn(Vec<Value<'a>>) -> Value<'a>
+{
+    let (_, len) = try_parse!(buf, call!(number, len));
+    if len < 0 {
+        return Done(buf, Value::Null);
+    } else if len > MAX_ITEMS {
+        return IResult::Error(ErrorKind::Custom(2));
+    }
+
+    let mut rest = buf;
+    let mut vec  = Vec::with_capacity((len as usize * n).min(1024));
+
+    for _ in 0..len as usize * n {
+        let (more, v) = try_parse!(rest, call!(value, depth + 1));
+        rest = more;
+        vec.push(v);
+    }
+
+    Done(rest, f(vec))
+}
+
+fn line(buf: &[u8]) -> IResult<&[u8], &[u8]> {
+    match buf.iter().position(|&b| b == b'\n') {
+        Some(n) if n > 0 && buf[n - 1] == b'\r' => Done(&buf[n + 1..], &buf[..n - 1]),
+        Some(n)                                 => Done(&buf[n + 1..], &buf[..n]),
+        None                                    => Incomplete(Needed::Unknown),
+    }
+}
+
+fn number<'a>(buf: &'a [u8], line: &[u8]) -> IResult<&'a [u8], i64> {
+    match str::from_utf8(line).ok().and_then(|s| s.parse().ok()) {
+        Some(n) => Done(buf, n),
+        None    => IResult::Error(ErrorKind::Custom(3)),
+    }
+}
@@ -1,7 +1,10 @@
 use std::ffi::CString;
+use std::mem;
 use time::Duration;
 use nom::IResult::*;
+use super::fingerprint;
 use super::parser::*;
+use super::x509::Certificate;
 use crate::protocol::buf::Buffer;
 use crate::time::Timestamp;
 
@@ -17,9 +20,22 @@ pub struct State {
     pub server_ver:   Option<Version>,
     pub host_name:    Option<CString>,
     pub cipher_suite: Option<CipherSuite>,
+    pub ja3:          Option<CString>,
+    pub ja3s:         Option<CString>,
+    pub ja4:          Option<CString>,
+    pub protocol:     Option<CString>,
+    pub certificate:  Option<Certificate>,
     pub parsing:      bool,
+    fragment:         Vec<u8>,
 }
 
+// Largest TLSCiphertext record including its header.
+const MAX_RECORD: usize = 5 + (1 << 14) + 2048;
+
+// Largest partial handshake message kept while waiting for the
+// records which complete it, enough for most certificate chains.
+const MAX_FRAGMENT: usize = 1 << 16;
+
 impl Connection {
     pub fn new() -> Self {
         Connection {
@@ -64,37 +80,75 @@ impl State {
             server_ver:   None,
             host_name:    None,
             cipher_suite: None,
+            ja3:          None,
+            ja3s:         None,
+            ja4:          None,
+            protocol:     None,
+            certificate:  None,
             parsing:      true,
+            fragment:     Vec::new(),
         }
     }
 
     fn update(&mut self, rs: Vec<Record>, rest: &[u8]) -> usize {
         for r in rs {
             match r {
-                Record::Hello(Hello::Client(ver, host))  => {
-                    self.client_ver = Some(ver);
-                    self.host_name  = host;
-                },
-                Record::Hello(Hello::Server(ver, suite)) => {
-                    self.server_ver   = Some(ver);
-                    self.cipher_suite = Some(suite);
-                },
-                Record::Hello(Hello::Done)               => {
+                Record::Handshake(data)  => self.handshakes(data),
+                Record::Other(0x17)      => {
                     self.parsing = false;
                 },
-                Record::Unsupported(ver)                 => {
+                Record::Unsupported(ver) => {
                     self.client_ver = Some(ver);
                     self.server_ver = Some(ver);
                     self.parsing    = false;
                 },
-                _                                        => (),
+                _                        => (),
             }
         }
         rest.len()
     }
 
+    // Handshake messages may be fragmented across records, so the
+    // start of an incomplete message is kept until the rest arrives.
+    fn handshakes(&mut self, data: &[u8]) {
+        let mut buf = mem::take(&mut self.fragment);
+        buf.extend_from_slice(data);
+
+        let (hs, rest) = handshakes(&buf);
+        hs.into_iter().for_each(|h| self.handshake(h));
+
+        match rest.len() {
+            n if n > MAX_FRAGMENT => self.parsing = false,
+            _                     => self.fragment = rest.to_vec(),
+        }
+    }
+
+    fn handshake(&mut self, hello: Hello) {
+        match hello {
+            Hello::Client(hello)      => {
+                self.client_ver = Some(hello.version);
+                self.host_name  = hello.server_name().cloned();
+                self.ja3        = CString::new(fingerprint::ja3(&hello)).ok();
+                self.ja4        = CString::new(fingerprint::ja4(&hello)).ok();
+            },
+            Hello::Server(hello)      => {
+                self.server_ver   = Some(hello.selected_version());
+                self.cipher_suite = Some(hello.cipher);
+                self.protocol     = hello.protocol().cloned();
+                self.ja3s         = CString::new(fingerprint::ja3s(&hello)).ok();
+            },
+            Hello::Certificate(cert)  => {
+                self.certificate = self.certificate.take().or(cert);
+            },
+            Hello::Done               => {
+                self.parsing = false;
+            },
+            Hello::Other(..)          => (),
+        }
+    }
+
     fn partial(&mut self, len: usize) -> usize {
-        if len > 8192 {
+        if len > MAX_RECORD {
             self.parsing = false;
             return 0;
         }
@@ -8,19 +8,44 @@ use crate::time::Timestamp;
 use super::conn::Connection;
 
 pub struct Decoder {
-    server_name:  u64,
+    server_name: u64,
+    columns:     Columns,
+    conns:       HashMap<(Addr, Addr), Connection>,
+}
+
+#[derive(Copy, Clone)]
+struct Columns {
     server_ver:   Option<u64>,
     cipher_suite: Option<u64>,
-    conns:        HashMap<(Addr, Addr), Connection>,
+    ja3:          Option<u64>,
+    ja3s:         Option<u64>,
+    ja4:          Option<u64>,
+    alpn:         Option<u64>,
+    subject:      Option<u64>,
+    sans:         Option<u64>,
+    issuer:       Option<u64>,
+    serial:       Option<u64>,
+    not_after:    Option<u64>,
 }
 
 impl Decoder {
     pub fn new(cs: &Customs) -> Result<Decoder, ()> {
         Ok(Decoder{
-            server_name:  cs.get(TLS_SERVER_NAME)?,
-            server_ver:   cs.get(TLS_SERVER_VERSION).ok(),
-            cipher_suite: cs.get(TLS_CIPHER_SUITE).ok(),
-            conns:        HashMap::new(),
+            server_name: cs.get(TLS_SERVER_NAME)?,
+            columns:     Columns{
+                server_ver:   cs.get(TLS_SERVER_VERSION).ok(),
+                cipher_suite: cs.get(TLS_CIPHER_SUITE).ok(),
+                ja3:          cs.get(TLS_JA3).ok(),
+                ja3s:         cs.get(TLS_JA3S).ok(),
+                ja4:          cs.get(TLS_JA4).ok(),
+                alpn:         cs.get(TLS_ALPN).ok(),
+                subject:      cs.get(TLS_CERT_SUBJECT).ok(),
+                sans:         cs.get(TLS_CERT_SAN).ok(),
+                issuer:       cs.get(TLS_CERT_ISSUER).ok(),
+                serial:       cs.get(TLS_CERT_SERIAL).ok(),
+                not_after:    cs.get(TLS_CERT_NOT_AFTER).ok(),
+            },
+            conns:       HashMap::new(),
         })
     }
 
@@ -38,24 +63,36 @@ impl Decoder {
     }
 
     pub fn append(&mut self, key: &Key, cs: &mut Customs) {
-        let server_name  = self.server_name;
-        let server_ver   = self.server_ver;
-        let cipher_suite = self.cipher_suite;
+        let server_name = self.server_name;
+        let columns     = self.columns;
 
         if let Some(conn) = self.conn(key.1, key.2, 0) {
             let state = conn.state();
 
             state.host_name.as_ref().map(|name| cs.add_str(server_name, name));
 
-            state.server_ver.and_then(|ver| server_ver.map(|id| {
+            state.server_ver.and_then(|ver| columns.server_ver.map(|id| {
                 let major = ver.0 as u32;
                 let minor = ver.1 as u32;
                 cs.add_u32(id, major << 8 | minor);
             }));
 
-            state.cipher_suite.and_then(|suite| cipher_suite.map(|id| {
+            state.cipher_suite.and_then(|suite| columns.cipher_suite.map(|id| {
                 cs.add_u32(id, suite.0 as u32);
             }));
+
+            state.ja3.as_ref().and_then(|s| columns.ja3.map(|id| cs.add_str(id, s)));
+            state.ja3s.as_ref().and_then(|s| columns.ja3s.map(|id| cs.add_str(id, s)));
+            state.ja4.as_ref().and_then(|s| columns.ja4.map(|id| cs.add_str(id, s)));
+            state.protocol.as_ref().and_then(|s| columns.alpn.map(|id| cs.add_str(id, s)));
+
+            if let Some(ref cert) = state.certificate {
+                cert.subject.as_ref().and_then(|s| columns.subject.map(|id| cs.add_str(id, s)));
+                cert.sans.as_ref().and_then(|s| columns.sans.map(|id| cs.add_str(id, s)));
+                cert.issuer.as_ref().and_then(|s| columns.issuer.map(|id| cs.add_str(id, s)));
+                cert.serial.as_ref().and_then(|s| columns.serial.map(|id| cs.add_str(id, s)));
+                cert.not_after.and_then(|t| columns.not_after.map(|id| cs.add_u32(id, t)));
+            }
         }
     }
 
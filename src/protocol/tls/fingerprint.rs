@@ -0,0 +1,154 @@
+use std::fmt::Write;
+use ring::digest::{digest, SHA256};
+use md5::{Digest, Md5};
+use super::parser::{ClientHello, ServerHello, Extension};
+
+// JA3 and JA3S (https://github.com/salesforce/ja3) and JA4
+// (https://github.com/FoxIO-LLC/ja4) TLS fingerprints. GREASE
+// values (RFC 8701) are ignored by all three.
+
+pub fn ja3(hello: &ClientHello) -> String {
+    let version = u16::from(hello.version.0) << 8 | u16::from(hello.version.1);
+
+    let mut groups  = Vec::new();
+    let mut formats = Vec::new();
+    for e in &hello.extensions {
+        match e {
+            Extension::Groups(gs)  => groups  = ungreased(gs),
+            Extension::Formats(fs) => formats = fs.iter().map(|&f| u16::from(f)).collect(),
+            _                      => (),
+        }
+    }
+
+    let s = format!("{},{},{},{},{}",
+        version,
+        join(&ungreased(&hello.ciphers), "-", decimal),
+        join(&codes(&hello.extensions), "-", decimal),
+        join(&groups, "-", decimal),
+        join(&formats, "-", decimal),
+    );
+
+    hex(&Md5::digest(s.as_bytes()))
+}
+
+pub fn ja3s(hello: &ServerHello) -> String {
+    let version = u16::from(hello.version.0) << 8 | u16::from(hello.version.1);
+
+    let s = format!("{},{},{}",
+        version,
+        hello.cipher.0,
+        join(&codes(&hello.extensions), "-", decimal),
+    );
+
+    hex(&Md5::digest(s.as_bytes()))
+}
+
+pub fn ja4(hello: &ClientHello) -> String {
+    let mut version = u16::from(hello.version.0) << 8 | u16::from(hello.version.1);
+    let mut alpn    = None;
+    let mut sigalgs = Vec::new();
+    let mut sni     = false;
+
+    for e in &hello.extensions {
+        match e {
+            Extension::SNI(..)      => sni = true,
+            Extension::ALPN(ps)     => alpn = ps.first(),
+            Extension::SigAlgs(ss)  => sigalgs = ungreased(ss),
+            Extension::Versions(vs) => version = ungreased(vs).into_iter().max().unwrap_or(version),
+            _                       => (),
+        }
+    }
+
+    let mut ciphers = ungreased(&hello.ciphers);
+    let mut exts    = codes(&hello.extensions);
+    let count       = exts.len();
+
+    ciphers.sort();
+    exts.retain(|&e| e != 0x0000 && e != 0x0010);
+    exts.sort();
+
+    let mut c = join(&exts, ",", hex16);
+    if !sigalgs.is_empty() {
+        c.push('_');
+        c.push_str(&join(&sigalgs, ",", hex16));
+    }
+
+    format!("t{}{}{:02}{:02}{}_{}_{}",
+        version_code(version),
+        if sni { 'd' } else { 'i' },
+        ciphers.len().min(99),
+        count.min(99),
+        alpn_code(alpn.map(|p| p.as_bytes()).unwrap_or(&[])),
+        truncated(&join(&ciphers, ",", hex16), ciphers.is_empty()),
+        truncated(&c, exts.is_empty()),
+    )
+}
+
+fn grease(v: u16) -> bool {
+    v & 0x0f0f == 0x0a0a && v >> 8 == v & 0xff
+}
+
+fn ungreased(vs: &[u16]) -> Vec<u16> {
+    vs.iter().cloned().filter(|&v| !grease(v)).collect()
+}
+
+fn codes(es: &[Extension]) -> Vec<u16> {
+    es.iter().map(Extension::code).filter(|&c| !grease(c)).collect()
+}
+
+fn version_code(version: u16) -> &'static str {
+    match version {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        0x0002 => "s2",
+        0xfeff => "d1",
+        0xfefd => "d2",
+        0xfefc => "d3",
+        _      => "00",
+    }
+}
+
+// First and last characters of the first ALPN value, or of its hex
+// form when either isn't alphanumeric.
+fn alpn_code(alpn: &[u8]) -> String {
+    match (alpn.first(), alpn.last()) {
+        (Some(&f), Some(&l)) if f.is_ascii_alphanumeric() && l.is_ascii_alphanumeric() => {
+            format!("{}{}", f as char, l as char)
+        },
+        (Some(&f), Some(&l)) => {
+            let f = format!("{:02x}", f);
+            let l = format!("{:02x}", l);
+            format!("{}{}", &f[..1], &l[1..])
+        },
+        _ => "00".to_owned(),
+    }
+}
+
+fn truncated(s: &str, empty: bool) -> String {
+    match empty {
+        true  => "000000000000".to_owned(),
+        false => hex(digest(&SHA256, s.as_bytes()).as_ref())[..12].to_owned(),
+    }
+}
+
+fn join<F: Fn(u16) -> String>(vs: &[u16], sep: &str, f: F) -> String {
+    vs.iter().map(|&v| f(v)).collect::<Vec<_>>().join(sep)
+}
+
+fn decimal(v: u16) -> String {
+    v.to_string()
+}
+
+fn hex16(v: u16) -> String {
+    format!("{:04x}", v)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut s, b| {
+        let _ = write!(s, "{:02x}", b);
+        s
+    })
+}
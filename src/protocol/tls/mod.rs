@@ -1,5 +1,10 @@
 pub mod conn;
 pub mod decode;
+pub mod fingerprint;
 pub mod parser;
+pub mod x509;
 
 pub use self::decode::*;
+
+#[cfg(test)]
+mod tests;
@@ -2,18 +2,20 @@ use std::ffi::CString;
 use nom::*;
 use nom::IResult::*;
 use byteorder::{ByteOrder, BigEndian as BE};
+use super::x509::{self, Certificate};
 
 #[derive(Debug)]
-pub enum Record {
-    Hello(Hello),
+pub enum Record<'a> {
+    Handshake(&'a [u8]),
     Other(u8),
     Unsupported(Version),
 }
 
 #[derive(Debug)]
 pub enum Hello {
-    Client(Version, Option<CString>),
-    Server(Version, CipherSuite),
+    Client(ClientHello),
+    Server(ServerHello),
+    Certificate(Option<Certificate>),
     Done,
     Other(u8),
 }
@@ -24,10 +26,28 @@ pub struct Version(pub u8, pub u8);
 #[derive(Copy, Clone, Debug)]
 pub struct CipherSuite(pub u16);
 
+#[derive(Debug)]
+pub struct ClientHello {
+    pub version:    Version,
+    pub ciphers:    Vec<u16>,
+    pub extensions: Vec<Extension>,
+}
+
+#[derive(Debug)]
+pub struct ServerHello {
+    pub version:    Version,
+    pub cipher:     CipherSuite,
+    pub extensions: Vec<Extension>,
+}
+
 #[derive(Debug)]
 pub enum Extension {
     SNI(Vec<ServerName>),
     ALPN(Vec<CString>),
+    Groups(Vec<u16>),
+    Formats(Vec<u8>),
+    SigAlgs(Vec<u16>),
+    Versions(Vec<u16>),
     Other(u16),
 }
 
@@ -62,31 +82,45 @@ pub fn record(buf: &[u8]) -> IResult<&[u8], Record> {
     }
 
     match ctype {
-        0x16 => handshake(rest),
+        0x16 => Done(&rest[len..], Record::Handshake(&rest[..len])),
         n    => Done(&rest[len..], Record::Other(n)),
     }
 }
 
-pub fn handshake(buf: &[u8]) -> IResult<&[u8], Record> {
-    if buf.len() < 4 {
-        return Incomplete(Needed::Size(4));
-    }
+// Handshake messages in a record, and the remainder holding the start
+// of any message fragmented across records.
+pub fn handshakes(mut buf: &[u8]) -> (Vec<Hello>, &[u8]) {
+    let mut hs = Vec::new();
 
-    let n32   = BE::read_u32(&buf[0..4]);
-    let htype = (n32 >> 24) as u8;
-    let len   = (n32 & 0xFFFFFF) as usize;
-    let rest  = &buf[4..];
+    while buf.len() >= 4 {
+        let n32   = BE::read_u32(&buf[0..4]);
+        let htype = (n32 >> 24) as u8;
+        let len   = (n32 & 0xFFFFFF) as usize;
+        let rest  = &buf[4..];
 
-    if rest.len() < len {
-        return Incomplete(Needed::Size(len - rest.len()));
+        if rest.len() < len {
+            break;
+        }
+
+        let (body, rest) = rest.split_at(len);
+
+        let hello = match htype {
+            0x01 => client_hello(body),
+            0x02 => server_hello(body),
+            0x0b => Done(rest, Hello::Certificate(certificate(body))),
+            0x0e => Done(rest, Hello::Done),
+            n    => Done(rest, Hello::Other(n)),
+        };
+
+        hs.push(match hello {
+            Done(_, hello) => hello,
+            _              => Hello::Other(htype),
+        });
+
+        buf = rest;
     }
 
-    match htype {
-        0x01 => client_hello(rest),
-        0x02 => server_hello(rest),
-        0x0e => Done(&rest[len..], Hello::Done),
-        n    => Done(&rest[len..], Hello::Other(n)),
-    }.map(Record::Hello)
+    (hs, buf)
 }
 
 named!(client_hello<&[u8], Hello>, do_parse!(
@@ -95,10 +129,14 @@ named!(client_hello<&[u8], Hello>, do_parse!(
  >> _gmt_unix_time: be_u32
  >> _random_bytes:  take!(28)
  >> _session_id:    length_bytes!(be_u8)
- >> _cipher_suites: length_bytes!(be_u16)
+ >> cipher_suites:  map!(length_bytes!(be_u16), u16s)
  >> _compression:   length_bytes!(be_u8)
  >> extensions:     flat_map!(length_bytes!(be_u16), many0!(extension))
- >> (Hello::Client(Version(major, minor), find_sni(extensions)))
+ >> (Hello::Client(ClientHello{
+        version:    Version(major, minor),
+        ciphers:    cipher_suites,
+        extensions: extensions,
+    }))
 ));
 
 named!(server_hello<&[u8], Hello>, do_parse!(
@@ -110,7 +148,11 @@ named!(server_hello<&[u8], Hello>, do_parse!(
  >> cipher_suite:   be_u16
  >> _compression:   be_u8
  >> extensions:     flat_map!(length_bytes!(be_u16), many0!(extension))
- >> (Hello::Server(Version(major, minor), CipherSuite(cipher_suite)))
+ >> (Hello::Server(ServerHello{
+        version:    Version(major, minor),
+        cipher:     CipherSuite(cipher_suite),
+        extensions: extensions,
+    }))
 ));
 
 fn extension(buf: &[u8]) -> IResult<&[u8], Extension> {
@@ -126,10 +168,49 @@ fn extension(buf: &[u8]) -> IResult<&[u8], Extension> {
         return Incomplete(Needed::Size(len - rest.len()));
     }
 
+    let (data, rest) = rest.split_at(len);
+
     match etype {
-        0x0000 if len > 0 => server_names(rest),
-        0x0010 if len > 0 => protocols(rest),
-        n                 => Done(&rest[len..], Extension::Other(n)),
+        0x0000 if len > 0 => Done(rest, complete(server_names(data), etype)),
+        0x0010 if len > 0 => Done(rest, complete(protocols(data), etype)),
+        0x000a            => Done(rest, Extension::Groups(u16s(data.get(2..).unwrap_or(&[])))),
+        0x000b            => Done(rest, Extension::Formats(data.get(1..).unwrap_or(&[]).to_vec())),
+        0x000d            => Done(rest, Extension::SigAlgs(u16s(data.get(2..).unwrap_or(&[])))),
+        0x002b if len > 2 => Done(rest, Extension::Versions(u16s(data.get(1..).unwrap_or(&[])))),
+        0x002b            => Done(rest, Extension::Versions(u16s(data))),
+        n                 => Done(rest, Extension::Other(n)),
+    }
+}
+
+// The leaf certificate is first in the chain.
+fn certificate(buf: &[u8]) -> Option<Certificate> {
+    let len = BE::read_u24(buf.get(3..6)?) as usize;
+    x509::parse(buf.get(6..6 + len)?)
+}
+
+fn complete(r: IResult<&[u8], Extension>, etype: u16) -> Extension {
+    match r {
+        Done(_, e) => e,
+        _          => Extension::Other(etype),
+    }
+}
+
+// Big-endian u16 list, ignoring any trailing odd byte.
+fn u16s(buf: &[u8]) -> Vec<u16> {
+    buf.chunks_exact(2).map(BE::read_u16).collect()
+}
+
+impl Extension {
+    pub fn code(&self) -> u16 {
+        match *self {
+            Extension::SNI(..)      => 0x0000,
+            Extension::ALPN(..)     => 0x0010,
+            Extension::Groups(..)   => 0x000a,
+            Extension::Formats(..)  => 0x000b,
+            Extension::SigAlgs(..)  => 0x000d,
+            Extension::Versions(..) => 0x002b,
+            Extension::Other(n)     => n,
+        }
     }
 }
 
@@ -160,15 +241,42 @@ named!(cstring<&[u8], CString>, do_parse!(
  >> (str)
 ));
 
-fn find_sni(es: Vec<Extension>) -> Option<CString> {
-    es.into_iter().flat_map(|e| match e {
-        Extension::SNI(ns) => find_host_name(ns),
-        _                  => None,
-    }).next()
+impl ClientHello {
+    pub fn server_name(&self) -> Option<&CString> {
+        self.extensions.iter().flat_map(|e| match e {
+            Extension::SNI(ns) => find_host_name(ns),
+            _                  => None,
+        }).next()
+    }
+
+    pub fn protocols(&self) -> &[CString] {
+        self.extensions.iter().flat_map(|e| match e {
+            Extension::ALPN(ps) => Some(&ps[..]),
+            _                   => None,
+        }).next().unwrap_or(&[])
+    }
+}
+
+impl ServerHello {
+    // TLS 1.3 negotiates the version with the supported_versions
+    // extension and leaves the legacy version at 1.2.
+    pub fn selected_version(&self) -> Version {
+        self.extensions.iter().flat_map(|e| match e {
+            Extension::Versions(vs) if vs.len() == 1 => Some(Version((vs[0] >> 8) as u8, vs[0] as u8)),
+            _                                        => None,
+        }).next().unwrap_or(self.version)
+    }
+
+    pub fn protocol(&self) -> Option<&CString> {
+        self.extensions.iter().flat_map(|e| match e {
+            Extension::ALPN(ps) => ps.first(),
+            _                   => None,
+        }).next()
+    }
 }
 
-fn find_host_name(ns: Vec<ServerName>) -> Option<CString> {
-    ns.into_iter().flat_map(|n| match n {
+fn find_host_name(ns: &[ServerName]) -> Option<&CString> {
+    ns.iter().flat_map(|n| match n {
         ServerName::HostName(n) => Some(n),
         _                       => None,
     }).next()
@@ -0,0 +1,131 @@
+use nom::IResult::Done;
+use super::fingerprint;
+use super::parser::*;
+
+#[test]
+fn test_client_hello_fingerprints() {
+    let ciphers = [0x0a0a, 0x1301, 0x1302, 0xc02b];
+    let extensions = [
+        extension(0x1a1a, &[]),
+        extension(0x0000, &list16(&[&[0], &list16(&[b"example.com"])])),
+        extension(0x0010, &list16(&[&list8(&[b"h2"]), &list8(&[b"http/1.1"])])),
+        extension(0x000a, &list16(&[&u16s(&[0x2a2a, 0x001d, 0x0017])])),
+        extension(0x000b, &list8(&[&[0]])),
+        extension(0x000d, &list16(&[&u16s(&[0x0403, 0x0804, 0x0401])])),
+        extension(0x002b, &list8(&[&u16s(&[0x3a3a, 0x0304, 0x0303])])),
+        extension(0x0033, &[]),
+    ];
+
+    let hello = match hellos(&[handshake(0x01, &client_hello(&ciphers, &extensions))]).remove(0) {
+        Hello::Client(hello) => hello,
+        h                    => panic!("unexpected message {:?}", h),
+    };
+
+    assert_eq!("example.com", hello.server_name().unwrap().to_str().unwrap());
+    assert_eq!("14c0a698cc2b724919daa1f5aa16b117", fingerprint::ja3(&hello));
+    assert_eq!("t13d0307h2_5559582ccdc4_5e6a61e7dae5", fingerprint::ja4(&hello));
+}
+
+#[test]
+fn test_server_hello_tls13() {
+    let extensions = [
+        extension(0x002b, &u16s(&[0x0304])),
+        extension(0x0010, &list16(&[&list8(&[b"h2"])])),
+    ];
+
+    let record = [
+        handshake(0x02, &server_hello(0x1301, &extensions)),
+        handshake(0x0e, &[]),
+    ];
+
+    let mut hs = hellos(&record);
+    let hello = match hs.remove(0) {
+        Hello::Server(hello) => hello,
+        h                    => panic!("unexpected message {:?}", h),
+    };
+
+    assert_eq!((3, 4), (hello.selected_version().0, hello.selected_version().1));
+    assert_eq!((3, 3), (hello.version.0, hello.version.1));
+    assert_eq!("h2", hello.protocol().unwrap().to_str().unwrap());
+
+    match hs.as_slice() {
+        [Hello::Done] => (),
+        hs            => panic!("unexpected messages {:?}", hs),
+    }
+}
+
+#[test]
+fn test_fragmented_handshake() {
+    let mut fragment = handshake(0x0b, &[0; 64]);
+    fragment.truncate(32);
+
+    match hellos(&[handshake(0x0e, &[]), fragment]).as_slice() {
+        [Hello::Done] => (),
+        hs            => panic!("unexpected messages {:?}", hs),
+    }
+}
+
+fn client_hello(ciphers: &[u16], extensions: &[Vec<u8>]) -> Vec<u8> {
+    let mut body = vec![0x03, 0x03];
+    body.extend_from_slice(&[0x11; 32]);
+    body.extend(list8(&[]));
+    body.extend(list16(&[&u16s(ciphers)]));
+    body.extend(list8(&[&[0]]));
+    body.extend(list16(&extensions.iter().map(Vec::as_slice).collect::<Vec<_>>()));
+    body
+}
+
+fn server_hello(cipher: u16, extensions: &[Vec<u8>]) -> Vec<u8> {
+    let mut body = vec![0x03, 0x03];
+    body.extend_from_slice(&[0x22; 32]);
+    body.extend(list8(&[]));
+    body.extend_from_slice(&cipher.to_be_bytes());
+    body.push(0);
+    body.extend(list16(&extensions.iter().map(Vec::as_slice).collect::<Vec<_>>()));
+    body
+}
+
+fn handshake(kind: u8, body: &[u8]) -> Vec<u8> {
+    let len = body.len() as u32;
+    let mut v = vec![kind, (len >> 16) as u8, (len >> 8) as u8, len as u8];
+    v.extend_from_slice(body);
+    v
+}
+
+// Messages from a single handshake record.
+fn hellos(messages: &[Vec<u8>]) -> Vec<Hello> {
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend(list16(&messages.iter().map(Vec::as_slice).collect::<Vec<_>>()));
+
+    match parse_records(&record) {
+        Done(rest, mut rs) if rest.is_empty() && rs.len() == 1 => match rs.remove(0) {
+            Record::Handshake(hs) => handshakes(hs).0,
+            r                     => panic!("unexpected record {:?}", r),
+        },
+        r => panic!("parse failed {:?}", r),
+    }
+}
+
+fn extension(kind: u16, data: &[u8]) -> Vec<u8> {
+    let mut v = kind.to_be_bytes().to_vec();
+    v.extend(list16(&[data]));
+    v
+}
+
+fn list8(items: &[&[u8]]) -> Vec<u8> {
+    let data = items.concat();
+    let mut v = vec![data.len() as u8];
+    v.extend(data);
+    v
+}
+
+fn list16(items: &[&[u8]]) -> Vec<u8> {
+    let data = items.concat();
+    let mut v = (data.len() as u16).to_be_bytes().to_vec();
+    v.extend(data);
+    v
+}
+
+fn u16s(vs: &[u16]) -> Vec<u8> {
+    vs.iter().flat_map(|v| v.to_be_bytes().to_vec()).collect()
+}
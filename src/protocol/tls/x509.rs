@@ -0,0 +1,231 @@
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use time::{Date, Month};
+
+// Just enough of X.509 (RFC 5280) DER decoding to pull identifying
+// fields out of a certificate.
+
+#[derive(Debug)]
+pub struct Certificate {
+    pub subject:   Option<CString>,
+    pub sans:      Option<CString>,
+    pub issuer:    Option<CString>,
+    pub serial:    Option<CString>,
+    pub not_after: Option<u32>,
+}
+
+const SEQUENCE:     u8 = 0x30;
+const SET:          u8 = 0x31;
+const OID:          u8 = 0x06;
+const OCTET_STRING: u8 = 0x04;
+const VERSION:      u8 = 0xa0;
+const EXTENSIONS:   u8 = 0xa3;
+const DNS_NAME:     u8 = 0x82;
+const IP_ADDRESS:   u8 = 0x87;
+const UTC_TIME:     u8 = 0x17;
+const GEN_TIME:     u8 = 0x18;
+
+const CN:  &[u8] = &[0x55, 0x04, 0x03];
+const SAN: &[u8] = &[0x55, 0x1d, 0x11];
+
+const ATTRIBUTES: &[(&[u8], &str)] = &[
+    (&[0x55, 0x04, 0x06], "C"),
+    (&[0x55, 0x04, 0x08], "ST"),
+    (&[0x55, 0x04, 0x07], "L"),
+    (&[0x55, 0x04, 0x0a], "O"),
+    (&[0x55, 0x04, 0x0b], "OU"),
+    (CN,                  "CN"),
+];
+
+pub fn parse(der: &[u8]) -> Option<Certificate> {
+    let cert = Der(der).expect(SEQUENCE)?;
+    let mut tbs = Der(cert).expect(SEQUENCE).map(Der)?;
+
+    let mut serial = tbs.next()?;
+    if serial.0 == VERSION {
+        serial = tbs.next()?;
+    }
+
+    let _sig     = tbs.expect(SEQUENCE)?;
+    let issuer   = tbs.expect(SEQUENCE)?;
+    let validity = tbs.expect(SEQUENCE)?;
+    let subject  = tbs.expect(SEQUENCE)?;
+    let _spki    = tbs.expect(SEQUENCE)?;
+
+    let mut sans = None;
+    while let Some((tag, value)) = tbs.next() {
+        if tag == EXTENSIONS {
+            sans = extension(value, SAN).and_then(alt_names);
+        }
+    }
+
+    let mut validity = Der(validity);
+    let _not_before  = validity.next();
+
+    Some(Certificate {
+        subject:   common_name(subject),
+        sans:      sans,
+        issuer:    name(issuer),
+        serial:    CString::new(hex(integer(serial.1))).ok(),
+        not_after: validity.next().and_then(|(tag, v)| timestamp(tag, v)),
+    })
+}
+
+// DER type-length-value reader.
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+    fn next(&mut self) -> Option<(u8, &'a [u8])> {
+        let (&tag, rest) = self.0.split_first()?;
+        let (&first, rest) = rest.split_first()?;
+
+        let (len, rest) = match first {
+            n if n < 0x80 => (n as usize, rest),
+            n => {
+                let n = (n & 0x7f) as usize;
+                if n == 0 || n > 4 || rest.len() < n {
+                    return None;
+                }
+                let len = rest[..n].iter().fold(0usize, |len, &b| len << 8 | b as usize);
+                (len, &rest[n..])
+            },
+        };
+
+        if rest.len() < len {
+            return None;
+        }
+
+        let (value, rest) = rest.split_at(len);
+        self.0 = rest;
+        Some((tag, value))
+    }
+
+    fn expect(&mut self, tag: u8) -> Option<&'a [u8]> {
+        match self.next()? {
+            (t, value) if t == tag => Some(value),
+            _                      => None,
+        }
+    }
+}
+
+// Name attributes in order as (OID, value) pairs.
+fn attributes(name: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut attrs = Vec::new();
+    let mut rdns  = Der(name);
+
+    while let Some(rdn) = rdns.expect(SET) {
+        let mut rdn = Der(rdn);
+        while let Some(attr) = rdn.expect(SEQUENCE) {
+            let mut attr = Der(attr);
+            if let (Some(oid), Some((_, value))) = (attr.expect(OID), attr.next()) {
+                attrs.push((oid, value));
+            }
+        }
+    }
+
+    attrs
+}
+
+// Name formatted like "C=US, O=Example, CN=Example CA".
+fn name(name: &[u8]) -> Option<CString> {
+    let parts = attributes(name).into_iter().filter_map(|(oid, value)| {
+        let &(_, short) = ATTRIBUTES.iter().find(|&&(o, _)| o == oid)?;
+        Some(format!("{}={}", short, String::from_utf8_lossy(value)))
+    }).collect::<Vec<_>>();
+
+    match parts.is_empty() {
+        true  => None,
+        false => CString::new(parts.join(", ")).ok(),
+    }
+}
+
+fn common_name(name: &[u8]) -> Option<CString> {
+    let (_, value) = attributes(name).into_iter().find(|&(oid, _)| oid == CN)?;
+    CString::new(String::from_utf8_lossy(value).into_owned()).ok()
+}
+
+fn extension<'a>(exts: &'a [u8], id: &[u8]) -> Option<&'a [u8]> {
+    let mut exts = Der(Der(exts).expect(SEQUENCE)?);
+
+    while let Some(ext) = exts.expect(SEQUENCE) {
+        let mut ext = Der(ext);
+        if ext.expect(OID)? != id {
+            continue;
+        }
+        // skip the optional critical flag
+        while let Some((tag, value)) = ext.next() {
+            if tag == OCTET_STRING {
+                return Some(value);
+            }
+        }
+    }
+
+    None
+}
+
+// DNS names and IP addresses from a subjectAltName, comma separated.
+fn alt_names(san: &[u8]) -> Option<CString> {
+    let mut names = Der(Der(san).expect(SEQUENCE)?);
+    let mut list  = Vec::new();
+
+    while let Some((tag, value)) = names.next() {
+        match (tag, value.len()) {
+            (DNS_NAME,   _)  => list.push(String::from_utf8_lossy(value).into_owned()),
+            (IP_ADDRESS, 4)  => list.push(ip(value).to_string()),
+            (IP_ADDRESS, 16) => list.push(ip(value).to_string()),
+            _                => (),
+        }
+    }
+
+    match list.is_empty() {
+        true  => None,
+        false => CString::new(list.join(",")).ok(),
+    }
+}
+
+fn ip(b: &[u8]) -> IpAddr {
+    match b.len() {
+        4 => IpAddr::V4(Ipv4Addr::new(b[0], b[1], b[2], b[3])),
+        _ => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(b);
+            IpAddr::V6(Ipv6Addr::from(octets))
+        },
+    }
+}
+
+// UTCTime (YYMMDDHHMMSSZ) or GeneralizedTime (YYYYMMDDHHMMSSZ) as
+// seconds since the epoch, saturating at u32::MAX.
+fn timestamp(tag: u8, value: &[u8]) -> Option<u32> {
+    let s = ::std::str::from_utf8(value).ok()?.strip_suffix('Z')?;
+
+    let (year, rest) = match tag {
+        UTC_TIME => match s.get(..2)?.parse::<i32>().ok()? {
+            y if y < 50 => (2000 + y, &s[2..]),
+            y           => (1900 + y, &s[2..]),
+        },
+        GEN_TIME => (s.get(..4)?.parse().ok()?, &s[4..]),
+        _        => return None,
+    };
+
+    let field = |n: usize| rest.get(n * 2..n * 2 + 2)?.parse::<u8>().ok();
+    let month = Month::try_from(field(0)?).ok()?;
+    let date  = Date::from_calendar_date(year, month, field(1)?).ok()?;
+    let time  = date.with_hms(field(2)?, field(3)?, field(4)?).ok()?;
+    let secs  = time.assume_utc().unix_timestamp();
+
+    Some(secs.max(0).min(u32::max_value() as i64) as u32)
+}
+
+// INTEGER contents without the sign padding byte.
+fn integer(value: &[u8]) -> &[u8] {
+    match value {
+        [0, rest @ ..] if !rest.is_empty() => rest,
+        _                                  => value,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -1,9 +1,13 @@
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use time::Duration;
 use crate::flow::*;
 use crate::custom::Customs;
+use crate::direction::Method;
+use crate::geo::Geo;
+use crate::k8s::Kubernetes;
 use crate::libkflow;
-use crate::protocol::{Classify, Decoder, Decoders};
+use crate::protocol::{self, Classify, Decoder, Decoders};
 use crate::time::Timestamp;
 use crate::timer::{Timeout, Timer};
 use crate::track::Tracker;
@@ -12,6 +16,8 @@ use crate::track::Tracker;
 pub struct Counter {
     pub ethernet:  Ethernet,
     pub direction: Direction,
+    pub method:    Method,
+    pub ifindex:   u32,
     pub tos:       u8,
     pub tcp_flags: u8,
     pub packets:   u64,
@@ -27,6 +33,8 @@ pub struct FlowQueue {
     tracker:  Tracker,
     classify: Classify,
     customs:  Customs,
+    k8s:      Option<Kubernetes>,
+    geo:      Option<Arc<Mutex<Geo>>>,
     sample:   u32,
     compact:  Timer,
     export:   Timer,
@@ -41,6 +49,8 @@ impl FlowQueue {
             tracker:  Tracker::new(&customs),
             classify: classify,
             customs:  customs,
+            k8s:      None,
+            geo:      None,
             sample:   sample.unwrap_or(1) as u32,
             compact:  Timer::new(Duration::seconds(30)),
             export:   Timer::new(Duration::seconds(2)),
@@ -48,19 +58,37 @@ impl FlowQueue {
         }
     }
 
+    pub fn kubernetes(&mut self, k8s: Kubernetes) {
+        self.k8s = Some(k8s);
+    }
+
+    pub fn geo(&mut self, geo: Arc<Mutex<Geo>>) {
+        self.geo = Some(geo);
+    }
+
+    pub fn protocol(&mut self, cfg: &protocol::Config) {
+        self.decoders.configure(cfg);
+    }
+
     pub fn add(&mut self, flow: Flow) {
         let key = Key(flow.protocol, flow.src, flow.dst);
         let dec = self.record(key, &flow);
 
-        if self.decoders.decode(dec, &flow, &mut self.customs) {
+        // Every record a segment completes is sent, with the counters
+        // carried by the first.
+        let mut decoded = self.decoders.decode(dec, &flow, &mut self.customs);
+        while decoded {
             if flow.export {
                 if let Some(ctr) = self.flows.get_mut(&key) {
                     let customs = &mut self.customs;
                     let tracker = &mut self.tracker;
-                    Self::send(customs, tracker, &key, ctr, self.sample);
+                    let k8s     = self.k8s.as_ref();
+                    let geo     = self.geo.as_deref();
+                    Self::send(customs, tracker, k8s, geo, &key, ctr, self.sample);
                 }
             }
             self.customs.clear();
+            decoded = self.decoders.next(dec, &mut self.customs);
         }
     }
 
@@ -75,6 +103,8 @@ impl FlowQueue {
             Counter {
                 ethernet:  flow.ethernet,
                 direction: flow.direction,
+                method:    flow.method,
+                ifindex:   flow.ifindex,
                 tos:       0,
                 tcp_flags: 0,
                 packets:   0,
@@ -108,12 +138,27 @@ impl FlowQueue {
         let decoders = &mut self.decoders;
         let tracker  = &mut self.tracker;
 
+        let k8s = self.k8s.as_ref();
+        let geo = self.geo.as_deref();
+
+        if let Some(geo) = geo {
+            geo.lock().unwrap().refresh(ts);
+        }
+
         for (key, ctr) in &mut self.flows {
-            if ctr.export <= ts && ctr.packets > 0 {
-                decoders.append(ctr.decoder, key, customs);
-                Self::send(customs, tracker, key, ctr, self.sample);
-                ctr.export = self.timeout.next(ctr.export);
+            if ctr.export <= ts {
+                let expired = decoders.expire(ctr.decoder, key, ts, customs);
+                if ctr.packets > 0 || expired {
+                    decoders.append(ctr.decoder, key, customs);
+                    Self::send(customs, tracker, k8s, geo, key, ctr, self.sample);
+                    ctr.export = self.timeout.next(ctr.export);
+                }
                 customs.clear();
+
+                while expired && decoders.next(ctr.decoder, customs) {
+                    Self::send(customs, tracker, k8s, geo, key, ctr, self.sample);
+                    customs.clear();
+                }
             }
         }
 
@@ -128,11 +173,21 @@ impl FlowQueue {
         }
     }
 
-    fn send(customs: &mut Customs, tracker: &mut Tracker, key: &Key, ctr: &mut Counter, sr: u32) {
+    fn send(customs: &mut Customs, tracker: &mut Tracker, k8s: Option<&Kubernetes>, geo: Option<&Mutex<Geo>>, key: &Key, ctr: &mut Counter, sr: u32) {
         customs.append(ctr);
         tracker.append(key, customs);
 
-        let flow = libkflow::flow(key, ctr, sr, customs.app_protocol());
+        if let Some(k8s) = k8s {
+            k8s.append(key, customs);
+        }
+
+        let mut flow = libkflow::flow(key, ctr, sr, customs.app_protocol());
+
+        // The lock is shared by every capture thread, so it's only
+        // held for the lookup.
+        if let Some(geo) = geo {
+            geo.lock().unwrap().append(key, &mut flow);
+        }
 
         libkflow::send(flow, match &customs {
             cs if !cs.is_empty() => Some(cs),
@@ -1,4 +1,5 @@
 use std::ffi::{CString};
+use std::path::Path;
 use pnet::datalink::NetworkInterface;
 use crate::args::{Args, parser};
 use crate::libkflow::*;
@@ -75,6 +76,97 @@ fn test_http_config_with_proxy() {
     assert_eq!(Some("http://proxy:1234".into()), config.2);
 }
 
+#[test]
+fn test_direction_args() {
+    use crate::direction::Method;
+
+    let args = parse(&[
+        "--email",     "test@example.com",
+        "--token",     "asdf1234",
+        "--direction", "local",
+        "--direction", "port",
+        "--local-net", "10.0.0.0/8",
+        "--local-net", "fd00::/8",
+    ]);
+
+    assert_eq!(Some(vec![Method::Local, Method::Port]), args.direction);
+    assert_eq!(Some(vec!["10.0.0.0/8".parse().unwrap(), "fd00::/8".parse().unwrap()]), args.local_net);
+}
+
+#[test]
+fn test_multiple_interfaces() {
+    let args = parse(&[
+        "--email",            "test@example.com",
+        "--token",            "asdf1234",
+        "-i",                 "eth1",
+        "--filter",           "tcp",
+        "--interface-filter", "eth1=udp port 53",
+    ]);
+
+    let names = args.capture.iter().map(|c| format!("{:?}", c)).collect::<Vec<_>>();
+    assert_eq!(vec![r#"Capture("lo")"#, r#"Capture("eth1")"#], names);
+
+    assert_eq!(Some("tcp"),         args.filter(&args.capture[0]));
+    assert_eq!(Some("udp port 53"), args.filter(&args.capture[1]));
+}
+
+#[test]
+fn test_mode_single_interface() {
+    let args = ["-i", "lo", "--email", "test@example.com", "--token", "asdf1234"];
+
+    let one = [&args[..], &["dhcp"]].concat();
+    assert!(parser().run_inner(bpaf::Args::from(&one[..])).is_ok());
+
+    let two = [&args[..], &["-i", "eth1", "dhcp"]].concat();
+    assert!(parser().run_inner(bpaf::Args::from(&two[..])).is_err());
+}
+
+#[test]
+fn test_fanout_group_per_interface() {
+    let args = ["-i", "lo", "-i", "eth1", "--email", "test@example.com", "--token", "asdf1234"];
+
+    let two = [&args[..], &["--fanout-group", "10", "--fanout-group", "20"]].concat();
+    let two = parser().run_inner(bpaf::Args::from(&two[..])).unwrap();
+    assert_eq!(Some(vec![10, 20]), two.fangroup);
+
+    let one = [&args[..], &["--fanout-group", "10"]].concat();
+    assert!(parser().run_inner(bpaf::Args::from(&one[..])).is_err());
+}
+
+#[test]
+fn test_dnstap_without_interface() {
+    let args = ["--email", "test@example.com", "--token", "asdf1234"];
+
+    let dnstap = [&args[..], &["dns", "--dnstap-socket", "/run/dnstap.sock"]].concat();
+    let dnstap = parser().run_inner(bpaf::Args::from(&dnstap[..])).unwrap();
+    assert!(dnstap.capture.is_empty());
+    assert_eq!(Some(Path::new("/run/dnstap.sock")), dnstap.dnstap());
+
+    let dns = [&args[..], &["dns"]].concat();
+    assert!(parser().run_inner(bpaf::Args::from(&dns[..])).is_err());
+    assert!(parser().run_inner(bpaf::Args::from(&args[..])).is_err());
+}
+
+#[test]
+fn test_dns_timeout_limit() {
+    let args = parse(&["--email", "test@example.com", "--token", "asdf1234", "--dns-timeout", "59"]);
+    assert_eq!(Some(59), args.dns_timeout);
+
+    for timeout in &["0", "60"] {
+        let args = ["-i", "lo", "--email", "test@example.com", "--token", "asdf1234", "--dns-timeout", timeout];
+        assert!(parser().run_inner(bpaf::Args::from(&args[..])).is_err());
+    }
+}
+
+#[test]
+fn test_mirror_port_format() {
+    let args = parse(&["--email", "test@example.com", "--token", "asdf1234", "--mirror-format", "fixed:4", "--mirror-port", "30000"]);
+    assert_eq!(Some(vec![30000]), args.mirror().map(|m| m.ports));
+
+    let args = ["-i", "lo", "--email", "test@example.com", "--token", "asdf1234", "--mirror-format", "erspan", "--mirror-port", "30000"];
+    assert!(parser().run_inner(bpaf::Args::from(&args[..])).is_err());
+}
+
 fn cstr(str: &str) -> CString {
     CString::new(str).unwrap()
 }
@@ -19,6 +19,7 @@ fn decode_dhcp() {
     let yiaddr  = Value::from("10.211.55.16".parse::<IpAddr>().unwrap());
     let siaddr  = Value::from("10.211.55.1".parse::<IpAddr>().unwrap());
     let lease   = Value::from(1800);
+    let fingerprint = Value::from("1,28,2,3,15,6,119,12,44,47,26,121,42");
 
     // Request
     let flow = flows.next().unwrap();
@@ -34,6 +35,7 @@ fn decode_dhcp() {
     assert_eq!(None,                 value(DHCP_DOMAIN, &customs));
     assert_eq!(None,                 value(DHCP_LEASE, &customs));
     assert_eq!(None,                 value(APP_LATENCY, &customs));
+    assert_eq!(Some(fingerprint),    value(DHCP_FINGERPRINT, &customs));
 
     customs.clear();
 
@@ -51,6 +53,160 @@ fn decode_dhcp() {
     assert_eq!(Some(domain.clone()), value(DHCP_DOMAIN, &customs));
     assert_eq!(Some(lease.clone()),  value(DHCP_LEASE, &customs));
     assert_eq!(Some(Value::from(1)), value(APP_LATENCY, &customs));
+    assert_eq!(None,                 value(DHCP_FINGERPRINT, &customs));
+}
+
+#[test]
+fn decode_dhcp_relay() {
+    let columns = [CUSTOMS, &[
+        custom(b"DHCP_GI_ADDR\0",        26, KFLOW_CUSTOM_ADDR),
+        custom(b"DHCP_REQUESTED_ADDR\0", 27, KFLOW_CUSTOM_ADDR),
+        custom(b"DHCP_SERVER_ID\0",      28, KFLOW_CUSTOM_ADDR),
+        custom(b"DHCP_VENDOR_CLASS\0",   29, KFLOW_CUSTOM_STR),
+        custom(b"DHCP_CLIENT_ID\0",      30, KFLOW_CUSTOM_STR),
+        custom(b"DHCP_CLIENT_FQDN\0",    31, KFLOW_CUSTOM_STR),
+        custom(b"DHCP_CIRCUIT_ID\0",     32, KFLOW_CUSTOM_STR),
+        custom(b"DHCP_REMOTE_ID\0",      33, KFLOW_CUSTOM_STR),
+    ]].concat();
+
+    let mut customs  = Customs::new(&columns);
+    let mut classify = classifier();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let mut messages = Vec::new();
+
+    for flow in iter::flows("pcaps/dhcp/relay.pcap") {
+        let d = classify.find(&flow);
+        assert!(decoders.decode(d, &flow, &mut customs));
+        messages.push(vec![
+            value(DHCP_MSG_TYPE, &customs),
+            value(DHCP_GI_ADDR, &customs),
+            value(DHCP_REQUESTED_ADDR, &customs),
+            value(DHCP_SERVER_ID, &customs),
+            value(DHCP_VENDOR_CLASS, &customs),
+            value(DHCP_CLIENT_ID, &customs),
+            value(DHCP_CLIENT_FQDN, &customs),
+            value(DHCP_CIRCUIT_ID, &customs),
+            value(DHCP_REMOTE_ID, &customs),
+            value(DHCP_FINGERPRINT, &customs),
+        ]);
+        customs.clear();
+    }
+
+    let addr        = |s: &str| Some(Value::from(s.parse::<IpAddr>().unwrap()));
+    let str         = |s: &str| Some(Value::from(s));
+    let giaddr      = addr("10.1.0.1");
+    let server      = addr("10.0.0.67");
+    let mac         = "02:00:5e:10:20:30";
+    let fingerprint = "1,3,6,15,31,33,43,44,46,47,119,121,249,252";
+
+    assert_eq!(vec![
+        vec![
+            Some(Value::from(1)), giaddr.clone(), None, None, str("MSFT 5.0"),
+            str("01:02:00:5e:10:20:30"), str("laptop.example.com"),
+            str("eth0/1/2:100"), str(mac), str(fingerprint),
+        ],
+        vec![
+            Some(Value::from(2)), giaddr.clone(), None, server.clone(), None,
+            None, None, str("eth0/1/2:100"), str(mac), None,
+        ],
+        vec![
+            Some(Value::from(3)), giaddr, addr("10.1.0.50"), server, None,
+            None, str("laptop.example.com"), str("00:04:00:64"), None, str(fingerprint),
+        ],
+    ], messages);
+
+    for flow in iter::flows("pcaps/dhcp/dhcpv4.pcap") {
+        let d = classify.find(&flow);
+        assert!(decoders.decode(d, &flow, &mut customs));
+        assert_eq!(None, value(DHCP_GI_ADDR, &customs));
+        customs.clear();
+    }
+}
+
+#[test]
+fn decode_dhcpv6() {
+    let columns = [CUSTOMS, &[
+        custom(b"DHCPV6_MSG_TYPE\0", 26, KFLOW_CUSTOM_U32),
+        custom(b"DHCPV6_DUID\0",     27, KFLOW_CUSTOM_STR),
+        custom(b"DHCPV6_ADDRS\0",    28, KFLOW_CUSTOM_STR),
+        custom(b"DHCPV6_PREFIXES\0", 29, KFLOW_CUSTOM_STR),
+        custom(b"DHCPV6_FQDN\0",     30, KFLOW_CUSTOM_STR),
+    ]].concat();
+
+    let mut customs  = Customs::new(&columns);
+    let mut classify = classifier();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let mut messages = Vec::new();
+
+    for flow in iter::flows("pcaps/dhcp/dhcpv6.pcap") {
+        let d = classify.find(&flow);
+        assert!(decoders.decode(d, &flow, &mut customs));
+        messages.push(vec![
+            value(DHCPV6_MSG_TYPE, &customs),
+            value(DHCPV6_DUID, &customs),
+            value(DHCPV6_ADDRS, &customs),
+            value(DHCPV6_PREFIXES, &customs),
+            value(DHCPV6_FQDN, &customs),
+            value(APP_LATENCY, &customs),
+        ]);
+        customs.clear();
+    }
+
+    let str   = |s: &str| Some(Value::from(s));
+    let duid1 = str("00:03:00:01:02:00:5e:10:20:30");
+    let duid2 = str("00:03:00:01:02:00:5e:10:20:31");
+
+    assert_eq!(vec![
+        vec![Some(Value::from(1)), duid1.clone(), None, None, str("host1.example.com"), None],
+        vec![
+            Some(Value::from(2)), duid1.clone(), str("2001:db8::10 3600/7200"),
+            str("2001:db8:100::/56 1800/3600"), None, Some(Value::from(5)),
+        ],
+        vec![Some(Value::from(3)), duid2.clone(), str("2001:db8:1::20 0/0"), None, None, None],
+        vec![Some(Value::from(7)), duid2, str("2001:db8:1::20 100/200"), None, None, Some(Value::from(5))],
+        vec![Some(Value::from(8)), duid1, str("2001:db8::10 0/0"), None, None, None],
+    ], messages);
+}
+
+#[test]
+fn decode_dhcpv6_relay_depth() {
+    use crate::protocol::dhcpv6::parser::{self, Packet};
+
+    // SOLICIT with a client identifier wrapped in n RELAY-FORW messages.
+    let relayed = |n: usize| {
+        let mut msg = vec![1, 0, 0, 1, 0, 1, 0, 2, 0xAB, 0xCD];
+        for hops in (0..n).rev() {
+            let mut relay = vec![12, hops as u8];
+            relay.extend_from_slice(&[0u8; 32]);
+            relay.extend_from_slice(&[0, 9]);
+            relay.extend_from_slice(&(msg.len() as u16).to_be_bytes());
+            relay.extend_from_slice(&msg);
+            msg = relay;
+        }
+        msg
+    };
+
+    let depth = |buf: &[u8]| {
+        let mut packet = match parser::packet(buf) {
+            nom::IResult::Done(_, packet) => packet,
+            _                             => return None,
+        };
+        let mut n = 0;
+        while let Packet::Relay(relay) = packet {
+            packet = relay.opts.into_iter().find_map(|o| match o {
+                parser::Opt::RelayMsg(p) => Some(*p),
+                _                        => None,
+            })?;
+            n += 1;
+        }
+        Some(n)
+    };
+
+    assert_eq!(Some(1),  depth(&relayed(1)));
+    assert_eq!(Some(32), depth(&relayed(32)));
+    assert_eq!(None,     depth(&relayed(33)));
 }
 
 #[test]
@@ -173,6 +329,41 @@ fn decode_http() {
     assert_eq!(Some(Value::from(7)),latency);
 }
 
+#[test]
+fn decode_http_split_headers() {
+    let pcaps = &[
+        "pcaps/http/split-headers.pcap",
+        "pcaps/http/split-header-value.pcap",
+        "pcaps/http/split-bytes.pcap",
+    ];
+
+    for pcap in pcaps {
+        let mut customs  = Customs::new(&CUSTOMS);
+        let mut classify = Classify::new();
+        let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+        let mut values = Vec::new();
+        for flow in iter::flows(pcap) {
+            let d = classify.find(&flow);
+            if decoders.decode(d, &flow, &mut customs) {
+                values = [HTTP_URL, HTTP_HOST, HTTP_UA, HTTP_REFERER, HTTP_STATUS].iter().map(|name| {
+                    value(name, &customs)
+                }).collect();
+            }
+            customs.clear();
+        }
+
+        let ua = "Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/115.0";
+        assert_eq!(vec![
+            Some(Value::from("/search?q=kprobe")),
+            Some(Value::from("www.example.com")),
+            Some(Value::from(ua)),
+            Some(Value::from("https://www.example.com/index.html")),
+            Some(Value::from(200)),
+        ], values, "{}", pcap);
+    }
+}
+
 #[test]
 fn decode_http_dir_correct() {
     let mut customs  = Customs::new(&CUSTOMS);
@@ -258,7 +449,7 @@ fn decode_dns() {
         customs.clear();
     }
 
-    let reply = "172.217.26.14/A;2404:6800:4004:809::200e/AAAA;alt2.aspmx.l.google.com/MX;ns2.google.com/NS;;alt4.aspmx.l.google.com/MX;aspmx.l.google.com/MX;ns4.google.com/NS;alt3.aspmx.l.google.com/MX;alt1.aspmx.l.google.com/MX;v=spf1 include:_spf.google.com ~all/TXT;;ns1.google.com/NS;;ns3.google.com/NS";
+    let reply = "172.217.26.14/A;2404:6800:4004:809::200e/AAAA;alt2.aspmx.l.google.com/MX;ns2.google.com/NS;;alt4.aspmx.l.google.com/MX;aspmx.l.google.com/MX;ns4.google.com/NS;alt3.aspmx.l.google.com/MX;alt1.aspmx.l.google.com/MX;v=spf1 include:_spf.google.com ~all/TXT;0 issue symantec.com/CAA;ns1.google.com/NS;0 issue pki.goog/CAA;ns3.google.com/NS";
 
     assert_eq!(Some(Value::from("google.com")), query_name);
     assert_eq!(Some(Value::from(255)), query_type);
@@ -267,6 +458,215 @@ fn decode_dns() {
     assert_eq!(Some(Value::from(44)), latency);
 }
 
+#[test]
+fn decode_dns_tcp() {
+    let mut customs  = Customs::new(&CUSTOMS);
+    let mut classify = classifier();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    classify.add(Protocol::TCP, 53, Decoder::DNS);
+
+    let mut messages = Vec::new();
+
+    for flow in iter::flows("pcaps/dns/tcp-pipelined.pcap") {
+        let d = classify.find(&flow);
+        let mut decoded = decoders.decode(d, &flow, &mut customs);
+        while decoded {
+            messages.push((value(DNS_QUERY_NAME, &customs), value(DNS_REPLY_DATA, &customs)));
+            customs.clear();
+            decoded = decoders.next(d, &mut customs);
+        }
+    }
+
+    let a = "192.0.2.1/A;192.0.2.2/A";
+    let b = "198.51.100.7/A";
+
+    assert_eq!(vec![
+        (Some(Value::from("a.example.com")), None),
+        (Some(Value::from("b.example.com")), None),
+        (Some(Value::from("a.example.com")), Some(Value::from(a))),
+        (Some(Value::from("b.example.com")), Some(Value::from(b))),
+    ], messages);
+}
+
+#[test]
+fn decode_dns_dnssec() {
+    let columns = [CUSTOMS, &[
+        custom(b"DNS_FLAGS\0",     26, KFLOW_CUSTOM_STR),
+        custom(b"DNS_EDNS_SIZE\0", 27, KFLOW_CUSTOM_U32),
+        custom(b"DNS_EDNS_DO\0",   28, KFLOW_CUSTOM_U32),
+    ]].concat();
+
+    let mut customs  = Customs::new(&columns);
+    let mut classify = classifier();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let flow = iter::flows("pcaps/dns/sns-pb.isc.org-dnssec.pcap").next().unwrap();
+    let d = classify.find(&flow);
+    assert!(decoders.decode(d, &flow, &mut customs));
+
+    assert_eq!(Some(Value::from("sns-pb.isc.org")), value(DNS_QUERY_NAME, &customs));
+    assert_eq!(Some(Value::from("rd")),             value(DNS_FLAGS, &customs));
+    assert_eq!(Some(Value::from(4096)),             value(DNS_EDNS_SIZE, &customs));
+    assert_eq!(Some(Value::from(1)),                value(DNS_EDNS_DO, &customs));
+}
+
+#[test]
+fn decode_dns_edns() {
+    let columns = [CUSTOMS, &[
+        custom(b"DNS_FLAGS\0",      26, KFLOW_CUSTOM_STR),
+        custom(b"DNS_EDNS_SIZE\0",  27, KFLOW_CUSTOM_U32),
+        custom(b"DNS_EDNS_DO\0",    28, KFLOW_CUSTOM_U32),
+        custom(b"DNS_EXT_RCODE\0",  29, KFLOW_CUSTOM_U32),
+        custom(b"DNS_ECS_ADDR\0",   30, KFLOW_CUSTOM_ADDR),
+        custom(b"DNS_ECS_PREFIX\0", 31, KFLOW_CUSTOM_U32),
+    ]].concat();
+
+    let mut customs  = Customs::new(&columns);
+    let mut classify = classifier();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let mut messages = Vec::new();
+
+    for flow in iter::flows("pcaps/dns/edns.pcap") {
+        let d = classify.find(&flow);
+        if decoders.decode(d, &flow, &mut customs) {
+            messages.push(vec![
+                value(DNS_FLAGS, &customs),
+                value(DNS_EDNS_SIZE, &customs),
+                value(DNS_EDNS_DO, &customs),
+                value(DNS_EXT_RCODE, &customs),
+                value(DNS_ECS_ADDR, &customs),
+                value(DNS_ECS_PREFIX, &customs),
+                value(DNS_REPLY_DATA, &customs),
+            ]);
+        }
+        customs.clear();
+    }
+
+    let ecs   = Value::from("198.51.100.0".parse::<IpAddr>().unwrap());
+    let reply = "1 . alpn=h2,h3 ipv4hint=192.0.2.10/HTTPS;0 issue letsencrypt.org/CAA;10 60 5060 sip.example.com/SRV";
+
+    assert_eq!(vec![
+        vec![
+            Some(Value::from("rd ad")), Some(Value::from(1232)), Some(Value::from(0)),
+            None, Some(ecs.clone()), Some(Value::from(24)), None,
+        ],
+        vec![
+            Some(Value::from("qr rd ra ad")), Some(Value::from(1232)), Some(Value::from(1)),
+            Some(Value::from(0)), Some(ecs), Some(Value::from(24)), Some(Value::from(reply)),
+        ],
+        vec![
+            Some(Value::from("rd")), Some(Value::from(1232)), Some(Value::from(0)),
+            None, None, None, None,
+        ],
+        vec![
+            Some(Value::from("qr rd ra")), Some(Value::from(1232)), Some(Value::from(0)),
+            Some(Value::from(16)), None, None, Some(Value::from("")),
+        ],
+    ], messages);
+}
+
+#[test]
+fn decode_dns_timeouts() {
+    let columns = [CUSTOMS, &[
+        custom(b"DNS_RESOLVER\0",       26, KFLOW_CUSTOM_ADDR),
+        custom(b"DNS_SERVFAIL_COUNT\0", 27, KFLOW_CUSTOM_U32),
+        custom(b"DNS_NXDOMAIN_COUNT\0", 28, KFLOW_CUSTOM_U32),
+        custom(b"DNS_TIMEOUT_COUNT\0",  29, KFLOW_CUSTOM_U32),
+    ]].concat();
+
+    let mut customs  = Customs::new(&columns);
+    let mut classify = classifier();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let mut keys = Vec::new();
+    let mut last = Timestamp::zero();
+
+    for flow in iter::flows("pcaps/dns/failures.pcap") {
+        decoders.decode(classify.find(&flow), &flow, &mut customs);
+        keys.push(flow.key());
+        last = flow.timestamp;
+        customs.clear();
+    }
+
+    let (query, reply) = (keys[0], keys[1]);
+
+    assert!(!decoders.expire(Decoder::DNS, &query, last + Duration::seconds(1), &mut customs));
+    assert!(!decoders.expire(Decoder::DNS, &reply, last + Duration::seconds(6), &mut customs));
+    assert!(decoders.expire(Decoder::DNS, &query, last + Duration::seconds(6), &mut customs));
+
+    let resolver = "10.0.0.53".parse::<IpAddr>().unwrap();
+
+    assert_eq!(Some(Value::from("c.example.com")), value(DNS_QUERY_NAME, &customs));
+    assert_eq!(Some(Value::from(28)),              value(DNS_QUERY_TYPE, &customs));
+    assert_eq!(Some(Value::from(0xFFFF)),          value(DNS_REPLY_CODE, &customs));
+    assert_eq!(Some(Value::from(resolver)),        value(DNS_RESOLVER, &customs));
+    customs.clear();
+
+    assert!(decoders.next(Decoder::DNS, &mut customs));
+
+    assert_eq!(Some(Value::from("d.example.com")), value(DNS_QUERY_NAME, &customs));
+    assert_eq!(Some(Value::from(1)),               value(DNS_QUERY_TYPE, &customs));
+    assert_eq!(Some(Value::from(0xFFFF)),          value(DNS_REPLY_CODE, &customs));
+    assert_eq!(Some(Value::from(resolver)),        value(DNS_RESOLVER, &customs));
+    customs.clear();
+
+    assert!(!decoders.next(Decoder::DNS, &mut customs));
+
+    decoders.append(Decoder::DNS, &reply, &mut customs);
+    assert_eq!(None, value(DNS_TIMEOUT_COUNT, &customs));
+
+    decoders.append(Decoder::DNS, &query, &mut customs);
+    assert_eq!(Some(Value::from(1)), value(DNS_SERVFAIL_COUNT, &customs));
+    assert_eq!(Some(Value::from(1)), value(DNS_NXDOMAIN_COUNT, &customs));
+    assert_eq!(Some(Value::from(2)), value(DNS_TIMEOUT_COUNT, &customs));
+    customs.clear();
+
+    decoders.append(Decoder::DNS, &query, &mut customs);
+    assert_eq!(None, value(DNS_TIMEOUT_COUNT, &customs));
+}
+
+#[test]
+fn decode_dns_matching() {
+    let columns = [CUSTOMS, &[
+        custom(b"DNS_RETRANSMITS\0", 26, KFLOW_CUSTOM_U32),
+        custom(b"DNS_UNMATCHED\0",   27, KFLOW_CUSTOM_U32),
+    ]].concat();
+
+    let mut customs  = Customs::new(&columns);
+    let mut classify = classifier();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let mut messages = Vec::new();
+
+    for flow in iter::flows("pcaps/dns/matching.pcap") {
+        let d = classify.find(&flow);
+        if decoders.decode(d, &flow, &mut customs) {
+            messages.push((
+                value(DNS_QUERY_NAME, &customs),
+                value(APP_LATENCY, &customs),
+                value(DNS_RETRANSMITS, &customs),
+                value(DNS_UNMATCHED, &customs),
+            ));
+        }
+        customs.clear();
+    }
+
+    let name = |s: &str| Some(Value::from(s));
+    let n    = |n: u32| Some(Value::from(n));
+
+    assert_eq!(vec![
+        (name("a.example.com"), None,  None, None),
+        (name("b.example.com"), None,  None, None),
+        (name("a.example.com"), None,  n(1), None),
+        (name("B.Example.Com"), n(10), None, None),
+        (name("c.example.com"), n(0),  None, n(1)),
+        (name("a.example.com"), n(25), n(1), None),
+        (name("a.example.com"), n(0),  None, n(2)),
+    ], messages);
+}
+
 #[test]
 fn decode_tls_handshake() {
     let mut customs  = Customs::new(&CUSTOMS);
@@ -350,6 +750,8 @@ fn app_protocol_ok() {
         customs.append(&Counter{
             ethernet:  flow.ethernet,
             direction: flow.direction,
+            method:    flow.method,
+            ifindex:   flow.ifindex,
             tos:       flow.tos,
             tcp_flags: flow.tcp_flags(),
             bytes:     flow.bytes as u64,
@@ -0,0 +1,132 @@
+use pnet::datalink::NetworkInterface;
+use crate::custom::*;
+use crate::direction::{Config, Infer, Method};
+use crate::flow::*;
+use crate::libkflow::*;
+use crate::queue::Counter;
+use crate::protocol::Decoder;
+use crate::time::Timestamp;
+use super::{custom, flow, value, Value};
+
+#[test]
+fn test_direction_mac() {
+    let infer = Infer::new(Config::default(), &interface("00:0a:0b:0c:0d:0e", &[]));
+
+    let a = flow(1, 2, true);
+    let mut b = flow(2, 1, true);
+    b.ethernet.src = a.ethernet.dst;
+    b.ethernet.dst = a.ethernet.src;
+
+    assert_eq!((Direction::In,  Method::Mac), infer.infer(&a));
+    assert_eq!((Direction::Out, Method::Mac), infer.infer(&b));
+}
+
+#[test]
+fn test_direction_interface() {
+    let infer = Infer::new(Config::default(), &interface("02:00:00:00:00:01", &["0.0.0.7/24"]));
+
+    let a = flow(7, 1024 << 8, true);
+    let b = flow(1024 << 8, 7, true);
+
+    assert_eq!((Direction::Out, Method::Interface), infer.infer(&a));
+    assert_eq!((Direction::In,  Method::Interface), infer.infer(&b));
+}
+
+#[test]
+fn test_direction_local() {
+    let cfg = Config {
+        methods: vec![Method::Local],
+        local:   vec!["0.0.0.0/24".parse().unwrap()],
+    };
+    let infer = Infer::new(cfg, &interface("02:00:00:00:00:01", &[]));
+
+    assert_eq!((Direction::Out,     Method::Local), infer.infer(&flow(7, 4096, true)));
+    assert_eq!((Direction::In,      Method::Local), infer.infer(&flow(4096, 7, true)));
+    assert_eq!((Direction::Unknown, Method::None),  infer.infer(&flow(7, 9, true)));
+    assert_eq!((Direction::Unknown, Method::None),  infer.infer(&flow(4096, 8192, true)));
+}
+
+#[test]
+fn test_direction_port() {
+    let cfg = Config {
+        methods: vec![Method::Port],
+        local:   Vec::new(),
+    };
+    let infer = Infer::new(cfg, &interface("02:00:00:00:00:01", &[]));
+
+    let port = |src, dst, protocol| {
+        let mut flow = flow(1, 2, true);
+        flow.src.port = src;
+        flow.dst.port = dst;
+        flow.protocol = protocol;
+        infer.infer(&flow)
+    };
+
+    assert_eq!((Direction::Out,     Method::Port), port(51234, 443,   Protocol::TCP));
+    assert_eq!((Direction::In,      Method::Port), port(5432,  41000, Protocol::TCP));
+    assert_eq!((Direction::Out,     Method::Port), port(33000, 53,    Protocol::UDP));
+    assert_eq!((Direction::Unknown, Method::None), port(51234, 41000, Protocol::TCP));
+    assert_eq!((Direction::Unknown, Method::None), port(80,    443,   Protocol::TCP));
+    assert_eq!((Direction::Unknown, Method::None), port(0,     443,   Protocol::ICMP));
+}
+
+#[test]
+fn test_direction_method_order() {
+    let cfg = Config {
+        methods: vec![Method::Port, Method::Mac],
+        local:   Vec::new(),
+    };
+    let infer = Infer::new(cfg, &interface("00:0a:0b:0c:0d:0e", &[]));
+
+    let mut a = flow(1, 2, true);
+    a.src.port = 80;
+    a.dst.port = 50000;
+    assert_eq!((Direction::In, Method::Port), infer.infer(&a));
+
+    a.src.port = 50001;
+    assert_eq!((Direction::In, Method::Mac), infer.infer(&a));
+}
+
+#[test]
+fn test_direction_method_custom() {
+    let mut customs = Customs::new(&DIRECTION_CUSTOMS);
+    let flow = flow(1, 2, true);
+
+    let mut ctr = Counter{
+        ethernet:  flow.ethernet,
+        direction: Direction::In,
+        method:    Method::Local,
+        ifindex:   0,
+        tos:       flow.tos,
+        tcp_flags: flow.tcp_flags(),
+        bytes:     flow.bytes as u64,
+        packets:   flow.packets as u64,
+        fragments: 0,
+        decoder:   Decoder::None,
+        export:    Timestamp::zero(),
+    };
+
+    customs.append(&ctr);
+    assert_eq!(Some(Value::from(3)), value(DIRECTION_METHOD, &customs));
+
+    customs.clear();
+    ctr.method = Method::None;
+    customs.append(&ctr);
+    assert_eq!(None, value(DIRECTION_METHOD, &customs));
+}
+
+fn interface(mac: &str, ips: &[&str]) -> NetworkInterface {
+    NetworkInterface {
+        name:  "test".to_owned(),
+        index: 1,
+        mac:   Some(mac.parse().unwrap()),
+        ips:   ips.iter().map(|ip| ip.parse().unwrap()).collect(),
+        flags: 0,
+        description: "".to_owned(),
+    }
+}
+
+const DIRECTION_CUSTOMS: &[kflowCustom] = &[
+    custom(b"FRAGMENTS\0",        1, KFLOW_CUSTOM_U32),
+    custom(b"DIRECTION_METHOD\0", 2, KFLOW_CUSTOM_U32),
+];
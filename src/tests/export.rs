@@ -183,3 +183,26 @@ fn expired_flows_removed_on_compact() {
     queue.export(export);
     assert_eq!(0, queue.len());
 }
+
+#[test]
+fn flow_ports_from_ifindex() {
+    let customs   = Customs::new(&[]);
+    let mut queue = FlowQueue::new(None, customs, classifier(), true);
+
+    let mut a = flow(23, 31, true);
+    let mut b = flow(31, 23, true);
+    a.direction = Direction::In;
+    a.ifindex   = 7;
+    b.direction = Direction::Out;
+
+    queue.add(a.clone());
+    queue.add(b.clone());
+
+    let kflow = crate::libkflow::flow(&a.key(), &queue[&a.key()], 1, 0);
+    assert_eq!(7, kflow.inputPort);
+    assert_eq!(0, kflow.outputPort);
+
+    let kflow = crate::libkflow::flow(&b.key(), &queue[&b.key()], 1, 0);
+    assert_eq!(0,      kflow.inputPort);
+    assert_eq!(0x0405, kflow.outputPort);
+}
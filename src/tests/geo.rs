@@ -0,0 +1,232 @@
+use std::env;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::process;
+use time::Duration;
+use crate::flow::*;
+use crate::geo::{self, Geo, Info};
+use crate::libkflow::kflow;
+use crate::time::Timestamp;
+
+#[test]
+fn test_geo_lookup() {
+    let (root, cfg) = databases("lookup");
+    let mut geo = Geo::new(cfg);
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(Info {
+        asn:     15169,
+        country: u32::from_be_bytes([0, 0, b'U', b'S']),
+        region:  5332921,
+        city:    5375480,
+    }, geo.lookup("8.8.8.8".parse().unwrap()));
+
+    assert_eq!(Info {
+        asn:     3320,
+        country: u32::from_be_bytes([0, 0, b'D', b'E']),
+        region:  0,
+        city:    0,
+    }, geo.lookup("80.128.0.1".parse().unwrap()));
+
+    assert_eq!(Info::default(), geo.lookup("10.0.0.1".parse().unwrap()));
+    assert_eq!(Info::default(), geo.lookup("2001:db8::1".parse().unwrap()));
+}
+
+#[test]
+fn test_geo_append_kflow() {
+    let (root, cfg) = databases("append");
+    let mut geo = Geo::new(cfg);
+    fs::remove_dir_all(&root).unwrap();
+
+    let src = Addr{addr: "80.128.0.1".parse().unwrap(), port: 41234};
+    let dst = Addr{addr: "8.8.8.8".parse().unwrap(),    port: 53};
+    let key = Key(Protocol::UDP, src, dst);
+
+    let mut kflow = kflow::default();
+    geo.append(&key, &mut kflow);
+
+    assert_eq!(3320,    kflow.srcAs);
+    assert_eq!(15169,   kflow.dstAs);
+    assert_eq!(0x4445,  kflow.srcGeo);
+    assert_eq!(0x5553,  kflow.dstGeo);
+    assert_eq!(5332921, kflow.dstGeoRegion);
+    assert_eq!(5375480, kflow.dstGeoCity);
+    assert_eq!(0,       kflow.srcGeoCity);
+}
+
+#[test]
+fn test_geo_missing_database() {
+    let mut geo = Geo::new(geo::Config {
+        asn:     Some(PathBuf::from("/nonexistent/GeoLite2-ASN.mmdb")),
+        city:    None,
+        country: None,
+    });
+
+    assert_eq!(Info::default(), geo.lookup("8.8.8.8".parse().unwrap()));
+}
+
+#[test]
+fn test_geo_refresh_modified() {
+    let (root, cfg) = databases("refresh");
+    let path = cfg.asn.clone().unwrap();
+    let mut geo = Geo::new(cfg);
+
+    let addr = "8.8.8.8".parse().unwrap();
+    let ts   = Timestamp::now();
+    assert_eq!(15169, geo.lookup(addr).asn);
+
+    let modified = fs::metadata(&path).unwrap().modified().unwrap();
+    let asn = |n| {
+        write(&path, "GeoLite2-ASN", &[
+            (Ipv4Addr::new(8, 8, 8, 0), 24, map(&[("autonomous_system_number", u32v(n))])),
+        ]);
+    };
+    let touch = |t| {
+        fs::File::options().write(true).open(&path).unwrap().set_modified(t).unwrap();
+    };
+
+    // a file with an unchanged modification time isn't reloaded
+    asn(64512);
+    touch(modified);
+    geo.refresh(ts);
+    assert_eq!(15169, geo.lookup(addr).asn);
+
+    touch(modified + std::time::Duration::from_secs(60));
+    geo.refresh(ts + Duration::minutes(5));
+    assert_eq!(64512, geo.lookup(addr).asn);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+fn databases(name: &str) -> (PathBuf, geo::Config) {
+    let root = env::temp_dir().join(format!("kprobe-geo-{}-{}", name, process::id()));
+    fs::create_dir_all(&root).unwrap();
+
+    let asn = root.join("asn.mmdb");
+    write(&asn, "GeoLite2-ASN", &[
+        (Ipv4Addr::new(8, 8, 8, 0),   24, map(&[("autonomous_system_number", u32v(15169))])),
+        (Ipv4Addr::new(80, 128, 0, 0), 11, map(&[("autonomous_system_number", u32v(3320))])),
+    ]);
+
+    let city = root.join("city.mmdb");
+    write(&city, "GeoLite2-City", &[
+        (Ipv4Addr::new(8, 8, 8, 0), 24, map(&[
+            ("city",         map(&[("geoname_id", u32v(5375480))])),
+            ("country",      map(&[("iso_code", string("US"))])),
+            ("subdivisions", array(&[map(&[("geoname_id", u32v(5332921))])])),
+        ])),
+    ]);
+
+    let country = root.join("country.mmdb");
+    write(&country, "GeoLite2-Country", &[
+        (Ipv4Addr::new(80, 128, 0, 0), 11, map(&[("country", map(&[("iso_code", string("DE"))]))])),
+    ]);
+
+    (root.clone(), geo::Config {
+        asn:     Some(asn),
+        city:    Some(city),
+        country: Some(country),
+    })
+}
+
+// Write a minimal IPv4 MaxMind DB with 24-bit records.
+fn write(path: &Path, kind: &str, nets: &[(Ipv4Addr, usize, Vec<u8>)]) {
+    #[derive(Clone, Copy)]
+    enum Record { Empty, Node(usize), Data(usize) }
+
+    let mut nodes = vec![[Record::Empty; 2]];
+    let mut data  = Vec::new();
+
+    for (addr, len, value) in nets {
+        let bits = u32::from(*addr);
+        let mut node = 0;
+
+        for i in 0..*len {
+            let bit = ((bits >> (31 - i)) & 1) as usize;
+            if i == len - 1 {
+                nodes[node][bit] = Record::Data(data.len());
+            } else if let Record::Node(next) = nodes[node][bit] {
+                node = next;
+            } else {
+                nodes.push([Record::Empty; 2]);
+                nodes[node][bit] = Record::Node(nodes.len() - 1);
+                node = nodes.len() - 1;
+            }
+        }
+
+        data.extend_from_slice(value);
+    }
+
+    let count = nodes.len();
+    let mut db = Vec::new();
+
+    for node in &nodes {
+        for record in node {
+            let n = match *record {
+                Record::Empty   => count,
+                Record::Node(n) => n,
+                Record::Data(n) => count + 16 + n,
+            } as u32;
+            db.extend_from_slice(&n.to_be_bytes()[1..]);
+        }
+    }
+
+    db.extend_from_slice(&[0u8; 16]);
+    db.extend_from_slice(&data);
+    db.extend_from_slice(b"\xAB\xCD\xEFMaxMind.com");
+    db.extend_from_slice(&map(&[
+        ("binary_format_major_version", u16v(2)),
+        ("binary_format_minor_version", u16v(0)),
+        ("build_epoch",                 u64v(0)),
+        ("database_type",               string(kind)),
+        ("description",                 map(&[])),
+        ("ip_version",                  u16v(4)),
+        ("languages",                   array(&[])),
+        ("node_count",                  u32v(count as u32)),
+        ("record_size",                 u16v(24)),
+    ]));
+
+    fs::write(path, db).unwrap();
+}
+
+fn string(s: &str) -> Vec<u8> {
+    let mut v = vec![2 << 5 | s.len() as u8];
+    v.extend_from_slice(s.as_bytes());
+    v
+}
+
+fn u16v(n: u16) -> Vec<u8> {
+    let mut v = vec![5 << 5 | 2];
+    v.extend_from_slice(&n.to_be_bytes());
+    v
+}
+
+fn u32v(n: u32) -> Vec<u8> {
+    let mut v = vec![6 << 5 | 4];
+    v.extend_from_slice(&n.to_be_bytes());
+    v
+}
+
+fn u64v(n: u64) -> Vec<u8> {
+    let mut v = vec![8, 9 - 7];
+    v.extend_from_slice(&n.to_be_bytes());
+    v
+}
+
+fn map(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    let mut v = vec![7 << 5 | entries.len() as u8];
+    for (key, value) in entries {
+        v.extend(string(key));
+        v.extend_from_slice(value);
+    }
+    v
+}
+
+fn array(items: &[Vec<u8>]) -> Vec<u8> {
+    let mut v = vec![items.len() as u8, 11 - 7];
+    for item in items {
+        v.extend_from_slice(item);
+    }
+    v
+}
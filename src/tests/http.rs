@@ -0,0 +1,47 @@
+use crate::custom::Customs;
+use crate::protocol::{self, Classify, Decoders};
+use super::*;
+
+const PORT: u16 = 80;
+
+#[test]
+fn decode_http_fields() {
+    let columns = [CUSTOMS, &[
+        custom(b"HTTP_METHOD\0",            26, KFLOW_CUSTOM_STR),
+        custom(b"HTTP_VERSION\0",           27, KFLOW_CUSTOM_STR),
+        custom(b"HTTP_REQ_LENGTH\0",        28, KFLOW_CUSTOM_U32),
+        custom(b"HTTP_RES_LENGTH\0",        29, KFLOW_CUSTOM_U32),
+        custom(b"HTTP_CONTENT_TYPE\0",      30, KFLOW_CUSTOM_STR),
+        custom(b"HTTP_CLIENT_IP\0",         31, KFLOW_CUSTOM_ADDR),
+        custom(b"HTTP_HEADER_X_TRACE_ID\0", 32, KFLOW_CUSTOM_STR),
+    ]].concat();
+
+    let mut customs  = Customs::new(&columns);
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let mut cfg = protocol::Config::default();
+    cfg.http_headers = vec!["X-Trace-Id".to_owned(), "X-Missing".to_owned()];
+    decoders.configure(&cfg);
+
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true,  SYN,       b"");
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, false, SYN | ACK, b"");
+
+    let req = b"PUT /items/1 HTTP/1.1\r\nHost: api\r\nx-trace-id: 4bf92f35\r\nX-Forwarded-For: 198.51.100.9\r\nContent-Length: 3\r\n\r\nabc";
+    decode(&mut decoders, &classify, &mut customs, PORT, 1, true, ACK, req);
+
+    let res = b"HTTP/1.1 201 Created\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{}";
+    decode(&mut decoders, &classify, &mut customs, PORT, 4, false, ACK, res);
+
+    assert_eq!(Some(Value::from("/items/1")),         value(HTTP_URL, &customs));
+    assert_eq!(Some(Value::from(201)),                value(HTTP_STATUS, &customs));
+    assert_eq!(Some(Value::from("PUT")),              value(HTTP_METHOD, &customs));
+    assert_eq!(Some(Value::from("1.1")),              value(HTTP_VERSION, &customs));
+    assert_eq!(Some(Value::from(3)),                  value(HTTP_REQ_LENGTH, &customs));
+    assert_eq!(Some(Value::from(2)),                  value(HTTP_RES_LENGTH, &customs));
+    assert_eq!(Some(Value::from("application/json")), value(HTTP_CONTENT_TYPE, &customs));
+    assert_eq!(Some(Value::from("4bf92f35")),         value("HTTP_HEADER_X_TRACE_ID", &customs));
+
+    let client = "198.51.100.9".parse::<IpAddr>().unwrap();
+    assert_eq!(Some(Value::from(client)), value(HTTP_CLIENT_IP, &customs));
+}
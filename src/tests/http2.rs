@@ -0,0 +1,315 @@
+use crate::custom::Customs;
+use crate::protocol::{self, Classify, Decoder, Decoders};
+use crate::protocol::http::h2::PREFACE;
+use crate::protocol::http::hpack;
+use super::*;
+
+const PORT: u16 = 80;
+
+const HEADERS:  u8 = 0x1;
+const SETTINGS: u8 = 0x4;
+const DATA:     u8 = 0x0;
+
+const END_STREAM:  u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+
+#[test]
+fn decode_h2c_grpc() {
+    let columns = columns();
+    let mut customs  = Customs::new(&columns);
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    handshake(&mut decoders, &classify, &mut customs);
+
+    let block = [
+        &[0x83, 0x86][..],
+        &literal(0x44, b"", b"/helloworld.Greeter/SayHello"),
+        &literal(0x41, b"", b"greeter:50051"),
+        &literal(0x5f, b"", b"application/grpc"),
+        &literal(0x7a, b"", b"grpc-go/1.60.0"),
+        &literal(0x00, b"te", b"trailers"),
+    ].concat();
+
+    let req = [
+        PREFACE,
+        &frame(SETTINGS, 0, 0, &[]),
+        &frame(HEADERS, END_HEADERS, 1, &block),
+        &frame(DATA, END_STREAM, 1, &[0, 0, 0, 0, 0]),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &req);
+
+    let res = [
+        frame(SETTINGS, 0, 0, &[]),
+        frame(HEADERS, END_HEADERS, 1, &[&[0x88][..], &literal(0x5f, b"", b"application/grpc")].concat()),
+        frame(DATA, 0, 1, &[0, 0, 0, 0, 2, 8, 1]),
+        frame(HEADERS, END_HEADERS | END_STREAM, 1, &literal(0x00, b"grpc-status", b"5")),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 7, false, ACK, &res);
+
+    assert_eq!(Some(Value::from("/helloworld.Greeter/SayHello")), value(HTTP_URL, &customs));
+    assert_eq!(Some(Value::from("greeter:50051")),                value(HTTP_HOST, &customs));
+    assert_eq!(Some(Value::from("grpc-go/1.60.0")),               value(HTTP_UA, &customs));
+    assert_eq!(Some(Value::from(200)),                            value(HTTP_STATUS, &customs));
+    assert_eq!(Some(Value::from("POST")),                         value(HTTP_METHOD, &customs));
+    assert_eq!(Some(Value::from("helloworld.Greeter")),           value(GRPC_SERVICE, &customs));
+    assert_eq!(Some(Value::from("SayHello")),                     value(GRPC_METHOD, &customs));
+    assert_eq!(Some(Value::from(5)),                              value(GRPC_STATUS, &customs));
+    assert_eq!(Some(Value::from(7)),                              value(APP_LATENCY, &customs));
+}
+
+#[test]
+fn decode_h2c_concurrent_streams() {
+    let mut customs  = Customs::new(&CUSTOMS);
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    handshake(&mut decoders, &classify, &mut customs);
+
+    // the second request refers to :authority in the dynamic table
+    let req = [
+        PREFACE,
+        &frame(SETTINGS, 0, 0, &[]),
+        &frame(HEADERS, END_HEADERS | END_STREAM, 1, &[
+            &[0x82, 0x86][..], &literal(0x04, b"", b"/a"), &literal(0x41, b"", b"api.local"),
+        ].concat()),
+        &frame(HEADERS, END_HEADERS | END_STREAM, 3, &[
+            &[0x82, 0x86][..], &literal(0x04, b"", b"/b"), &[0xbe],
+        ].concat()),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &req);
+
+    // stream 3 completes first, stream 1 DATA spans two segments
+    let res = [
+        frame(HEADERS, END_HEADERS | END_STREAM, 3, &[0x8d]),
+        frame(HEADERS, END_HEADERS, 1, &[0x88]),
+        frame(DATA, 0, 1, &[1; 10]),
+    ].concat();
+    let (a, b) = res.split_at(res.len() - 6);
+    decode(&mut decoders, &classify, &mut customs, PORT, 4, false, ACK, a);
+
+    assert_eq!(Some(Value::from("/b")),        value(HTTP_URL, &customs));
+    assert_eq!(Some(Value::from("api.local")), value(HTTP_HOST, &customs));
+    assert_eq!(Some(Value::from(404)),         value(HTTP_STATUS, &customs));
+    assert_eq!(None,                           value(GRPC_STATUS, &customs));
+
+    customs.clear();
+    let b = [b, &frame(DATA, END_STREAM, 1, &[])].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 9, false, ACK, &b);
+
+    assert_eq!(Some(Value::from("/a")),        value(HTTP_URL, &customs));
+    assert_eq!(Some(Value::from("api.local")), value(HTTP_HOST, &customs));
+    assert_eq!(Some(Value::from(200)),         value(HTTP_STATUS, &customs));
+    assert_eq!(Some(Value::from(9)),           value(APP_LATENCY, &customs));
+}
+
+#[test]
+fn decode_h2c_streams_in_segment() {
+    let columns = [CUSTOMS, &[
+        custom(b"HTTP_REQ_LENGTH\0",        26, KFLOW_CUSTOM_U32),
+        custom(b"HTTP_RES_LENGTH\0",        27, KFLOW_CUSTOM_U32),
+        custom(b"HTTP_CONTENT_TYPE\0",      28, KFLOW_CUSTOM_STR),
+        custom(b"HTTP_HEADER_X_TRACE_ID\0", 29, KFLOW_CUSTOM_STR),
+    ]].concat();
+
+    let mut customs  = Customs::new(&columns);
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let mut cfg = protocol::Config::default();
+    cfg.http_headers = vec!["X-Trace-Id".to_owned()];
+    decoders.configure(&cfg);
+
+    handshake(&mut decoders, &classify, &mut customs);
+
+    let req = [
+        PREFACE,
+        &frame(SETTINGS, 0, 0, &[]),
+        &frame(HEADERS, END_HEADERS, 1, &[
+            &[0x83, 0x86][..], &literal(0x04, b"", b"/a"), &literal(0x41, b"", b"api.local"),
+            &literal(0x00, b"x-trace-id", b"t1"),
+        ].concat()),
+        &frame(DATA, END_STREAM, 1, b"abc"),
+        &frame(HEADERS, END_HEADERS | END_STREAM, 3, &[
+            &[0x82, 0x86][..], &literal(0x04, b"", b"/b"), &[0xbe],
+        ].concat()),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &req);
+
+    // both streams complete in one segment, each exported in turn
+    let res = [
+        frame(HEADERS, END_HEADERS, 1, &[&[0x88][..], &literal(0x5f, b"", b"application/json")].concat()),
+        frame(DATA, END_STREAM, 1, b"{\"a\":1}"),
+        frame(HEADERS, END_HEADERS | END_STREAM, 3, &[&[0x8d][..], &literal(0x00, b"x-trace-id", b"t3")].concat()),
+    ].concat();
+    assert!(decode(&mut decoders, &classify, &mut customs, PORT, 4, false, ACK, &res));
+
+    assert_eq!(Some(Value::from("/a")),               value(HTTP_URL, &customs));
+    assert_eq!(Some(Value::from(200)),                value(HTTP_STATUS, &customs));
+    assert_eq!(Some(Value::from(3)),                  value(HTTP_REQ_LENGTH, &customs));
+    assert_eq!(Some(Value::from(7)),                  value(HTTP_RES_LENGTH, &customs));
+    assert_eq!(Some(Value::from("application/json")), value(HTTP_CONTENT_TYPE, &customs));
+    assert_eq!(Some(Value::from("t1")),               value("HTTP_HEADER_X_TRACE_ID", &customs));
+
+    customs.clear();
+    assert!(decoders.next(Decoder::HTTP, &mut customs));
+
+    assert_eq!(Some(Value::from("/b")),               value(HTTP_URL, &customs));
+    assert_eq!(Some(Value::from(404)),                value(HTTP_STATUS, &customs));
+    assert_eq!(Some(Value::from(0)),                  value(HTTP_REQ_LENGTH, &customs));
+    assert_eq!(Some(Value::from(0)),                  value(HTTP_RES_LENGTH, &customs));
+    assert_eq!(None,                                  value(HTTP_CONTENT_TYPE, &customs));
+    assert_eq!(Some(Value::from("t3")),               value("HTTP_HEADER_X_TRACE_ID", &customs));
+
+    customs.clear();
+    assert!(!decoders.next(Decoder::HTTP, &mut customs));
+}
+
+#[test]
+fn decode_h2c_upgrade() {
+    let mut customs  = Customs::new(&CUSTOMS);
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    handshake(&mut decoders, &classify, &mut customs);
+
+    let req = b"GET /upgrade HTTP/1.1\r\nHost: example.com\r\nConnection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAAP__\r\n\r\n";
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, req);
+
+    let res = b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n";
+    decode(&mut decoders, &classify, &mut customs, PORT, 1, false, ACK, res);
+
+    assert_eq!(None, value(HTTP_STATUS, &customs));
+
+    let req = [PREFACE, &frame(SETTINGS, 0, 0, &[])].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 2, true, ACK, &req);
+
+    let res = [
+        frame(SETTINGS, 0, 0, &[]),
+        frame(HEADERS, END_HEADERS | END_STREAM, 1, &[0x88]),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 5, false, ACK, &res);
+
+    assert_eq!(Some(Value::from("/upgrade")),    value(HTTP_URL, &customs));
+    assert_eq!(Some(Value::from("example.com")), value(HTTP_HOST, &customs));
+    assert_eq!(Some(Value::from(200)),           value(HTTP_STATUS, &customs));
+    assert_eq!(Some(Value::from(5)),             value(APP_LATENCY, &customs));
+}
+
+#[test]
+fn decode_h2c_invalid_header_block() {
+    let mut customs  = Customs::new(&CUSTOMS);
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    handshake(&mut decoders, &classify, &mut customs);
+
+    // index 70 is beyond the empty dynamic table
+    let req = [PREFACE, &frame(HEADERS, END_HEADERS | END_STREAM, 1, &[0xc6])].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &req);
+
+    let res = frame(HEADERS, END_HEADERS | END_STREAM, 1, &[0x88]);
+    decode(&mut decoders, &classify, &mut customs, PORT, 1, false, ACK, &res);
+
+    assert_eq!(None, value(HTTP_STATUS, &customs));
+}
+
+#[test]
+fn hpack_request_examples() {
+    let mut decoder = hpack::Decoder::new();
+
+    // RFC 7541 C.4, requests with Huffman coding
+    let blocks = [
+        &b"\x82\x86\x84\x41\x8c\xf1\xe3\xc2\xe5\xf2\x3a\x6b\xa0\xab\x90\xf4\xff"[..],
+        b"\x82\x86\x84\xbe\x58\x86\xa8\xeb\x10\x64\x9c\xbf",
+        b"\x82\x87\x85\xbf\x40\x88\x25\xa8\x49\xe9\x5b\xa9\x7d\x7f\x89\x25\xa8\x49\xe9\x5b\xb8\xe8\xb4\xbf",
+    ];
+
+    let headers = decoder.decode(blocks[0]).unwrap();
+    assert_eq!(vec![
+        (":method",    "GET"),
+        (":scheme",    "http"),
+        (":path",      "/"),
+        (":authority", "www.example.com"),
+    ], strs(&headers));
+
+    let headers = decoder.decode(blocks[1]).unwrap();
+    assert_eq!(vec![
+        (":method",       "GET"),
+        (":scheme",       "http"),
+        (":path",         "/"),
+        (":authority",    "www.example.com"),
+        ("cache-control", "no-cache"),
+    ], strs(&headers));
+
+    let headers = decoder.decode(blocks[2]).unwrap();
+    assert_eq!(vec![
+        (":method",    "GET"),
+        (":scheme",    "https"),
+        (":path",      "/index.html"),
+        (":authority", "www.example.com"),
+        ("custom-key", "custom-value"),
+    ], strs(&headers));
+}
+
+#[test]
+fn hpack_response_example() {
+    let mut decoder = hpack::Decoder::new();
+
+    // RFC 7541 C.6.1, response with Huffman coding
+    let block = [
+        &b"\x48\x82\x64\x02\x58\x85\xae\xc3\x77\x1a\x4b\x61\x96\xd0\x7a\xbe"[..],
+        b"\x94\x10\x54\xd4\x44\xa8\x20\x05\x95\x04\x0b\x81\x66\xe0\x82\xa6",
+        b"\x2d\x1b\xff\x6e\x91\x9d\x29\xad\x17\x18\x63\xc7\x8f\x0b\x97\xc8",
+        b"\xe9\xae\x82\xae\x43\xd3",
+    ].concat();
+
+    let headers = decoder.decode(&block).unwrap();
+    assert_eq!(vec![
+        (":status",       "302"),
+        ("cache-control", "private"),
+        ("date",          "Mon, 21 Oct 2013 20:13:21 GMT"),
+        ("location",      "https://www.example.com"),
+    ], strs(&headers));
+
+    // padding longer than 7 bits is invalid
+    assert_eq!(None, decoder.decode(b"\x00\x01a\x82\xff\xff"));
+}
+
+fn handshake(ds: &mut Decoders, classify: &Classify, cs: &mut Customs) {
+    decode(ds, classify, cs, PORT, 0, true,  SYN,       &[]);
+    decode(ds, classify, cs, PORT, 0, false, SYN | ACK, &[]);
+}
+
+
+fn frame(kind: u8, flags: u8, stream: u32, payload: &[u8]) -> Vec<u8> {
+    let len = (payload.len() as u32).to_be_bytes();
+    [&len[1..], &[kind, flags], &stream.to_be_bytes(), payload].concat()
+}
+
+// Literal header without Huffman coding, with a new name when the
+// prefix byte has no name index.
+fn literal(prefix: u8, name: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut vec = vec![prefix];
+    if !name.is_empty() {
+        vec.push(name.len() as u8);
+        vec.extend_from_slice(name);
+    }
+    vec.push(value.len() as u8);
+    vec.extend_from_slice(value);
+    vec
+}
+
+fn strs(headers: &[hpack::Header]) -> Vec<(&str, &str)> {
+    headers.iter().map(|&(ref n, ref v)| {
+        (::std::str::from_utf8(n).unwrap(), ::std::str::from_utf8(v).unwrap())
+    }).collect()
+}
+
+fn columns() -> Vec<kflowCustom> {
+    [CUSTOMS, &[
+        custom(b"HTTP_METHOD\0",            26, KFLOW_CUSTOM_STR),
+        custom(b"GRPC_SERVICE\0",           27, KFLOW_CUSTOM_STR),
+        custom(b"GRPC_METHOD\0",            28, KFLOW_CUSTOM_STR),
+    ]].concat()
+}
@@ -0,0 +1,173 @@
+use std::env;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::process;
+use crate::custom::*;
+use crate::flow::*;
+use crate::k8s::{self, Kubernetes};
+use crate::libkflow::*;
+use super::{custom, value, Value};
+
+const PODS: &str = r#"{
+  "apiVersion": "v1",
+  "kind": "PodList",
+  "items": [
+    {
+      "metadata": {
+        "name": "web-7c9d8f6b5d-x2k4q",
+        "namespace": "shop",
+        "ownerReferences": [
+          { "kind": "ReplicaSet", "name": "web-7c9d8f6b5d", "controller": true }
+        ]
+      },
+      "spec":   { "nodeName": "node-1" },
+      "status": { "podIP": "10.244.1.7", "podIPs": [{ "ip": "10.244.1.7" }, { "ip": "fd00::7" }] }
+    },
+    {
+      "metadata": {
+        "name": "db-0",
+        "namespace": "shop",
+        "ownerReferences": [
+          { "kind": "StatefulSet", "name": "db", "controller": true }
+        ]
+      },
+      "spec":   { "nodeName": "node-2" },
+      "status": { "podIP": "10.244.2.3" }
+    },
+    {
+      "metadata": { "name": "kube-proxy-abcde", "namespace": "kube-system" },
+      "spec":   { "nodeName": "node-1", "hostNetwork": true },
+      "status": { "podIP": "192.168.1.10" }
+    }
+  ]
+}"#;
+
+const CRICTL: &str = r#"
+{ "status": { "metadata": { "name": "agent-x7z2p", "namespace": "monitoring" },
+              "network":  { "ip": "10.244.1.9", "additionalIps": [] } } }
+{ "status": { "metadata": { "name": "api-5f7b9c6d4-qwert", "namespace": "default" },
+              "network":  { "ip": "10.244.1.10" } } }
+"#;
+
+#[test]
+fn test_k8s_snapshot_pod_list() {
+    let pods = k8s::snapshot(PODS).unwrap();
+
+    let pod = |addr: &str| {
+        let addr = addr.parse::<IpAddr>().unwrap();
+        pods.iter().find(|(ip, _)| *ip == addr).map(|(_, pod)| pod)
+    };
+
+    assert_eq!(3, pods.len());
+
+    let web = pod("10.244.1.7").unwrap();
+    assert_eq!("web-7c9d8f6b5d-x2k4q", web.name.to_str().unwrap());
+    assert_eq!("shop",                 web.namespace.to_str().unwrap());
+    assert_eq!("web",                  web.workload.to_str().unwrap());
+    assert_eq!("node-1",               web.node.to_str().unwrap());
+    assert!(pod("fd00::7").is_some());
+
+    let db = pod("10.244.2.3").unwrap();
+    assert_eq!("db",     db.workload.to_str().unwrap());
+    assert_eq!("node-2", db.node.to_str().unwrap());
+
+    assert!(pod("192.168.1.10").is_none());
+}
+
+#[test]
+fn test_k8s_snapshot_crictl() {
+    let pods = k8s::snapshot(CRICTL).unwrap();
+
+    assert_eq!(2, pods.len());
+
+    let (ip, pod) = &pods[0];
+    assert_eq!("10.244.1.9".parse::<IpAddr>().unwrap(), *ip);
+    assert_eq!("monitoring", pod.namespace.to_str().unwrap());
+    assert_eq!("agent",      pod.workload.to_str().unwrap());
+
+    let (_, pod) = &pods[1];
+    assert_eq!("api", pod.workload.to_str().unwrap());
+}
+
+#[test]
+fn test_k8s_kubelet_scan() {
+    let root = tmpdir("kubelet");
+    let pods = root.join("pods");
+    let logs = root.join("logs");
+
+    fs::create_dir_all(pods.join("1234-uid")).unwrap();
+    fs::create_dir_all(logs.join("shop_cart-2_1234-uid")).unwrap();
+    fs::create_dir_all(logs.join("shop_gone-1_5678-uid")).unwrap();
+    fs::write(pods.join("1234-uid").join("etc-hosts"), [
+        "127.0.0.1\tlocalhost",
+        "::1\tlocalhost ip6-localhost",
+        "10.244.3.4\tcart-2",
+    ].join("\n")).unwrap();
+
+    let found = k8s::kubelet(&pods, &logs, "node-3").unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(1, found.len());
+
+    let (ip, pod) = &found[0];
+    assert_eq!("10.244.3.4".parse::<IpAddr>().unwrap(), *ip);
+    assert_eq!("cart-2", pod.name.to_str().unwrap());
+    assert_eq!("shop",   pod.namespace.to_str().unwrap());
+    assert_eq!("cart",   pod.workload.to_str().unwrap());
+    assert_eq!("node-3", pod.node.to_str().unwrap());
+}
+
+#[test]
+fn test_k8s_append_customs() {
+    let root = tmpdir("snapshot");
+    let path = root.join("pods.json");
+    fs::create_dir_all(&root).unwrap();
+    fs::write(&path, PODS).unwrap();
+
+    let customs = Customs::new(&K8S_CUSTOMS);
+    let k8s = Kubernetes::new(k8s::Config {
+        kubelet:  false,
+        snapshot: Some(path),
+        node:     "node-1".to_owned(),
+    }, &customs);
+    fs::remove_dir_all(&root).unwrap();
+
+    let src = Addr{addr: "10.244.1.7".parse().unwrap(), port: 41234};
+    let dst = Addr{addr: "10.244.2.3".parse().unwrap(), port: 5432};
+    let key = Key(Protocol::TCP, src, dst);
+
+    let mut customs = customs;
+    k8s.append(&key, &mut customs);
+
+    assert_eq!(Some(Value::from("web-7c9d8f6b5d-x2k4q")), value(K8S_SRC_POD_NAME,  &customs));
+    assert_eq!(Some(Value::from("shop")),                 value(K8S_SRC_NAMESPACE, &customs));
+    assert_eq!(Some(Value::from("web")),                  value(K8S_SRC_WORKLOAD,  &customs));
+    assert_eq!(Some(Value::from("node-1")),               value(K8S_SRC_NODE,      &customs));
+    assert_eq!(Some(Value::from("db-0")),                 value(K8S_DST_POD_NAME,  &customs));
+    assert_eq!(Some(Value::from("db")),                   value(K8S_DST_WORKLOAD,  &customs));
+    assert_eq!(None,                                      value(K8S_DST_NODE,      &customs));
+
+    let src = Addr{addr: "10.0.0.1".parse().unwrap(), port: 41234};
+    let key = Key(Protocol::TCP, src, dst);
+
+    customs.clear();
+    k8s.append(&key, &mut customs);
+
+    assert_eq!(None,                      value(K8S_SRC_POD_NAME, &customs));
+    assert_eq!(Some(Value::from("db-0")), value(K8S_DST_POD_NAME, &customs));
+}
+
+fn tmpdir(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("kprobe-k8s-{}-{}", name, process::id()))
+}
+
+const K8S_CUSTOMS: &[kflowCustom] = &[
+    custom(b"K8S_SRC_POD_NAME\0",  1, KFLOW_CUSTOM_STR),
+    custom(b"K8S_SRC_NAMESPACE\0", 2, KFLOW_CUSTOM_STR),
+    custom(b"K8S_SRC_WORKLOAD\0",  3, KFLOW_CUSTOM_STR),
+    custom(b"K8S_SRC_NODE\0",      4, KFLOW_CUSTOM_STR),
+    custom(b"K8S_DST_POD_NAME\0",  5, KFLOW_CUSTOM_STR),
+    custom(b"K8S_DST_NAMESPACE\0", 6, KFLOW_CUSTOM_STR),
+    custom(b"K8S_DST_WORKLOAD\0",  7, KFLOW_CUSTOM_STR),
+];
@@ -0,0 +1,161 @@
+use std::net::IpAddr;
+use pcap::Capture;
+use pnet::packet::ethernet::EthernetPacket;
+use crate::mirror::{Format, Frame, Mirror};
+use crate::packet::{self, Packet, Transport};
+use crate::reasm::Reassembler;
+use crate::time::Timestamp;
+
+#[test]
+fn mirror_format_parse() {
+    assert_eq!(Format::Juniper,    "juniper".parse().unwrap());
+    assert_eq!(Format::Erspan,     "erspan".parse().unwrap());
+    assert_eq!(Format::Trailer(8), "arista".parse().unwrap());
+    assert_eq!(Format::Trailer(4), "trailer:4".parse().unwrap());
+    assert_eq!(Format::Fixed(12),  "fixed:12".parse().unwrap());
+
+    assert!("fixed".parse::<Format>().is_err());
+    assert!("juniper:8".parse::<Format>().is_err());
+    assert!("trailer:x".parse::<Format>().is_err());
+    assert!("gre".parse::<Format>().is_err());
+}
+
+#[test]
+fn mirror_decap_juniper() {
+    let mirror = Mirror::new(Format::Juniper, vec![30000]);
+    let frames = decap(&mirror);
+
+    assert_eq!(vec![
+        Some(("10.0.0.53".parse().unwrap(), 40001)),
+        None,
+        None,
+        None,
+        None,
+    ], frames);
+
+    let mirror = Mirror::new(Format::Juniper, Vec::new());
+    let frames = decap(&mirror);
+
+    assert_eq!(Some(("10.0.0.53".parse().unwrap(), 40002)), frames[1]);
+}
+
+#[test]
+fn mirror_decap_fixed() {
+    let juniper = decap(&Mirror::new(Format::Juniper,  Vec::new()));
+    let fixed   = decap(&Mirror::new(Format::Fixed(8), Vec::new()));
+    assert_eq!(juniper, fixed);
+
+    let fixed = decap(&Mirror::new(Format::Fixed(4), Vec::new()));
+    assert_eq!(vec![None; 5], fixed);
+}
+
+#[test]
+fn mirror_decap_erspan() {
+    let mirror = Mirror::new(Format::Erspan, Vec::new());
+    let frames = decap(&mirror);
+
+    assert_eq!(vec![
+        None,
+        None,
+        Some(("10.0.0.53".parse().unwrap(), 40003)),
+        Some(("10.0.0.53".parse().unwrap(), 40004)),
+        None,
+    ], frames);
+}
+
+#[test]
+fn mirror_decap_trailer() {
+    let mut cap = Capture::from_file("pcaps/mirror/mirror.pcap").unwrap();
+    let mirror  = Mirror::new(Format::Trailer(8), Vec::new());
+
+    while let Ok(pkt) = cap.next_packet() {
+        let n = pkt.data.len() - 8;
+        assert_eq!(Some(Frame::Ethernet(&pkt.data[..n])), mirror.decap(pkt.data));
+    }
+
+    let mirror = Mirror::new(Format::Trailer(1 << 16), Vec::new());
+    assert_eq!(None, mirror.decap(&[0u8; 64]));
+}
+
+#[test]
+fn mirror_decap_ipv6_extensions() {
+    let mirror = Mirror::new(Format::Juniper, vec![30000]);
+    let inner  = b"mirrored packet";
+
+    let mut udp = vec![0x75, 0x30, 0x75, 0x31, 0, 0, 0, 0];
+    udp.extend_from_slice(&[0u8; 8]);
+    udp.extend_from_slice(inner);
+    let len = udp.len() as u16;
+    udp[4..6].copy_from_slice(&len.to_be_bytes());
+
+    // hop-by-hop options followed by an atomic fragment header
+    let mut payload = vec![44, 0, 1, 4, 0, 0, 0, 0, 17, 0, 0, 0, 0, 0, 0, 1];
+    payload.extend_from_slice(&udp);
+
+    let mut frame = vec![0u8; 12];
+    frame.extend_from_slice(&[0x86, 0xDD, 0x60, 0, 0, 0]);
+    frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    frame.extend_from_slice(&[0, 64]);
+    frame.extend_from_slice(&[0u8; 32]);
+    frame.extend_from_slice(&payload);
+
+    assert_eq!(Some(Frame::IP(&inner[..])), mirror.decap(&frame));
+
+    // fragments aren't decapsulated
+    frame[14 + 40 + 8 + 3] = 0x01;
+    assert_eq!(None, mirror.decap(&frame));
+}
+
+#[test]
+fn mirror_decap_fragmented() {
+    let mut cap = Capture::from_file("pcaps/mirror/fragment.pcap").unwrap();
+    let mut asm = Reassembler::new();
+    let mirror  = Mirror::new(Format::Juniper, vec![30000]);
+    let mut frames = Vec::new();
+
+    while let Ok(pkt) = cap.next_packet() {
+        assert_eq!(None, mirror.decap(pkt.data));
+
+        let ts = Timestamp::from(pkt.header.ts);
+        frames.push(mirror.reassemble(&mut asm, ts, pkt.data).and_then(|data| {
+            match mirror.decap(&data)? {
+                Frame::IP(data) => packet::decode_from_l3(data).and_then(|pkt| reply(&pkt)),
+                _               => None,
+            }
+        }));
+    }
+
+    assert_eq!(vec![
+        None,
+        Some(("10.0.0.53".parse().unwrap(), 40006)),
+    ], frames);
+}
+
+// Source address and destination port of the DNS reply carried by each
+// frame of the mirror capture.
+fn decap(mirror: &Mirror) -> Vec<Option<(IpAddr, u16)>> {
+    let mut cap    = Capture::from_file("pcaps/mirror/mirror.pcap").unwrap();
+    let mut frames = Vec::new();
+
+    while let Ok(pkt) = cap.next_packet() {
+        frames.push(match mirror.decap(pkt.data) {
+            Some(Frame::Ethernet(data)) => {
+                let eth = EthernetPacket::new(data).unwrap();
+                packet::decode(&eth).1.and_then(|pkt| reply(&pkt))
+            },
+            Some(Frame::IP(data)) => {
+                packet::decode_from_l3(data).and_then(|pkt| reply(&pkt))
+            },
+            None => None,
+        });
+    }
+
+    frames
+}
+
+fn reply(pkt: &Packet) -> Option<(IpAddr, u16)> {
+    match pkt.transport(pkt.payload())? {
+        Transport::UDP(udp) if udp.get_source() == 53 => Some((pkt.src(), udp.get_destination())),
+        _                                             => None,
+    }
+}
@@ -4,6 +4,17 @@ mod decoders;
 mod sampling;
 mod export;
 mod modes;
+mod k8s;
+mod direction;
+mod geo;
+mod postgres;
+mod mysql;
+mod redis;
+mod http;
+mod http2;
+mod tls;
+mod quic;
+mod mirror;
 
 use std::borrow::Cow;
 use std::ffi::CStr;
@@ -20,7 +31,7 @@ use crate::libkflow::*;
 use crate::flow::*;
 use crate::packet;
 use crate::custom::*;
-use crate::protocol::{Classify, Decoder};
+use crate::protocol::{Classify, Decoder, Decoders};
 use crate::reasm::Reassembler;
 use crate::time::Timestamp;
 use crate::timer::{Timer, Timeout};
@@ -479,6 +490,29 @@ pub fn classifier() -> Classify {
     classify
 }
 
+// Decode a TCP segment sent ms milliseconds in, from the client to a
+// server listening on port or back.
+fn decode(ds: &mut Decoders, classify: &Classify, cs: &mut Customs, port: u16, ms: i64, client: bool, flags: u8, payload: &[u8]) -> bool {
+    let client_addr = Addr{addr: "10.0.0.1".parse().unwrap(), port: 40000};
+    let server_addr = Addr{addr: "10.0.0.2".parse().unwrap(), port: port};
+
+    let (src, dst) = match client {
+        true  => (client_addr, server_addr),
+        false => (server_addr, client_addr),
+    };
+
+    let flow = Flow{
+        timestamp: Timestamp::zero() + Duration::milliseconds(ms),
+        src:       src,
+        dst:       dst,
+        transport: Transport::TCP{seq: 0, flags: flags, window: Default::default()},
+        payload:   payload,
+        ..flow(0, 0, false)
+    };
+
+    ds.decode(classify.find(&flow), &flow, cs)
+}
+
 fn flow<'a>(src: u32, dst: u32, export: bool) -> Flow<'a> {
     Flow{
         timestamp: Timestamp::zero(),
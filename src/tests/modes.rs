@@ -1,4 +1,8 @@
-use crate::mode::{dns::Dns, radius};
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use crate::mirror::{Format, Mirror};
+use crate::mode::{dhcp, dns::{Dns, Segment, Stats}, dnstap, radius};
 use kentik_api::{dns, tag, AsyncClient, Client};
 use super::*;
 
@@ -13,7 +17,7 @@ fn dns_mode_parse() {
     let res   = flows.skip(1).next().unwrap();
 
     let packet = res.payload.to_vec();
-    let result = dns.parse(res.src, res.dst, &packet);
+    let result = dns.parse(res.src, res.dst, &packet, res.timestamp);
 
     let expect = Some(dns::Response {
         question: dns::Question {
@@ -22,13 +26,13 @@ fn dns_mode_parse() {
         },
         answers: vec![
             dns::Answer {
-                name:  String::new(),
+                name:  "google.com".to_owned(),
                 cname: String::new(),
                 ip:    vec![172, 217, 26, 14],
                 ttl:   299,
             },
             dns::Answer {
-                name:  String::new(),
+                name:  "google.com".to_owned(),
                 cname: String::new(),
                 ip:    vec![36, 4, 104, 0, 64, 4, 8, 9, 0, 0, 0, 0, 0, 0, 32, 14],
                 ttl:   299,
@@ -39,6 +43,272 @@ fn dns_mode_parse() {
     assert_eq!(expect, result);
 }
 
+#[test]
+fn dns_mode_parse_tcp() {
+    let client = AsyncClient::new("test@example.com", "token", "http://127.0.0.1", None);
+    let client = dns::Client::new(client.unwrap());
+
+    let mut dns = Dns::new(client);
+    let mut responses = Vec::new();
+
+    for flow in iter::flows("pcaps/dns/tcp-pipelined.pcap").filter(|f| f.src.port == 53) {
+        let rs = dns.parse_tcp(flow.src, flow.dst, flow.payload, flow.timestamp);
+        responses.push(rs.into_iter().map(|r| {
+            let ips = r.answers.into_iter().map(|a| a.ip).collect::<Vec<_>>();
+            (r.question.name, ips)
+        }).collect::<Vec<_>>());
+    }
+
+    assert_eq!(vec![
+        vec![],
+        vec![],
+        vec![("a.example.com".to_owned(), vec![vec![192, 0, 2, 1], vec![192, 0, 2, 2]])],
+        vec![("b.example.com".to_owned(), vec![vec![198, 51, 100, 7]])],
+    ], responses);
+}
+
+#[test]
+fn dns_mode_parse_cname_chain() {
+    let client = AsyncClient::new("test@example.com", "token", "http://127.0.0.1", None);
+    let client = dns::Client::new(client.unwrap());
+
+    let mut dns = Dns::new(client);
+    let mut responses = Vec::new();
+
+    for flow in iter::flows("pcaps/dns/cname-chain.pcap") {
+        let payload = flow.payload.to_vec();
+        responses.extend(dns.parse(flow.src, flow.dst, &payload, flow.timestamp));
+    }
+
+    let answer = |ip: [u8; 4]| dns::Answer {
+        name:  "www.example.com".to_owned(),
+        cname: "a.cdn.example.net,b.cdn.example.net".to_owned(),
+        ip:    ip.to_vec(),
+        ttl:   60,
+    };
+
+    let response = dns::Response {
+        question: dns::Question {
+            name: "www.example.com".to_owned(),
+            host: vec![10, 0, 0, 1],
+        },
+        answers: vec![
+            answer([198, 51, 100, 1]),
+            answer([198, 51, 100, 2]),
+        ],
+    };
+
+    // the forwarder's upstream reply is attributed to the client
+    assert_eq!(vec![response.clone(), response], responses);
+}
+
+#[test]
+fn dns_mode_parse_empty() {
+    let client = AsyncClient::new("test@example.com", "token", "http://127.0.0.1", None);
+    let client = dns::Client::new(client.unwrap());
+
+    let mut dns = Dns::new(client);
+    dns.forward_empty(true);
+
+    let mut flows = iter::flows("pcaps/dns/cname-chain.pcap");
+    let flow   = flows.by_ref().last().unwrap();
+    let packet = flow.payload.to_vec();
+    let result = dns.parse(flow.src, flow.dst, &packet, flow.timestamp);
+
+    let expect = Some(dns::Response {
+        question: dns::Question {
+            name: "nx.example.com".to_owned(),
+            host: vec![10, 0, 0, 1],
+        },
+        answers: vec![],
+    });
+
+    assert_eq!(expect, result);
+}
+
+#[test]
+fn dns_mode_record_forwarded() {
+    let client = AsyncClient::new("test@example.com", "token", "http://127.0.0.1", None);
+    let client = dns::Client::new(client.unwrap());
+
+    let mut dns = Dns::new(client);
+    let mut cap = Capture::from_file("pcaps/dns/cname-chain.pcap").unwrap();
+    let mut hosts = Vec::new();
+
+    let mut consumer = |dns: &mut Dns, src: Addr, dst: Addr, segment: Segment, ts: Timestamp| {
+        if let Segment::Datagram(payload) = segment {
+            hosts.extend(dns.parse(src, dst, payload, ts).map(|r| r.question.host));
+        }
+    };
+
+    while let Ok(packet) = cap.next_packet() {
+        dns.record(packet, &mut consumer);
+    }
+
+    // queries reach the parser so the upstream reply is attributed to
+    // the client rather than the forwarder
+    assert_eq!(vec![vec![10, 0, 0, 1]; 2], hosts);
+}
+
+#[test]
+fn dns_mode_dedup() {
+    let client = AsyncClient::new("test@example.com", "token", "http://127.0.0.1", None);
+    let client = dns::Client::new(client.unwrap());
+
+    let mut dns = Dns::new(client);
+
+    let mut flows = iter::flows("pcaps/dns/google.com-any.pcap");
+    let flow  = flows.nth(1).unwrap();
+    let reply = flow.payload.to_vec();
+    let ts    = flow.timestamp;
+
+    let record = |dns: &mut Dns, ts: Timestamp| {
+        dns.plain_parse(flow.src, flow.dst, Segment::Datagram(&reply), ts);
+    };
+
+    record(&mut dns, ts);
+    record(&mut dns, ts + Duration::seconds(1));
+    dns.flush(ts + Duration::seconds(2));
+
+    assert_eq!(Stats{sent: 1, deduped: 1, dropped: 0}, dns.stats());
+
+    // resent once the 299 second TTL has passed
+    record(&mut dns, ts + Duration::seconds(299));
+    dns.flush(ts + Duration::seconds(300));
+
+    assert_eq!(Stats{sent: 2, deduped: 1, dropped: 0}, dns.stats());
+}
+
+#[test]
+fn dns_mode_dnstap() {
+    let client = AsyncClient::new("test@example.com", "token", "http://127.0.0.1", None);
+    let client = dns::Client::new(client.unwrap());
+
+    let mut dns = Dns::new(client);
+
+    let reply = iter::flows("pcaps/dns/google.com-any.pcap").skip(1).next().map(|flow| {
+        flow.payload.to_vec()
+    }).unwrap();
+
+    let (reader, mut writer) = UnixStream::pair().unwrap();
+
+    // stands in for a resolver writing dnstap
+    let resolver = thread::spawn(move || {
+        let mut ack = [0u8; 42];
+
+        dnstap::control(&mut writer, dnstap::READY, Some(dnstap::CONTENT_TYPE)).unwrap();
+        writer.read_exact(&mut ack).unwrap();
+        dnstap::control(&mut writer, dnstap::START, Some(dnstap::CONTENT_TYPE)).unwrap();
+
+        // oversized frames are skipped
+        let frame = vec![0u8; (1 << 16) + 1];
+        writer.write_all(&(frame.len() as u32).to_be_bytes()).unwrap();
+        writer.write_all(&frame).unwrap();
+
+        for kind in &[5, dnstap::CLIENT_RESPONSE] {
+            let mut msg = Vec::new();
+            protobuf(&mut msg, 1, &[*kind as u8]);
+            protobuf(&mut msg, 4, &[10, 0, 0, 52]);
+            protobuf(&mut msg, 5, &[10, 0, 0, 1]);
+            protobuf(&mut msg, 14, &reply);
+
+            let mut frame = Vec::new();
+            protobuf(&mut frame, 15, &[1]);
+            protobuf(&mut frame, 14, &msg);
+
+            writer.write_all(&(frame.len() as u32).to_be_bytes()).unwrap();
+            writer.write_all(&frame).unwrap();
+        }
+
+        dnstap::control(&mut writer, dnstap::STOP, None).unwrap();
+
+        let mut finish = Vec::new();
+        writer.read_to_end(&mut finish).unwrap();
+
+        (ack.to_vec(), finish)
+    });
+
+    let mut messages = Vec::new();
+    dnstap::read(reader, |msg| messages.push(msg)).unwrap();
+
+    let (ack, finish) = resolver.join().unwrap();
+    assert_eq!(&ack[8..12], &dnstap::ACCEPT.to_be_bytes());
+    assert_eq!(&ack[20..], dnstap::CONTENT_TYPE);
+    assert_eq!(finish, [0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 5]);
+
+    assert_eq!(1, messages.len());
+
+    let msg = &messages[0];
+    let res = dns.parse(msg.response, msg.query, &msg.payload, msg.time).unwrap();
+
+    assert_eq!("google.com", res.question.name);
+    assert_eq!(vec![10, 0, 0, 52], res.question.host);
+    assert_eq!(vec![172, 217, 26, 14], res.answers[0].ip);
+}
+
+#[test]
+fn dns_mode_dnstap_existing_file() {
+    let client = AsyncClient::new("test@example.com", "token", "http://127.0.0.1", None);
+    let client = dns::Client::new(client.unwrap());
+
+    let path = std::env::temp_dir().join(format!("kprobe-dnstap-{}", std::process::id()));
+    std::fs::write(&path, b"not a socket").unwrap();
+
+    // a regular file is left in place rather than replaced
+    assert!(dnstap::run(&path, client, false).is_err());
+    assert_eq!(b"not a socket".to_vec(), std::fs::read(&path).unwrap());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn dns_mode_mirror() {
+    let sent = |mirror: Option<Mirror>| {
+        let client = AsyncClient::new("test@example.com", "token", "http://127.0.0.1", None);
+        let client = dns::Client::new(client.unwrap());
+
+        let mut dns = Dns::new(client);
+        if let Some(mirror) = mirror {
+            dns.mirror(mirror);
+        }
+
+        let mut cap = Capture::from_file("pcaps/mirror/mirror.pcap").unwrap();
+        let mut ts  = Timestamp::zero();
+
+        while let Ok(packet) = cap.next_packet() {
+            ts = Timestamp::from(packet.header.ts);
+            dns.record(packet, &mut Dns::plain_parse);
+        }
+
+        dns.flush(ts + Duration::seconds(1));
+        dns.stats().sent
+    };
+
+    assert_eq!(1, sent(None));
+    assert_eq!(1, sent(Some(Mirror::new(Format::Juniper, vec![30000]))));
+    assert_eq!(2, sent(Some(Mirror::new(Format::Juniper, Vec::new()))));
+    assert_eq!(2, sent(Some(Mirror::new(Format::Erspan, Vec::new()))));
+    assert_eq!(1, sent(Some(Mirror::new(Format::Trailer(8), Vec::new()))));
+}
+
+// Encode a protobuf field, as a varint when the value is one byte
+// and length-delimited otherwise.
+fn protobuf(buf: &mut Vec<u8>, field: u8, value: &[u8]) {
+    match value {
+        [n] if *n < 0x80 => buf.extend_from_slice(&[field << 3, *n]),
+        _                => {
+            buf.extend_from_slice(&[field << 3 | 2]);
+            let mut len = value.len();
+            while len >= 0x80 {
+                buf.push(len as u8 | 0x80);
+                len >>= 7;
+            }
+            buf.push(len as u8);
+            buf.extend_from_slice(value);
+        },
+    }
+}
+
 #[test]
 fn radius_mode_parse() {
     let client = Client::new("test@example.com", "token", "http://127.0.0.1", None);
@@ -67,3 +337,61 @@ fn radius_mode_parse() {
 
     assert_eq!(expect, result);
 }
+
+#[test]
+fn dhcp_mode_leases() {
+    let client = Client::new("test@example.com", "token", "http://127.0.0.1", None);
+    let client = tag::Client::new(client.unwrap());
+
+    let mut dhcp = dhcp::Dhcp::new(client);
+
+    let mac  = "00:1c:42:60:bb:37".parse().unwrap();
+    let addr = "10.211.55.16".parse().unwrap();
+
+    let mut flows = iter::flows("pcaps/dhcp/dhcpv4.pcap");
+
+    let req = flows.next().unwrap();
+    let result = dhcp.parse(&req.payload);
+    let expect = Some(dhcp::Request::Name(mac, "chdev".to_owned()));
+    assert_eq!(expect, result);
+    dhcp.update(result.unwrap(), req.timestamp);
+
+    let ack = flows.next().unwrap();
+    let result = dhcp.parse(&ack.payload);
+    let expect = Some(dhcp::Request::Ack(mac, addr, Some("chdev".to_owned()), Some(1800)));
+    assert_eq!(expect, result);
+    dhcp.update(result.unwrap(), ack.timestamp);
+
+    let lease = dhcp::Lease {
+        addr:    addr,
+        value:   "chdev (00:1c:42:60:bb:37)".to_owned(),
+        expires: ack.timestamp + Duration::seconds(1800),
+    };
+    assert_eq!(Some(&lease), dhcp.leases().get(&mac));
+
+    // renewed, then expired once the lease time passes
+    let ts = ack.timestamp + Duration::seconds(900);
+    dhcp.update(dhcp::Request::Ack(mac, addr, None, Some(1800)), ts);
+    dhcp.flush(ts + Duration::seconds(1799));
+    assert_eq!(Some(ts + Duration::seconds(1800)), dhcp.leases().get(&mac).map(|l| l.expires));
+    assert_eq!("chdev (00:1c:42:60:bb:37)", dhcp.leases()[&mac].value);
+
+    dhcp.flush(ts + Duration::seconds(1800));
+    assert!(dhcp.leases().is_empty());
+
+    // tagged by MAC without a hostname, until released
+    dhcp.update(dhcp::Request::Ack(mac, addr, None, None), ts);
+    assert_eq!("00:1c:42:60:bb:37", dhcp.leases()[&mac].value);
+
+    dhcp.update(dhcp::Request::Release(mac), ts);
+    assert!(dhcp.leases().is_empty());
+
+    // clients sharing a hostname are tagged separately
+    let other = "00:1c:42:60:bb:38".parse().unwrap();
+    dhcp.update(dhcp::Request::Ack(mac, addr, Some("chdev".to_owned()), None), ts);
+    dhcp.update(dhcp::Request::Ack(other, "10.211.55.17".parse().unwrap(), Some("chdev".to_owned()), None), ts);
+    assert_eq!("chdev (00:1c:42:60:bb:38)", dhcp.leases()[&other].value);
+
+    dhcp.update(dhcp::Request::Release(mac), ts);
+    assert_eq!(1, dhcp.leases().len());
+}
@@ -0,0 +1,230 @@
+use crate::custom::Customs;
+use crate::protocol::{Classify, Decoders};
+use crate::protocol::mysql::statement;
+use super::*;
+
+const PORT: u16 = 3306;
+
+const DEPRECATE_EOF: u32 = 0x0100_0000;
+const SSL:           u32 = 0x0000_0800;
+
+#[test]
+fn decode_mysql_resultset() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    login(&mut decoders, &classify, &mut customs, 0);
+
+    let query = pkt(0, &[&[0x03][..], b"SELECT id FROM users"].concat());
+    decode(&mut decoders, &classify, &mut customs, PORT, 10, true, ACK, &query);
+
+    let reply = [
+        pkt(1, &[1]),
+        pkt(2, &column("id")),
+        pkt(3, &eof()),
+        pkt(4, &[1, b'1']),
+        pkt(5, &[1, b'2']),
+        pkt(6, &eof()),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 14, false, ACK, &reply);
+
+    assert_eq!(Some(Value::from("SELECT")), value(MYSQL_STATEMENT, &customs));
+    assert_eq!(Some(Value::from("8.0.32")), value(MYSQL_VERSION, &customs));
+    assert_eq!(Some(Value::from(2)),        value(MYSQL_ROWS, &customs));
+    assert_eq!(None,                        value(MYSQL_ERROR, &customs));
+    assert_eq!(Some(Value::from(4)),        value(APP_LATENCY, &customs));
+}
+
+#[test]
+fn decode_mysql_deprecate_eof() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let caps = DEPRECATE_EOF;
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, false, ACK, &pkt(0, &handshake(caps)));
+    decode(&mut decoders, &classify, &mut customs, PORT, 1, true,  ACK, &pkt(1, &response(caps)));
+    decode(&mut decoders, &classify, &mut customs, PORT, 2, false, ACK, &pkt(2, &ok(0)));
+
+    // empty result set terminated by an OK packet with an 0xFE header
+    let query = pkt(0, &[&[0x03][..], b"select id from users where 0"].concat());
+    decode(&mut decoders, &classify, &mut customs, PORT, 10, true, ACK, &query);
+
+    let reply = [
+        pkt(1, &[1]),
+        pkt(2, &column("id")),
+        pkt(3, &[0xFE, 0, 0, 2, 0, 0, 0]),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 11, false, ACK, &reply);
+
+    assert_eq!(Some(Value::from("SELECT")), value(MYSQL_STATEMENT, &customs));
+    assert_eq!(Some(Value::from(0)),        value(MYSQL_ROWS, &customs));
+
+    customs.clear();
+
+    let query = pkt(0, &[&[0x03][..], b"DELETE FROM users"].concat());
+    decode(&mut decoders, &classify, &mut customs, PORT, 20, true, ACK, &query);
+    decode(&mut decoders, &classify, &mut customs, PORT, 25, false, ACK, &pkt(1, &ok(7)));
+
+    assert_eq!(Some(Value::from("DELETE")), value(MYSQL_STATEMENT, &customs));
+    assert_eq!(Some(Value::from(7)),        value(MYSQL_ROWS, &customs));
+    assert_eq!(Some(Value::from(5)),        value(APP_LATENCY, &customs));
+}
+
+#[test]
+fn decode_mysql_error() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    // connection already established, no handshake seen
+    let query = pkt(0, &[&[0x03][..], b"/* app */ UPDATE missing SET x = 1"].concat());
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &query);
+
+    let err = [&[0xFF, 0x7A, 0x04, b'#'][..], b"42S02", b"Table doesn't exist"].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 2, false, ACK, &pkt(1, &err));
+
+    assert_eq!(Some(Value::from("UPDATE")), value(MYSQL_STATEMENT, &customs));
+    assert_eq!(Some(Value::from(1146)),     value(MYSQL_ERROR, &customs));
+    assert_eq!(None,                        value(MYSQL_VERSION, &customs));
+    assert_eq!(None,                        value(MYSQL_ROWS, &customs));
+}
+
+#[test]
+fn decode_mysql_prepared_statement() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    login(&mut decoders, &classify, &mut customs, 0);
+
+    let prepare = pkt(0, &[&[0x16][..], b"INSERT INTO t VALUES (?)"].concat());
+    decode(&mut decoders, &classify, &mut customs, PORT, 10, true, ACK, &prepare);
+
+    let reply = [
+        pkt(1, &[0x00, 7, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0]),
+        pkt(2, &column("?")),
+        pkt(3, &eof()),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 11, false, ACK, &reply);
+
+    assert_eq!(None, value(MYSQL_STATEMENT, &customs));
+
+    let execute = pkt(0, &[0x17, 7, 0, 0, 0, 0, 1, 0, 0, 0]);
+    decode(&mut decoders, &classify, &mut customs, PORT, 20, true, ACK, &execute);
+    decode(&mut decoders, &classify, &mut customs, PORT, 23, false, ACK, &pkt(1, &ok(1)));
+
+    assert_eq!(Some(Value::from("INSERT")), value(MYSQL_STATEMENT, &customs));
+    assert_eq!(Some(Value::from(1)),        value(MYSQL_ROWS, &customs));
+    assert_eq!(Some(Value::from(3)),        value(APP_LATENCY, &customs));
+}
+
+#[test]
+fn decode_mysql_ssl() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let mut ssl = response(SSL);
+    ssl.truncate(32);
+
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, false, ACK, &pkt(0, &handshake(SSL)));
+    decode(&mut decoders, &classify, &mut customs, PORT, 1, true,  ACK, &pkt(1, &ssl));
+
+    let query = pkt(0, &[&[0x03][..], b"SELECT 1"].concat());
+    decode(&mut decoders, &classify, &mut customs, PORT, 10, true, ACK, &query);
+    decode(&mut decoders, &classify, &mut customs, PORT, 11, false, ACK, &pkt(1, &ok(0)));
+
+    assert_eq!(None, value(MYSQL_STATEMENT, &customs));
+}
+
+#[test]
+fn decode_mysql_split_packets() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let query = pkt(0, &[&[0x03][..], b"SET autocommit=1"].concat());
+    let (a, b) = query.split_at(6);
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, a);
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, b);
+
+    let reply = pkt(1, &ok(0));
+    let (a, b) = reply.split_at(2);
+    decode(&mut decoders, &classify, &mut customs, PORT, 1, false, ACK, a);
+    assert_eq!(None, value(MYSQL_STATEMENT, &customs));
+    decode(&mut decoders, &classify, &mut customs, PORT, 1, false, ACK, b);
+
+    assert_eq!(Some(Value::from("SET")), value(MYSQL_STATEMENT, &customs));
+}
+
+#[test]
+fn mysql_statement_type() {
+    let cases = &[
+        ("select 1",                        "SELECT"),
+        ("  (SELECT a FROM t) UNION ...",   "SELECT"),
+        ("/* hint */ INSERT INTO t",        "INSERT"),
+        ("-- comment\n# other\nupdate t",   "UPDATE"),
+        ("CALL proc()",                     "CALL"),
+        ("",                                ""),
+    ];
+
+    for &(query, kind) in cases {
+        assert_eq!(kind, statement(query));
+    }
+}
+
+fn login(ds: &mut Decoders, classify: &Classify, cs: &mut Customs, ms: i64) {
+    decode(ds, classify, cs, PORT, ms,     false, ACK, &pkt(0, &handshake(0)));
+    decode(ds, classify, cs, PORT, ms + 1, true,  ACK, &pkt(1, &response(0)));
+    decode(ds, classify, cs, PORT, ms + 2, false, ACK, &pkt(2, &[0xFE, b'x', 0]));
+    decode(ds, classify, cs, PORT, ms + 3, true,  ACK, &pkt(3, &[0; 20]));
+    decode(ds, classify, cs, PORT, ms + 4, false, ACK, &pkt(4, &ok(0)));
+    cs.clear();
+}
+
+
+fn pkt(seq: u8, payload: &[u8]) -> Vec<u8> {
+    let len = (payload.len() as u32).to_le_bytes();
+    [&len[..3], &[seq], payload].concat()
+}
+
+fn handshake(caps: u32) -> Vec<u8> {
+    let caps = caps.to_le_bytes();
+    [
+        &[0x0a][..], b"8.0.32\0", &[1, 0, 0, 0], b"abcdefgh", &[0],
+        &caps[..2], &[0xff, 2, 0], &caps[2..], &[21], &[0; 10], b"ijklmnopqrst\0",
+    ].concat()
+}
+
+fn response(caps: u32) -> Vec<u8> {
+    [&caps.to_le_bytes()[..], &[0, 0, 0, 1, 0xff], &[0; 23], b"root\0"].concat()
+}
+
+fn column(name: &str) -> Vec<u8> {
+    let mut col = Vec::new();
+    for s in &["def", "db", "t", "t", name, name] {
+        col.push(s.len() as u8);
+        col.extend_from_slice(s.as_bytes());
+    }
+    col.extend_from_slice(&[0x0c, 0x21, 0, 0x0b, 0, 0, 0, 0x03, 0, 0, 0, 0, 0]);
+    col
+}
+
+fn eof() -> Vec<u8> {
+    vec![0xFE, 0, 0, 2, 0]
+}
+
+fn ok(rows: u8) -> Vec<u8> {
+    vec![0x00, rows, 0, 2, 0, 0, 0]
+}
+
+fn columns() -> Vec<kflowCustom> {
+    [CUSTOMS, &[
+        custom(b"MYSQL_STATEMENT\0", 26, KFLOW_CUSTOM_STR),
+        custom(b"MYSQL_VERSION\0",   27, KFLOW_CUSTOM_STR),
+        custom(b"MYSQL_ERROR\0",     28, KFLOW_CUSTOM_U32),
+        custom(b"MYSQL_ROWS\0",      29, KFLOW_CUSTOM_U32),
+    ]].concat()
+}
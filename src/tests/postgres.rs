@@ -0,0 +1,261 @@
+use time::Duration;
+use crate::custom::Customs;
+use crate::protocol::{Classify, Decoder, Decoders};
+use crate::protocol::postgres::normalize;
+use super::*;
+
+const PORT: u16 = 5432;
+
+#[test]
+fn decode_postgres_simple_query() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let query = msg(b'Q', &cstr("SELECT * FROM users WHERE id = 42 AND name = 'bob'"));
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &query);
+    assert_eq!(None, value(POSTGRES_QUERY, &customs));
+
+    let reply = [
+        msg(b'D', &[0, 1, 0, 0, 0, 1, b'1']),
+        msg(b'C', &cstr("SELECT 1")),
+        msg(b'Z', b"I"),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 5, false, ACK, &reply);
+
+    let query = "SELECT * FROM users WHERE id = ? AND name = ?";
+    assert_eq!(Some(Value::from(query)),    value(POSTGRES_QUERY, &customs));
+    assert_eq!(Some(Value::from("SELECT")), value(POSTGRES_COMMAND, &customs));
+    assert_eq!(Some(Value::from(1)),        value(POSTGRES_ROWS, &customs));
+    assert_eq!(None,                        value(POSTGRES_SQLSTATE, &customs));
+    assert_eq!(Some(Value::from(5)),        value(APP_LATENCY, &customs));
+}
+
+#[test]
+fn decode_postgres_generic_columns() {
+    let mut customs  = Customs::new(&CUSTOMS);
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    // only exported to dedicated columns, never the shared ones
+    let query = msg(b'Q', &cstr("SELECT 1"));
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &query);
+    decode(&mut decoders, &classify, &mut customs, PORT, 1, false, ACK, &msg(b'Z', b"I"));
+
+    let shared = ["INT00", "INT01", "STR00", "STR01", "STR02", "STR03"];
+    assert!(shared.iter().all(|name| value(name, &customs).is_none()));
+}
+
+#[test]
+fn decode_postgres_extended_query() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let query = [
+        msg(b'P', &[cstr("s1"), cstr("INSERT INTO t VALUES ($1, $2)"), vec![0, 0]].concat()),
+        msg(b'B', &[cstr(""), cstr("s1"), vec![0, 0, 0, 0, 0, 0]].concat()),
+        msg(b'E', &[cstr(""), vec![0, 0, 0, 0]].concat()),
+        msg(b'S', &[]),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &query);
+
+    let reply = [
+        msg(b'1', &[]),
+        msg(b'2', &[]),
+        msg(b'C', &cstr("INSERT 0 3")),
+        msg(b'Z', b"I"),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 12, false, ACK, &reply);
+
+    let query = "INSERT INTO t VALUES (?, ?)";
+    assert_eq!(Some(Value::from(query)),    value(POSTGRES_QUERY, &customs));
+    assert_eq!(Some(Value::from("INSERT")), value(POSTGRES_COMMAND, &customs));
+    assert_eq!(Some(Value::from(3)),        value(POSTGRES_ROWS, &customs));
+    assert_eq!(Some(Value::from(12)),       value(APP_LATENCY, &customs));
+}
+
+#[test]
+fn decode_postgres_error() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let query = msg(b'Q', &cstr("SELECT * FROM missing"));
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &query);
+
+    let reply = [
+        msg(b'E', &[cstr("SERROR"), cstr("C42P01"), cstr("Mrelation does not exist"), vec![0]].concat()),
+        msg(b'Z', b"I"),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 3, false, ACK, &reply);
+
+    assert_eq!(Some(Value::from("SELECT * FROM missing")), value(POSTGRES_QUERY, &customs));
+    assert_eq!(Some(Value::from("42P01")),                 value(POSTGRES_SQLSTATE, &customs));
+    assert_eq!(None,                                       value(POSTGRES_COMMAND, &customs));
+
+    customs.clear();
+
+    // failed Parse aborts the Bind and Execute that follow it
+    let query = [
+        msg(b'P', &[cstr(""), cstr("SELEC 1"), vec![0, 0]].concat()),
+        msg(b'B', &[cstr(""), cstr(""), vec![0, 0, 0, 0, 0, 0]].concat()),
+        msg(b'E', &[cstr(""), vec![0, 0, 0, 0]].concat()),
+        msg(b'S', &[]),
+        msg(b'Q', &cstr("SELECT 2")),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 10, true, ACK, &query);
+
+    let reply = [
+        msg(b'E', &[cstr("C42601"), vec![0]].concat()),
+        msg(b'Z', b"I"),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 11, false, ACK, &reply);
+
+    assert_eq!(Some(Value::from("SELEC ?")), value(POSTGRES_QUERY, &customs));
+    assert_eq!(Some(Value::from("42601")),   value(POSTGRES_SQLSTATE, &customs));
+
+    customs.clear();
+
+    let reply = [
+        msg(b'C', &cstr("SELECT 1")),
+        msg(b'Z', b"I"),
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 12, false, ACK, &reply);
+
+    assert_eq!(Some(Value::from("SELECT ?")), value(POSTGRES_QUERY, &customs));
+    assert_eq!(None,                          value(POSTGRES_SQLSTATE, &customs));
+}
+
+#[test]
+fn decode_postgres_ssl_request() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let ssl = [0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x2f];
+
+    // refused, followed by a plaintext startup and query
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &ssl);
+    decode(&mut decoders, &classify, &mut customs, PORT, 1, false, ACK, b"N");
+
+    let query = msg(b'Q', &cstr("SELECT 1"));
+    decode(&mut decoders, &classify, &mut customs, PORT, 2, true, ACK, &query);
+
+    let reply = [msg(b'C', &cstr("SELECT 1")), msg(b'Z', b"I")].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 3, false, ACK, &reply);
+
+    assert_eq!(Some(Value::from("SELECT ?")), value(POSTGRES_QUERY, &customs));
+
+    customs.clear();
+
+    // accepted, everything after is encrypted
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &ssl);
+    decode(&mut decoders, &classify, &mut customs, PORT, 1, false, ACK, b"S");
+    decode(&mut decoders, &classify, &mut customs, PORT, 2, true, ACK, &query);
+    decode(&mut decoders, &classify, &mut customs, PORT, 3, false, ACK, &reply);
+
+    assert_eq!(None, value(POSTGRES_QUERY, &customs));
+}
+
+#[test]
+fn decode_postgres_custom_port() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    classify.add(Protocol::TCP, 6432, Decoder::Postgres);
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let query = msg(b'Q', &cstr("SELECT 1"));
+    let reply = [msg(b'C', &cstr("SELECT 1")), msg(b'Z', b"I")].concat();
+
+    decode(&mut decoders, &classify, &mut customs, 6432, 0, true,  ACK, &query);
+    decode(&mut decoders, &classify, &mut customs, 6432, 1, false, ACK, &reply);
+
+    assert_eq!(Some(Value::from("SELECT ?")), value(POSTGRES_QUERY, &customs));
+
+    // configured ports replace the default
+    assert_eq!(vec![6432], classify.ports(Protocol::TCP, Decoder::Postgres));
+}
+
+#[test]
+fn decode_postgres_pipelined() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let query = [msg(b'Q', &cstr("SELECT 1")), msg(b'Q', &cstr("DELETE FROM t"))].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &query);
+
+    let reply = [
+        msg(b'C', &cstr("SELECT 1")),
+        msg(b'Z', b"I"),
+        msg(b'C', &cstr("DELETE 2")),
+        msg(b'Z', b"I"),
+    ].concat();
+    assert!(decode(&mut decoders, &classify, &mut customs, PORT, 4, false, ACK, &reply));
+
+    assert_eq!(Some(Value::from("SELECT ?")), value(POSTGRES_QUERY, &customs));
+    assert_eq!(Some(Value::from(1)),          value(POSTGRES_ROWS, &customs));
+
+    customs.clear();
+    assert!(decoders.next(Decoder::Postgres, &mut customs));
+
+    assert_eq!(Some(Value::from("DELETE FROM t")), value(POSTGRES_QUERY, &customs));
+    assert_eq!(Some(Value::from(2)),               value(POSTGRES_ROWS, &customs));
+
+    customs.clear();
+    assert!(!decoders.next(Decoder::Postgres, &mut customs));
+}
+
+#[test]
+fn decode_postgres_expire_idle() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let query = msg(b'Q', &cstr("SELECT 1"));
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &query);
+
+    decoders.clear(Timestamp::zero() + Duration::seconds(120));
+
+    let reply = [msg(b'C', &cstr("SELECT 1")), msg(b'Z', b"I")].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 120_001, false, ACK, &reply);
+
+    assert_eq!(None, value(POSTGRES_QUERY, &customs));
+}
+
+#[test]
+fn postgres_normalize() {
+    let cases = &[
+        ("SELECT 1",                                  "SELECT ?"),
+        ("select  *\n  from t\twhere a = 'x''y'",     "select * from t where a = ?"),
+        ("SELECT * FROM t1 WHERE id IN (1, 2.5, $3)", "SELECT * FROM t1 WHERE id IN (?, ?, ?)"),
+        ("SELECT 1 -- comment\nFROM dual",            "SELECT ? FROM dual"),
+        ("SELECT /* hint */ col_2 FROM t",            "SELECT col_2 FROM t"),
+    ];
+
+    for &(query, normalized) in cases {
+        assert_eq!(normalized, normalize(query));
+    }
+
+    assert_eq!(512, normalize(&"x".repeat(4096)).len());
+}
+
+fn msg(tag: u8, body: &[u8]) -> Vec<u8> {
+    let len = body.len() as u32 + 4;
+    [&[tag][..], &len.to_be_bytes(), body].concat()
+}
+
+fn cstr(s: &str) -> Vec<u8> {
+    [s.as_bytes(), &[0]].concat()
+}
+
+fn columns() -> Vec<kflowCustom> {
+    [CUSTOMS, &[
+        custom(b"POSTGRES_QUERY\0",    26, KFLOW_CUSTOM_STR),
+        custom(b"POSTGRES_COMMAND\0",  27, KFLOW_CUSTOM_STR),
+        custom(b"POSTGRES_SQLSTATE\0", 28, KFLOW_CUSTOM_STR),
+        custom(b"POSTGRES_ROWS\0",     29, KFLOW_CUSTOM_U32),
+    ]].concat()
+}
@@ -0,0 +1,71 @@
+use crate::custom::Customs;
+use crate::protocol::{Classify, Decoder, Decoders};
+use super::*;
+
+#[test]
+fn decode_quic_v1_initial() {
+    let values = decode("pcaps/quic/initial-v1.pcap");
+
+    assert_eq!(Some(Value::from("quic.example.com")), values[0]);
+    assert_eq!(Some(Value::from("h3,h3-29")),         values[1]);
+    assert_eq!(Some(Value::from(1)),                  values[2]);
+    assert_eq!(Some(Value::from("8394c8f03e515708")), values[3]);
+    assert_eq!(Some(Value::from("c101")),             values[4]);
+}
+
+#[test]
+fn decode_quic_v2_initial() {
+    let values = decode("pcaps/quic/initial-v2.pcap");
+
+    assert_eq!(Some(Value::from("v2.example.net")),                     values[0]);
+    assert_eq!(Some(Value::from("h3")),                                 values[1]);
+    assert_eq!(Some(Value::from(0x6b3343cf)),                           values[2]);
+    assert_eq!(Some(Value::from("0011223344556677889900aabbccddeeff")), values[3]);
+    assert_eq!(None,                                                     values[4]);
+}
+
+#[test]
+fn classify_quic() {
+    let customs = Customs::new(&CUSTOMS);
+    let mut classify = Classify::new();
+    let _decoders = Decoders::new(&customs, &mut classify, true);
+
+    let mut flow = flow(0, 443, false);
+    flow.protocol = Protocol::UDP;
+
+    assert_eq!(Decoder::QUIC, classify.find(&flow));
+}
+
+// Values appended for the connection after each packet, keeping the
+// last seen value of each column.
+fn decode(pcap: &str) -> Vec<Option<Value>> {
+    let columns = [CUSTOMS, &[
+        custom(b"TLS_ALPN\0",     26, KFLOW_CUSTOM_STR),
+        custom(b"QUIC_VERSION\0", 27, KFLOW_CUSTOM_U32),
+        custom(b"QUIC_DCID\0",    28, KFLOW_CUSTOM_STR),
+        custom(b"QUIC_SCID\0",    29, KFLOW_CUSTOM_STR),
+    ]].concat();
+
+    let mut customs  = Customs::new(&columns);
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let names = [TLS_SERVER_NAME, TLS_ALPN, QUIC_VERSION, QUIC_DCID, QUIC_SCID];
+    let mut values = vec![None; names.len()];
+
+    for flow in iter::flows(pcap) {
+        let key = flow.key();
+
+        let d = classify.find(&flow);
+        decoders.decode(d, &flow, &mut customs);
+        decoders.append(d, &key,  &mut customs);
+
+        for (v, name) in values.iter_mut().zip(names.iter()) {
+            *v = value(name, &customs).or(v.take());
+        }
+
+        customs.clear();
+    }
+
+    values
+}
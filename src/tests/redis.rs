@@ -0,0 +1,219 @@
+use crate::custom::Customs;
+use crate::protocol::{self, Classify, Decoder, Decoders};
+use crate::protocol::redis::prefix;
+use super::*;
+
+const PORT: u16 = 6379;
+
+#[test]
+fn decode_redis_command() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let req = b"*2\r\n$3\r\nGET\r\n$15\r\nuser:42:profile\r\n";
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, req);
+    decode(&mut decoders, &classify, &mut customs, PORT, 3, false, ACK, b"$5\r\nhello\r\n");
+
+    assert_eq!(Some(Value::from("GET")),  value(REDIS_COMMAND, &customs));
+    assert_eq!(Some(Value::from("user")), value(REDIS_KEY, &customs));
+    assert_eq!(Some(Value::from("bulk")), value(REDIS_REPLY_TYPE, &customs));
+    assert_eq!(None,                      value(REDIS_ERROR, &customs));
+    assert_eq!(Some(Value::from(3)),      value(APP_LATENCY, &customs));
+}
+
+#[test]
+fn decode_redis_pipeline() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let mut cfg = protocol::Config::default();
+    cfg.redis_key_depth = 2;
+    decoders.configure(&cfg);
+
+    let reqs = [
+        &b"*3\r\n$3\r\nSET\r\n$11\r\nsession:abc\r\n$1\r\nx\r\n"[..],
+        b"*2\r\n$4\r\nINCR\r\n$14\r\ncounter:hits:1\r\n",
+        b"*1\r\n$4\r\nPI",
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &reqs);
+    decode(&mut decoders, &classify, &mut customs, PORT, 1, true, ACK, b"NG\r\n");
+
+    // first two replies, the last one split across segments
+    decode(&mut decoders, &classify, &mut customs, PORT, 5, false, ACK, b"+OK\r\n:1");
+    assert_eq!(Some(Value::from("SET")),         value(REDIS_COMMAND, &customs));
+    assert_eq!(Some(Value::from("session:abc")), value(REDIS_KEY, &customs));
+    assert_eq!(Some(Value::from("simple")),      value(REDIS_REPLY_TYPE, &customs));
+
+    customs.clear();
+    decode(&mut decoders, &classify, &mut customs, PORT, 6, false, ACK, b"7\r\n+PONG\r\n");
+
+    assert_eq!(Some(Value::from("INCR")),         value(REDIS_COMMAND, &customs));
+    assert_eq!(Some(Value::from("counter:hits")), value(REDIS_KEY, &customs));
+    assert_eq!(Some(Value::from("integer")),      value(REDIS_REPLY_TYPE, &customs));
+
+    customs.clear();
+    assert!(decoders.next(Decoder::Redis, &mut customs));
+
+    assert_eq!(Some(Value::from("PING")),   value(REDIS_COMMAND, &customs));
+    assert_eq!(None,                        value(REDIS_KEY, &customs));
+    assert_eq!(Some(Value::from("simple")), value(REDIS_REPLY_TYPE, &customs));
+    assert_eq!(Some(Value::from(5)),        value(APP_LATENCY, &customs));
+
+    customs.clear();
+    assert!(!decoders.next(Decoder::Redis, &mut customs));
+}
+
+#[test]
+fn decode_redis_error() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, b"*2\r\n$4\r\nAUTH\r\n$6\r\nsecret\r\n");
+    decode(&mut decoders, &classify, &mut customs, PORT, 1, false, ACK, b"-WRONGPASS invalid username-password pair\r\n");
+
+    assert_eq!(Some(Value::from("AUTH")),  value(REDIS_COMMAND, &customs));
+    assert_eq!(None,                       value(REDIS_KEY, &customs));
+    assert_eq!(Some(Value::from("error")), value(REDIS_REPLY_TYPE, &customs));
+
+    let error = "WRONGPASS invalid username-password pair";
+    assert_eq!(Some(Value::from(error)), value(REDIS_ERROR, &customs));
+}
+
+#[test]
+fn decode_redis_resp3() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, b"HGETALL h:1\r\n");
+
+    // an out-of-band push precedes the map reply with an attribute
+    let reply = [
+        &b">2\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nh:2\r\n"[..],
+        b"|1\r\n+ttl\r\n:3\r\n",
+        b"%1\r\n+field\r\n,1.5\r\n",
+    ].concat();
+    decode(&mut decoders, &classify, &mut customs, PORT, 2, false, ACK, &reply);
+
+    assert_eq!(Some(Value::from("HGETALL")), value(REDIS_COMMAND, &customs));
+    assert_eq!(Some(Value::from("h")),       value(REDIS_KEY, &customs));
+    assert_eq!(Some(Value::from("map")),     value(REDIS_REPLY_TYPE, &customs));
+}
+
+#[test]
+fn decode_redis_null_and_array() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let reqs = b"*2\r\n$3\r\nGET\r\n$7\r\nmissing\r\n*3\r\n$4\r\nMGET\r\n$1\r\na\r\n$1\r\nb\r\n";
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, reqs);
+    decode(&mut decoders, &classify, &mut customs, PORT, 1, false, ACK, b"$-1\r\n");
+
+    assert_eq!(Some(Value::from("null")), value(REDIS_REPLY_TYPE, &customs));
+
+    customs.clear();
+    decode(&mut decoders, &classify, &mut customs, PORT, 2, false, ACK, b"*2\r\n$1\r\n1\r\n$-1\r\n");
+
+    assert_eq!(Some(Value::from("MGET")),  value(REDIS_COMMAND, &customs));
+    assert_eq!(Some(Value::from("array")), value(REDIS_REPLY_TYPE, &customs));
+}
+
+#[test]
+fn decode_redis_unsubscribe() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let message = b"*3\r\n$7\r\nmessage\r\n$2\r\nch\r\n$2\r\nhi\r\n";
+
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, b"*2\r\n$9\r\nSUBSCRIBE\r\n$2\r\nch\r\n");
+    assert!(decode(&mut decoders, &classify, &mut customs, PORT, 1, false, ACK, b"*3\r\n$9\r\nsubscribe\r\n$2\r\nch\r\n:1\r\n"));
+    assert!(!decode(&mut decoders, &classify, &mut customs, PORT, 2, false, ACK, message));
+
+    customs.clear();
+    decode(&mut decoders, &classify, &mut customs, PORT, 3, true, ACK, b"*1\r\n$11\r\nUNSUBSCRIBE\r\n");
+    decode(&mut decoders, &classify, &mut customs, PORT, 4, false, ACK, b"*3\r\n$11\r\nunsubscribe\r\n$2\r\nch\r\n:0\r\n");
+
+    assert_eq!(Some(Value::from("UNSUBSCRIBE")), value(REDIS_COMMAND, &customs));
+
+    // arrays are replies again once no subscriptions are left
+    customs.clear();
+    decode(&mut decoders, &classify, &mut customs, PORT, 5, true, ACK, b"*4\r\n$6\r\nLRANGE\r\n$1\r\nl\r\n$1\r\n0\r\n$2\r\n-1\r\n");
+    assert!(decode(&mut decoders, &classify, &mut customs, PORT, 6, false, ACK, message));
+
+    assert_eq!(Some(Value::from("LRANGE")), value(REDIS_COMMAND, &customs));
+    assert_eq!(Some(Value::from("array")),  value(REDIS_REPLY_TYPE, &customs));
+}
+
+#[test]
+fn decode_redis_subscribe_channels() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let confirm = |kind: &str, ch: &str, n: usize| {
+        format!("*3\r\n${}\r\n{}\r\n${}\r\n{}\r\n:{}\r\n", kind.len(), kind, ch.len(), ch, n).into_bytes()
+    };
+
+    // one confirmation per channel answers a single request
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, b"SUBSCRIBE a b c\r\n");
+    let confirms = [confirm("subscribe", "a", 1), confirm("subscribe", "b", 2)].concat();
+    assert!(!decode(&mut decoders, &classify, &mut customs, PORT, 1, false, ACK, &confirms));
+    assert!(decode(&mut decoders, &classify, &mut customs, PORT, 2, false, ACK, &confirm("subscribe", "c", 3)));
+
+    assert_eq!(Some(Value::from("SUBSCRIBE")), value(REDIS_COMMAND, &customs));
+    assert_eq!(Some(Value::from(2)),           value(APP_LATENCY, &customs));
+
+    // without channels, until no subscriptions are left
+    customs.clear();
+    decode(&mut decoders, &classify, &mut customs, PORT, 3, true, ACK, b"UNSUBSCRIBE\r\n");
+    let confirms = [confirm("unsubscribe", "a", 2), confirm("unsubscribe", "b", 1)].concat();
+    assert!(!decode(&mut decoders, &classify, &mut customs, PORT, 4, false, ACK, &confirms));
+    decode(&mut decoders, &classify, &mut customs, PORT, 5, true, ACK, b"PING\r\n");
+    let replies = [confirm("unsubscribe", "c", 0), b"+PONG\r\n".to_vec()].concat();
+    assert!(decode(&mut decoders, &classify, &mut customs, PORT, 6, false, ACK, &replies));
+
+    assert_eq!(Some(Value::from("UNSUBSCRIBE")), value(REDIS_COMMAND, &customs));
+
+    customs.clear();
+    assert!(decoders.next(Decoder::Redis, &mut customs));
+    assert_eq!(Some(Value::from("PING")), value(REDIS_COMMAND, &customs));
+}
+
+#[test]
+fn decode_redis_max_pending() {
+    let mut customs  = Customs::new(&columns());
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let ping = b"*1\r\n$4\r\nPING\r\n";
+
+    // replies can't be matched once too many requests are outstanding
+    decode(&mut decoders, &classify, &mut customs, PORT, 0, true, ACK, &ping.repeat(1025));
+    assert!(!decode(&mut decoders, &classify, &mut customs, PORT, 1, false, ACK, b"+PONG\r\n"));
+
+    decode(&mut decoders, &classify, &mut customs, PORT, 2, true, ACK, ping);
+    assert!(!decode(&mut decoders, &classify, &mut customs, PORT, 3, false, ACK, b"+PONG\r\n"));
+    assert_eq!(None, value(REDIS_COMMAND, &customs));
+}
+
+#[test]
+fn redis_key_prefix() {
+    assert_eq!(&b"user"[..],            prefix(b"user:42:profile", 1));
+    assert_eq!(&b"user:42"[..],         prefix(b"user:42:profile", 2));
+    assert_eq!(&b"user:42:profile"[..], prefix(b"user:42:profile", 3));
+    assert_eq!(&b"plain"[..],           prefix(b"plain", 1));
+}
+
+fn columns() -> Vec<kflowCustom> {
+    [CUSTOMS, &[
+        custom(b"REDIS_COMMAND\0",    26, KFLOW_CUSTOM_STR),
+        custom(b"REDIS_KEY\0",        27, KFLOW_CUSTOM_STR),
+        custom(b"REDIS_REPLY_TYPE\0", 28, KFLOW_CUSTOM_STR),
+        custom(b"REDIS_ERROR\0",      29, KFLOW_CUSTOM_STR),
+    ]].concat()
+}
@@ -0,0 +1,135 @@
+use byteorder::{BigEndian as BE, ByteOrder};
+use crate::custom::Customs;
+use crate::flow::{Key, Protocol};
+use crate::protocol::{Classify, Decoder, Decoders};
+use super::*;
+
+#[test]
+fn decode_tls_fingerprints() {
+    let columns = [CUSTOMS, &[
+        custom(b"TLS_JA3\0",  26, KFLOW_CUSTOM_STR),
+        custom(b"TLS_JA3S\0", 27, KFLOW_CUSTOM_STR),
+        custom(b"TLS_JA4\0",  28, KFLOW_CUSTOM_STR),
+    ]].concat();
+
+    let mut customs  = Customs::new(&columns);
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let mut ja3:  Option<Value> = None;
+    let mut ja3s: Option<Value> = None;
+    let mut ja4:  Option<Value> = None;
+
+    for flow in iter::flows("pcaps/tls/google.com-tls-1.2.pcap") {
+        let key = flow.key();
+
+        let d = classify.find(&flow);
+        decoders.decode(d, &flow, &mut customs);
+        decoders.append(d, &key,  &mut customs);
+
+        ja3  = value(TLS_JA3, &customs).or(ja3);
+        ja3s = value(TLS_JA3S, &customs).or(ja3s);
+        ja4  = value(TLS_JA4, &customs).or(ja4);
+
+        customs.clear();
+    }
+
+    assert_eq!(Some(Value::from("488b6b601cb141b062d4da7f524b4b22")),     ja3);
+    assert_eq!(Some(Value::from("4765c897c6d53b1d85a8d7f365d20860")),     ja3s);
+    assert_eq!(Some(Value::from("t12d340700_c09add56dd4c_3304d8368043")), ja4);
+}
+
+#[test]
+fn decode_tls_certificate() {
+    let columns = [CUSTOMS, &[
+        custom(b"TLS_ALPN\0",           26, KFLOW_CUSTOM_STR),
+        custom(b"TLS_CERT_SUBJECT\0",   27, KFLOW_CUSTOM_STR),
+        custom(b"TLS_CERT_SAN\0",       28, KFLOW_CUSTOM_STR),
+        custom(b"TLS_CERT_ISSUER\0",    29, KFLOW_CUSTOM_STR),
+        custom(b"TLS_CERT_SERIAL\0",    30, KFLOW_CUSTOM_STR),
+        custom(b"TLS_CERT_NOT_AFTER\0", 31, KFLOW_CUSTOM_U32),
+    ]].concat();
+
+    let mut customs  = Customs::new(&columns);
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    let mut values = Vec::new();
+
+    for flow in iter::flows("pcaps/tls/google.com-tls-1.2.pcap") {
+        let key = flow.key();
+
+        let d = classify.find(&flow);
+        decoders.decode(d, &flow, &mut customs);
+        decoders.append(d, &key,  &mut customs);
+
+        if let Some(subject) = value(TLS_CERT_SUBJECT, &customs) {
+            values = vec![
+                Some(subject),
+                value(TLS_CERT_ISSUER, &customs),
+                value(TLS_CERT_SERIAL, &customs),
+                value(TLS_CERT_NOT_AFTER, &customs),
+                value(TLS_CERT_SAN, &customs),
+                value(TLS_ALPN, &customs),
+            ];
+        }
+
+        customs.clear();
+    }
+
+    let sans = match values.get(4) {
+        Some(Some(Value::Str(sans))) => sans.split(',').collect::<Vec<_>>(),
+        v                            => panic!("missing SANs {:?}", v),
+    };
+
+    assert_eq!(Some(Value::from("*.google.com")),                                       values[0]);
+    assert_eq!(Some(Value::from("C=US, O=Google Inc, CN=Google Internet Authority G2")), values[1]);
+    assert_eq!(Some(Value::from("23fe4ed12904f9d1")),                                   values[2]);
+    assert_eq!(Some(Value::from(1505310720)),                                           values[3]);
+    assert_eq!(None,                                                                    values[5]);
+
+    assert_eq!(60, sans.len());
+    assert_eq!(Some(&"*.google.com"), sans.first());
+    assert_eq!(Some(&"yt.be"),        sans.last());
+}
+
+#[test]
+fn decode_tls_certificate_across_records() {
+    let columns = [CUSTOMS, &[
+        custom(b"TLS_CERT_SUBJECT\0", 26, KFLOW_CUSTOM_STR),
+    ]].concat();
+
+    let mut customs  = Customs::new(&columns);
+    let mut classify = Classify::new();
+    let mut decoders = Decoders::new(&customs, &mut classify, true);
+
+    // the server's handshake messages, re-framed into 512 byte records
+    // so the Certificate spans several of them
+    let payload = iter::flows("pcaps/tls/google.com-tls-1.2.pcap").filter(|flow| {
+        flow.src.port == 443
+    }).flat_map(|flow| flow.payload.to_vec()).collect::<Vec<_>>();
+
+    let mut messages = Vec::new();
+    let mut rest     = &payload[..];
+    while rest.len() >= 5 && rest[0] == 0x16 {
+        let len = BE::read_u16(&rest[3..5]) as usize;
+        messages.extend_from_slice(&rest[5..5 + len]);
+        rest = &rest[5 + len..];
+    }
+
+    let records = messages.chunks(512).flat_map(|chunk| {
+        [&[0x16, 0x03, 0x03][..], &(chunk.len() as u16).to_be_bytes(), chunk].concat()
+    }).collect::<Vec<_>>();
+
+    decode(&mut decoders, &classify, &mut customs, 443, 0, true, SYN, &[]);
+    for segment in records.chunks(1400) {
+        decode(&mut decoders, &classify, &mut customs, 443, 1, false, ACK, segment);
+    }
+
+    let client = Addr{addr: "10.0.0.1".parse().unwrap(), port: 40000};
+    let server = Addr{addr: "10.0.0.2".parse().unwrap(), port: 443};
+    decoders.append(Decoder::TLS, &Key(Protocol::TCP, client, server), &mut customs);
+
+    assert!(messages.len() > 2048);
+    assert_eq!(Some(Value::from("*.google.com")), value(TLS_CERT_SUBJECT, &customs));
+}